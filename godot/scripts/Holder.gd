@@ -14,12 +14,17 @@ func _ready():
 
   #self.viewport.size = self.tesser.viewport_size()                                       
   (self.screen.material as ShaderMaterial).set_shader_parameter("TREE_TEXTURE", self.tesser.tree_tex())
+  (self.screen.material as ShaderMaterial).set_shader_parameter("foxelPalette", self.tesser.palette_tex())
 
-func _process(_delta: float):
+func _process(delta: float):
+  tesser.tick(delta)
   %OverlayUi.set_debug_info(tesser.debug_string())
   self.apply_shader_params()
-  %TesseractoryGodotBridge.upload_foxels(GdPlayerCamera.make(
-    self.player.position, self.player.rotation(), self.player.FOV, self.player.FOCAL_DIST))
+  %TesseractoryGodotBridge.upload_foxels(self.camera())
+
+func camera() -> GdPlayerCamera:
+  return GdPlayerCamera.make(
+    self.player.position, self.player.rotation(), self.player.FOV, self.player.FOCAL_DIST)
 
 func apply_shader_params() -> void:
   var shader := self.screen.material as ShaderMaterial
@@ -36,6 +41,12 @@ func _input(event: InputEvent):
   if event.is_action_pressed("exit"):
     get_tree().quit()
   
+  # The mouse is captured, so aim with the middle of the screen
+  if event.is_action_pressed("break"):
+    self.tesser.break_foxel(self.camera(), Vector2(0.5, 0.5))
+  if event.is_action_pressed("place"):
+    self.tesser.place_foxel(self.camera(), Vector2(0.5, 0.5), "stone")
+
   if event.is_action_pressed("pause"):
     var paused := get_tree().paused
     if paused:
@@ -5,17 +5,27 @@ pub use math::GdPlayerCamera;
 use std::time::Instant;
 
 use godot::{
-  engine::{image, Image, ImageTexture, RenderingServer},
+  engine::{image, Image, ImageTexture, Os, RenderingServer},
   prelude::*,
 };
 
-use crate::{math::hexadecitree::Hexadecitree, TesseractoryGame};
+use crate::{
+  math::hexadecitree::{upload::UploadState, Hexadecitree},
+  render::Camera,
+  world::{
+    foxel::FoxelRegistry,
+    scenes::{SceneRegistry, SceneSpec},
+    World,
+  },
+  TesseractoryGame,
+};
 
 /// https://github.com/godotengine/godot/issues/57841
 const TREE_IMG_FORMAT: image::Format = image::Format::RF;
 
 const VIEWPORT_WIDTH: u32 = 1000;
 const VIEWPORT_HEIGHT: u32 = 600;
+const ASPECT: f32 = VIEWPORT_WIDTH as f32 / VIEWPORT_HEIGHT as f32;
 
 struct TesseractoryExtension;
 
@@ -37,8 +47,11 @@ struct OnReadyStuff {
 
   /// Keep this around to avoid having to realloc all the time
   tree_scratch: PackedByteArray,
+  /// What's in `tree_scratch` already
+  upload: UploadState,
   tree_image: Gd<Image>,
   tree_tex: Gd<ImageTexture>,
+  palette_tex: Gd<ImageTexture>,
 }
 
 #[godot_api]
@@ -54,7 +67,19 @@ impl INode for TesseractoryGodotBridge {
   }
 
   fn ready(&mut self) {
-    let game = TesseractoryGame::new();
+    let args = Os::singleton()
+      .get_cmdline_user_args()
+      .to_vec()
+      .into_iter()
+      .map(|s| s.to_string())
+      .collect::<Vec<_>>();
+    let env = std::env::var(SceneRegistry::ENV_VAR).ok();
+    let scene = SceneSpec::from_config(args, env).unwrap_or_else(|e| {
+      godot_error!("bad scene config: {:?}", e);
+      SceneSpec::default()
+    });
+    godot_print!("building scene {:?}", scene);
+    let game = TesseractoryGame::new(&scene);
 
     let scratch = PackedByteArray::from(
       vec![0u8; Hexadecitree::GPU_TRANSFER_IMAGE_SIZE_SQ * 4].as_slice(),
@@ -68,11 +93,23 @@ impl INode for TesseractoryGodotBridge {
     )
     .unwrap();
     let tree_tex = ImageTexture::create_from_image(tree_image.clone()).unwrap();
+
+    let palette_image = Image::create_from_data(
+      FoxelRegistry::PALETTE_SIZE as i32,
+      FoxelRegistry::PALETTE_SIZE as i32,
+      false,
+      image::Format::RGBA8,
+      PackedByteArray::from(FoxelRegistry::builtin().palette_rgba().as_slice()),
+    )
+    .unwrap();
+    let palette_tex = ImageTexture::create_from_image(palette_image).unwrap();
     self.on_ready = Some(OnReadyStuff {
       game,
       tree_tex,
       tree_image,
+      palette_tex,
       tree_scratch: scratch,
+      upload: UploadState::new(),
     });
 
     let mut rs = RenderingServer::singleton();
@@ -97,6 +134,10 @@ impl INode for TesseractoryGodotBridge {
         "TREE_BRICKS_BYTES",
         (Hexadecitree::GPU_BRICK_PTRS_BYTES as u32).to_variant(),
       ),
+      (
+        "TREE_AO_OFFSET",
+        (Hexadecitree::GPU_AO_OFFSET as u32).to_variant(),
+      ),
       ("TREE_MIN_COORD", Hexadecitree::MIN_COORD.to_variant()),
       ("TREE_MAX_COORD", Hexadecitree::MAX_COORD.to_variant()),
     ] {
@@ -118,35 +159,116 @@ impl TesseractoryGodotBridge {
     self.stuff().tree_tex.clone()
   }
 
+  /// One pixel per foxel id, generated from the foxel registry.
+  #[func]
+  pub fn palette_tex(&self) -> Gd<ImageTexture> {
+    self.stuff().palette_tex.clone()
+  }
+
   #[func]
   pub fn viewport_size(&self) -> Vector2i {
     Vector2i::new(VIEWPORT_WIDTH as _, VIEWPORT_HEIGHT as _)
   }
 
+  /// Advance the world by a frame that took `delta` seconds.
+  #[func]
+  pub fn tick(&mut self, delta: f64) {
+    self.stuff_mut().game.update(delta as f32);
+  }
+
+  /// Break the foxel under `uv` on the screen, if there's one in reach.
+  /// Gives back whether anything got broken.
+  #[func]
+  pub fn break_foxel(&mut self, cam: Gd<GdPlayerCamera>, uv: Vector2) -> bool {
+    let camera = Camera::from(&*cam.bind());
+    let world = &mut self.stuff_mut().game.world;
+    match world.break_picked(&camera, vec2_from_gd(uv), ASPECT, World::REACH) {
+      Ok(broken) => broken.is_some(),
+      Err(ono) => {
+        godot_error!("couldn't break foxel: {:?}", ono);
+        false
+      }
+    }
+  }
+
+  /// Place the foxel called `name` against the face under `uv` on the
+  /// screen, if there's one in reach. Gives back whether it got placed.
+  #[func]
+  pub fn place_foxel(
+    &mut self,
+    cam: Gd<GdPlayerCamera>,
+    uv: Vector2,
+    name: GString,
+  ) -> bool {
+    let Some(foxel) = FoxelRegistry::builtin().by_name(&name.to_string())
+    else {
+      godot_error!("no foxel called {}", name);
+      return false;
+    };
+    let camera = Camera::from(&*cam.bind());
+    let world = &mut self.stuff_mut().game.world;
+    let uv = vec2_from_gd(uv);
+    match world.place_picked(&camera, uv, ASPECT, World::REACH, foxel) {
+      Ok(placed) => placed.is_some(),
+      Err(ono) => {
+        godot_error!("couldn't place foxel: {:?}", ono);
+        false
+      }
+    }
+  }
+
   #[func]
   pub fn upload_foxels(&mut self, cam: Gd<GdPlayerCamera>) {
     let now = Instant::now();
 
     let stuff = self.stuff_mut();
-    stuff
-      .game
-      .world
-      .foxels
-      .upload(stuff.tree_scratch.as_mut_slice(), &*cam.bind());
-    stuff.tree_image.set_data(
-      Hexadecitree::GPU_TRANSFER_IMAGE_SIZE as i32,
-      Hexadecitree::GPU_TRANSFER_IMAGE_SIZE as i32,
-      false,
-      TREE_IMG_FORMAT,
-      stuff.tree_scratch.clone(),
+    let world = &mut stuff.game.world;
+    let patch = world.foxels.upload(
+      stuff.tree_scratch.as_mut_slice(),
+      &Camera::from(&*cam.bind()).view_volume(ASPECT),
+      &mut world.ao,
+      &mut stuff.upload,
     );
+    if patch.is_empty() {
+      return;
+    }
+
+    // Only copy over the rows that changed
+    let size = Hexadecitree::GPU_TRANSFER_IMAGE_SIZE as i32;
+    let row_bytes = Hexadecitree::GPU_TRANSFER_ROW_BYTES;
+    for rows in patch.rows() {
+      let data = &stuff.tree_scratch.as_slice()
+        [rows.start * row_bytes..rows.end * row_bytes];
+      let height = rows.len() as i32;
+      let Some(part) = Image::create_from_data(
+        size,
+        height,
+        false,
+        TREE_IMG_FORMAT,
+        PackedByteArray::from(data),
+      ) else {
+        godot_error!("couldn't make an image of rows {:?}", rows);
+        continue;
+      };
+      stuff.tree_image.blit_rect(
+        part,
+        Rect2i::new(Vector2i::ZERO, Vector2i::new(size, height)),
+        Vector2i::new(0, rows.start as i32),
+      );
+    }
+    // This still sends the whole image to the GPU. Godot 4 dropped
+    // `texture_set_data_partial`, and nothing godot 0.1 binds can update
+    // part of a 2D texture: `ImageTexture::update`,
+    // `RenderingServer::texture_2d_update` and
+    // `RenderingDevice::texture_update` all replace the whole thing. Only
+    // the copying above scales with the size of the edit.
     stuff.tree_tex.update(stuff.tree_image.clone());
 
     let time = Instant::now() - now;
     godot_print!(
-      "upload fps: {}; img size {}",
+      "upload fps: {}; {} bytes changed",
       1.0 / time.as_secs_f32(),
-      Hexadecitree::GPU_TRANSFER_IMAGE_SIZE
+      patch.len()
     );
   }
 }
@@ -165,6 +287,10 @@ impl TesseractoryGodotBridge {
 
 // Helper fns
 
+pub fn vec2_from_gd(v: Vector2) -> ultraviolet::Vec2 {
+  ultraviolet::Vec2::new(v.x, v.y)
+}
+
 pub fn vec4_from_gd(v: Vector4) -> ultraviolet::Vec4 {
   ultraviolet::Vec4::new(v.x, v.y, v.z, v.w)
 }
@@ -4,30 +4,49 @@
 pub mod extensions;
 pub mod godot_bridge;
 pub mod math;
+pub mod render;
 pub mod world;
 
 use extensions::GodotObjectExt;
 use godot::prelude::{Gd, Resource};
 use math::{geo::Rotor4, hexadecitree::Hexadecitree};
 use ultraviolet::Vec4;
-use world::{foxel::Foxel, World};
+use world::{
+  foxel::Foxel,
+  scenes::{SceneRegistry, SceneSpec},
+  sim::FixedTicker,
+  World,
+};
 
 pub struct TesseractoryGame {
   world: World,
   camera_pos: Vec4,
   camera_rot: Rotor4,
+  ticker: FixedTicker,
 }
 
 impl TesseractoryGame {
-  pub fn new() -> Self {
+  pub fn new(scene: &SceneSpec) -> Self {
     let sun_dir = Vec4::new(-0.5, 0.4, 0.2, 0.1).normalized();
-    let mut world = World::new(sun_dir);
-    world.setup_sample_scene();
+    let scenes = SceneRegistry::builtin();
+    let world = scenes.build(scene, sun_dir).unwrap_or_else(|e| {
+      log::error!("couldn't build scene {:?}: {:?}", scene, e);
+      let fallback = SceneSpec::default();
+      scenes.build(&fallback, sun_dir).unwrap()
+    });
 
     Self {
       world,
       camera_pos: Vec4::zero(),
       camera_rot: Rotor4::identity(),
+      ticker: FixedTicker::default(),
+    }
+  }
+
+  /// Run however many world ticks fit in a frame that took `delta` seconds.
+  pub fn update(&mut self, delta: f32) {
+    for _ in 0..self.ticker.advance(delta) {
+      self.world.tick();
     }
   }
 
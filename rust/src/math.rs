@@ -1,5 +1,7 @@
 pub mod geo;
 pub mod hexadecitree;
+pub mod noise;
+pub mod rng;
 
 use ultraviolet::{IVec4, Vec4};
 
@@ -0,0 +1,173 @@
+//! Sweeping a box through the world to find the first thing it bumps into,
+//! for physics and moving things about.
+//!
+//! It's the same DDA as `TreeIter`, but following the faces of the box
+//! that are in front. Every time one of them moves into a new layer of
+//! foxels, everything in that layer the box covers gets checked.
+
+use std::ops::RangeInclusive;
+
+use itertools::iproduct;
+use ultraviolet::Vec4;
+
+use crate::{extensions::F32Ext, math::BlockPos, Foxel};
+
+use super::Hexadecitree;
+
+/// How close the box can get to a foxel sideways without touching it.
+/// Keeps boxes sliding along a wall from catching on it through rounding.
+const SKIN: f32 = 1e-4;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoxHit {
+  pub foxel: Foxel,
+  /// The foxel it bumped into. If it bumped into a few at once, this is
+  /// one of them.
+  pub pos: BlockPos,
+  /// Which way the face of the foxel it hit points. Zero if the box was
+  /// already overlapping something at the start.
+  pub normal: Vec4,
+  /// How far the box got before it touched.
+  pub dist: f32,
+}
+
+impl Hexadecitree {
+  /// Sweep the box between `min` and `max` along `dir` for up to
+  /// `max_dist`, and find the first foxel that isn't air that it runs
+  /// into.
+  ///
+  /// Only touching doesn't count, so boxes can sit on the ground and slide
+  /// along walls. Outside the world counts as air, same as `raycast`.
+  pub fn box_cast(
+    &self,
+    min: Vec4,
+    max: Vec4,
+    dir: Vec4,
+    max_dist: f32,
+  ) -> Option<BoxHit> {
+    let dir = dir.normalized();
+    if !dir.as_array().iter().all(|v| v.is_finite()) {
+      return None;
+    }
+    let (lo, hi) = (min.min_by_component(max), min.max_by_component(max));
+    let covered = |lo: f32, hi: f32| {
+      cell_range((lo + SKIN).floor(), (hi - SKIN).ceil() - 1.0)
+    };
+
+    let start = std::array::from_fn(|axis| covered(lo[axis], hi[axis]));
+    if let Some((pos, foxel)) = self.first_solid(start) {
+      return Some(BoxHit {
+        foxel,
+        pos,
+        normal: Vec4::zero(),
+        dist: 0.0,
+      });
+    }
+
+    let signums = dir.as_array().map(f32::good_sign);
+    // The face in front along each axis, and the next grid plane it'll
+    // go through
+    let forwards = signums.map(|s| s > 0.0);
+    let lead = Vec4::from(std::array::from_fn(|axis| {
+      if forwards[axis] {
+        hi[axis]
+      } else {
+        lo[axis]
+      }
+    }));
+    let mut planes: [f32; 4] = std::array::from_fn(|axis| {
+      if forwards[axis] {
+        lead[axis].ceil()
+      } else {
+        lead[axis].floor()
+      }
+    });
+
+    loop {
+      let times: [f32; 4] = std::array::from_fn(|axis| {
+        if signums[axis] == 0.0 {
+          f32::INFINITY
+        } else {
+          (planes[axis] - lead[axis]) / dir[axis]
+        }
+      });
+      let time = times.into_iter().fold(f32::INFINITY, f32::min);
+      if time > max_dist || !time.is_finite() {
+        return None;
+      }
+      let (lo, hi) = (lo + dir * time, hi + dir * time);
+      // Once it's off the edge and heading away it's never coming back
+      let min_coord = Hexadecitree::MIN_COORD as f32;
+      let max_coord = Hexadecitree::MAX_COORD as f32 + 1.0;
+      if (0..4).any(|axis| {
+        (lo[axis] >= max_coord && signums[axis] >= 0.0)
+          || (hi[axis] <= min_coord && signums[axis] <= 0.0)
+      }) {
+        return None;
+      }
+
+      // What's covered on each axis, counting the new layer on every axis
+      // that's moving into one right now
+      let crossing = times.map(|t| t == time);
+      let new_layer: [f32; 4] = std::array::from_fn(|axis| {
+        if forwards[axis] {
+          planes[axis]
+        } else {
+          planes[axis] - 1.0
+        }
+      });
+      let ranges: [_; 4] = std::array::from_fn(|axis| {
+        let (from, to) = ((lo[axis] + SKIN).floor(), (hi[axis] - SKIN).ceil());
+        match (crossing[axis], forwards[axis]) {
+          (true, true) => cell_range(from, new_layer[axis]),
+          (true, false) => cell_range(new_layer[axis], to - 1.0),
+          (false, _) => cell_range(from, to - 1.0),
+        }
+      });
+
+      for axis in (0..4).filter(|a| crossing[*a]) {
+        let mut layer = ranges.clone();
+        layer[axis] = cell_range(new_layer[axis], new_layer[axis]);
+        if let Some((pos, foxel)) = self.first_solid(layer) {
+          let mut normal = Vec4::zero();
+          normal[axis] = -signums[axis];
+          return Some(BoxHit {
+            foxel,
+            pos,
+            normal,
+            dist: time,
+          });
+        }
+      }
+
+      for axis in (0..4).filter(|a| crossing[*a]) {
+        planes[axis] += signums[axis];
+      }
+    }
+  }
+
+  fn first_solid(
+    &self,
+    ranges: [RangeInclusive<i32>; 4],
+  ) -> Option<(BlockPos, Foxel)> {
+    let [x, y, z, w] = ranges;
+    iproduct!(x, y, z, w).find_map(|(x, y, z, w)| {
+      let pos = BlockPos::new(x, y, z, w);
+      match self.get(pos)? {
+        Foxel::AIR => None,
+        foxel => Some((pos, foxel)),
+      }
+    })
+  }
+}
+
+/// The cells from `from` to `to` inclusive, cut down to the ones that are
+/// in the world.
+fn cell_range(from: f32, to: f32) -> RangeInclusive<i32> {
+  let (min, max) = (Hexadecitree::MIN_COORD, Hexadecitree::MAX_COORD);
+  if from > to || to < min as f32 || from > max as f32 {
+    // Empty
+    return RangeInclusive::new(1, 0);
+  }
+  from.max(min as f32) as i32..=to.min(max as f32) as i32
+}
@@ -1,6 +1,8 @@
 use ultraviolet::{IVec4, Vec4};
 
-use crate::{extensions::F32Ext, math::BlockPos};
+use crate::{extensions::F32Ext, math::BlockPos, Foxel};
+
+use super::{reprs::BrickRef, Hexadecitree};
 
 pub struct TreeIter {
   // Optimizations to save on recomputing ray coeffs
@@ -11,6 +13,7 @@ pub struct TreeIter {
   signums: IVec4,
 
   cursor_offset: IVec4,
+  time: f32,
 }
 
 impl TreeIter {
@@ -37,8 +40,15 @@ impl TreeIter {
       cursor,
       signums,
       cursor_offset,
+      time: 0.0,
     }
   }
+
+  /// How far along the ray, in lengths of `dir`, the last cell it gave
+  /// out starts.
+  pub fn entry_time(&self) -> f32 {
+    self.time
+  }
 }
 
 impl Iterator for TreeIter {
@@ -48,7 +58,8 @@ impl Iterator for TreeIter {
     // what the paper calls {xyz}_1.
     // the position of the other corner of the cube.
     let exit_poses = Vec4::from(self.cursor + self.signums);
-    let exit_times = exit_poses.mul_add(self.dir_recip, self.slope_something);
+    // Not a mul_add, so the wide version in `PacketIter` comes out the same
+    let exit_times = exit_poses * self.dir_recip + self.slope_something;
     let exit_time = exit_times.component_min();
     // ugh
     let min_time_map = exit_times.as_array().map(|v| (v == exit_time) as i32);
@@ -56,6 +67,7 @@ impl Iterator for TreeIter {
     let cursor_inc = min_time_map * self.signums;
 
     self.cursor += cursor_inc;
+    self.time = exit_time;
 
     Some(IterItem {
       pos: BlockPos(self.cursor + self.cursor_offset),
@@ -69,3 +81,105 @@ pub struct IterItem {
   pub pos: BlockPos,
   pub normal: Vec4,
 }
+
+/// Like `TreeIter`, but when it goes into a brick that's all air it only
+/// gives out the first foxel, then jumps straight to wherever the ray
+/// leaves the brick. Outside the world counts as air.
+pub struct BrickSkipIter<'a> {
+  tree: &'a Hexadecitree,
+  inner: TreeIter,
+  skipper: BrickSkipper,
+}
+
+impl<'a> BrickSkipIter<'a> {
+  pub fn new(tree: &'a Hexadecitree, start: Vec4, dir: Vec4) -> Self {
+    Self {
+      tree,
+      inner: TreeIter::new(start, dir),
+      skipper: BrickSkipper::new(start, dir),
+    }
+  }
+
+  /// Same as `TreeIter::entry_time`.
+  pub fn entry_time(&self) -> f32 {
+    self.inner.entry_time()
+  }
+}
+
+impl Iterator for BrickSkipIter<'_> {
+  type Item = IterItem;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    let item = self.inner.next()?;
+    let time = self.inner.entry_time();
+    if let Some(cell) = self.skipper.jump(self.tree, item.pos, time) {
+      self.inner.cursor = cell - self.inner.cursor_offset;
+    }
+    Some(item)
+  }
+}
+
+/// Works out when a ray can jump over a brick of air, and where to.
+#[derive(Debug, Clone, Copy)]
+pub(super) struct BrickSkipper {
+  start: Vec4,
+  dir: Vec4,
+  /// The last brick jumped over, so it doesn't get jumped again if
+  /// coming out of the jump lands back in it.
+  skipped: Option<BlockPos>,
+}
+
+impl BrickSkipper {
+  /// How far back from where the ray leaves a brick to carry on from, so
+  /// the next cell is in the next brick.
+  const REENTRY_BACKOFF: f32 = 1e-3;
+
+  pub fn new(start: Vec4, dir: Vec4) -> Self {
+    Self {
+      start,
+      dir,
+      skipped: None,
+    }
+  }
+
+  /// If the ray's just gone into a brick of air at `pos` at `time`, the
+  /// cell it should carry on from.
+  pub fn jump(
+    &mut self,
+    tree: &Hexadecitree,
+    pos: BlockPos,
+    time: f32,
+  ) -> Option<IVec4> {
+    let corner = Hexadecitree::brick_corner(pos);
+    let all_air = match tree.brick_at(pos) {
+      None | Some(BrickRef::Solid(Foxel::AIR)) => true,
+      Some(_) => false,
+    };
+    if !all_air || self.skipped == Some(corner) {
+      return None;
+    }
+    let backoff = Self::REENTRY_BACKOFF / self.dir.mag();
+    let restart = self.exit_time(corner) - backoff;
+    // Not worth it if it's only just clipping the corner
+    if restart <= time {
+      return None;
+    }
+    self.skipped = Some(corner);
+    let resume = self.start + self.dir * restart;
+    Some(resume.as_array().map(|v| v.floor() as i32).into())
+  }
+
+  /// When the ray leaves the brick with this corner.
+  fn exit_time(&self, corner: BlockPos) -> f32 {
+    let fab = Hexadecitree::FOXELS_ACROSS_BRICK as f32;
+    // Axes it isn't moving along never get it out
+    (0..4)
+      .filter(|axis| self.dir[*axis] != 0.0)
+      .map(|axis| {
+        let lo = corner[axis] as f32;
+        let edge = if self.dir[axis] > 0.0 { lo + fab } else { lo };
+        (edge - self.start[axis]) / self.dir[axis]
+      })
+      .fold(f32::INFINITY, f32::min)
+  }
+}
@@ -4,27 +4,42 @@ to indicate that they generally don't refer to the actual position
 of a block
 */
 
+pub mod boxcast;
 pub mod iter;
+pub mod packet;
+pub mod raycast;
 pub mod reprs;
-mod upload;
+mod save;
+pub mod state;
+pub mod trace;
+pub mod upload;
 
 #[cfg(test)]
 mod tests;
 
+use ahash::AHashSet;
 use log::{error, trace};
 use ultraviolet::IVec4;
 
 use crate::{math::BlockPos, Foxel};
 
 use reprs::*;
+use state::{FoxelState, StateLayer};
 
 /// To facilitate passing to the gee poo, some memory shenanigans are in order.
 #[derive(Debug)]
 pub struct Hexadecitree {
   brick_ptrs: Box<[BrickPtrRepr; Self::TOTAL_BRICK_COUNT as usize]>,
   composite_bricks: Vec<Brick>,
-
-  dirty: bool,
+  states: StateLayer,
+  /// Positions `set` has changed and what was there before, if anyone's
+  /// asked to know.
+  changes: Option<Vec<(BlockPos, Foxel)>>,
+
+  /// Bricks (by grid index) that have changed since the last upload.
+  dirty_bricks: AHashSet<usize>,
+  /// Everything needs uploading, like when the tree's brand new.
+  all_dirty: bool,
 }
 
 impl Hexadecitree {
@@ -51,8 +66,11 @@ impl Hexadecitree {
     Self {
       brick_ptrs: grid,
       composite_bricks: Vec::new(),
+      states: StateLayer::default(),
+      changes: None,
 
-      dirty: true,
+      dirty_bricks: AHashSet::new(),
+      all_dirty: true,
     }
   }
 
@@ -65,7 +83,21 @@ impl Hexadecitree {
     })
   }
 
-  /// Return the previous foxel
+  /// Like `get`, but also get its state.
+  pub fn get_with_state(&self, pos: BlockPos) -> Option<(Foxel, FoxelState)> {
+    let (grid_idx, foxel_idx) = decompose_pos(pos)?;
+    let foxel = self.get(pos)?;
+    Some((foxel, self.states.get(grid_idx, foxel_idx)))
+  }
+
+  pub fn get_state(&self, pos: BlockPos) -> Option<FoxelState> {
+    let (grid_idx, foxel_idx) = decompose_pos(pos)?;
+    Some(self.states.get(grid_idx, foxel_idx))
+  }
+
+  /// Return the previous foxel.
+  ///
+  /// If the foxel changes, its state gets cleared.
   pub fn set(
     &mut self,
     pos: BlockPos,
@@ -129,11 +161,191 @@ impl Hexadecitree {
       }
     };
 
-    // if control flow reaches here, we modified the tree
-    self.dirty = true;
+    if ok_foxel != foxel {
+      self.states.set(grid_idx, foxel_idx, FoxelState::NONE);
+      if let Some(changes) = self.changes.as_mut() {
+        changes.push((pos, ok_foxel));
+      }
+      self.dirty_bricks.insert(grid_idx);
+    }
+
     Ok(ok_foxel)
   }
 
+  /// Start or stop remembering which foxels `set` and `set_brick` change,
+  /// so things like falling sand can react to them.
+  ///
+  /// Turn it off while generating worlds, which would make far too many.
+  pub fn track_changes(&mut self, on: bool) {
+    if on {
+      self.changes.get_or_insert_with(Vec::new);
+    } else {
+      self.changes = None;
+    }
+  }
+
+  /// Every foxel that's changed since the last call and what it was
+  /// before, in the order they changed. The same one can show up more than
+  /// once.
+  pub fn take_changes(&mut self) -> Vec<(BlockPos, Foxel)> {
+    self
+      .changes
+      .as_mut()
+      .map(std::mem::take)
+      .unwrap_or_default()
+  }
+
+  /// Set a foxel and its state at once, returning the previous ones.
+  pub fn set_with_state(
+    &mut self,
+    pos: BlockPos,
+    foxel: Foxel,
+    state: FoxelState,
+  ) -> Result<(Foxel, FoxelState), SetFoxelError> {
+    let (grid_idx, foxel_idx) =
+      decompose_pos(pos).ok_or(SetFoxelError::OutOfBounds)?;
+    let old_state = self.states.get(grid_idx, foxel_idx);
+    let old_foxel = self.set(pos, foxel)?;
+    self.states.set(grid_idx, foxel_idx, state);
+    Ok((old_foxel, old_state))
+  }
+
+  /// Change just the state, and return the previous one.
+  ///
+  /// State doesn't go to the GPU, so this doesn't mark the tree dirty.
+  pub fn set_state(
+    &mut self,
+    pos: BlockPos,
+    state: FoxelState,
+  ) -> Result<FoxelState, SetFoxelError> {
+    let (grid_idx, foxel_idx) =
+      decompose_pos(pos).ok_or(SetFoxelError::OutOfBounds)?;
+    Ok(self.states.set(grid_idx, foxel_idx, state))
+  }
+
+  /// Every foxel with state in the brick containing `pos`.
+  pub fn states_in_brick(
+    &self,
+    pos: BlockPos,
+  ) -> impl Iterator<Item = (BlockPos, FoxelState)> + '_ {
+    let found = decompose_pos(pos)
+      .and_then(|(grid_idx, _)| Some((grid_idx, self.states.brick(grid_idx)?)));
+    found.into_iter().flat_map(|(grid_idx, brick)| {
+      let corner = grid_idx_to_corner(grid_idx);
+      brick.iter().map(move |(idx, state)| {
+        (
+          BlockPos(corner.0 + Brick::idx_to_offset(*idx as usize)),
+          *state,
+        )
+      })
+    })
+  }
+
+  /// Every foxel with state in the whole tree, in no particular order.
+  pub fn states(&self) -> impl Iterator<Item = (BlockPos, FoxelState)> + '_ {
+    self.states.iter().flat_map(|(grid_idx, brick)| {
+      let corner = grid_idx_to_corner(grid_idx);
+      brick.iter().map(move |(idx, state)| {
+        (
+          BlockPos(corner.0 + Brick::idx_to_offset(*idx as usize)),
+          *state,
+        )
+      })
+    })
+  }
+
+  /// How many foxels have state.
+  pub fn state_count(&self) -> usize {
+    self.states.len()
+  }
+
+  /// Overwrite the entire brick containing `pos` at once.
+  ///
+  /// If every foxel in it is the same, and the brick hasn't been expanded
+  /// already, it's kept solid and doesn't eat a composite slot.
+  pub fn set_brick(
+    &mut self,
+    pos: BlockPos,
+    brick: &Brick,
+  ) -> Result<(), SetFoxelError> {
+    let (grid_idx, _) = decompose_pos(pos).ok_or(SetFoxelError::OutOfBounds)?;
+    // What was there, if anyone's asked to know what changes
+    let old = (self.changes.is_some())
+      .then(|| self.brick_at(pos).map(|b| b.to_brick()))
+      .flatten();
+
+    let slot = &mut self.brick_ptrs[grid_idx];
+    match slot.decode() {
+      BrickPtr::Pointer(ptr) => {
+        let Some(bricc) = self.composite_bricks.get_mut(ptr) else {
+          error!(
+            "when setting a brick, a BrickPtr pointed to {} but only have {} \
+            bricks",
+            ptr,
+            self.composite_bricks.len()
+          );
+          return Err(SetFoxelError::OutOfBounds);
+        };
+        self
+          .states
+          .retain_in_brick(grid_idx, |i| bricc.0[i] == brick.0[i]);
+        *bricc = *brick;
+      }
+      BrickPtr::Solid(fill) => match brick.uniform() {
+        Some(f) if f == fill => return Ok(()),
+        Some(f) => {
+          self.states.retain_in_brick(grid_idx, |_| false);
+          *slot = BrickPtr::Solid(f).encode();
+        }
+        None => {
+          let new_composite_idx = self.composite_bricks.len();
+          if new_composite_idx >= Self::COMPOSITE_BRICK_COUNT as usize {
+            return Err(SetFoxelError::OutOfMemory);
+          }
+          let fill = fill.encode();
+          self
+            .states
+            .retain_in_brick(grid_idx, |i| brick.0[i] == fill);
+          self.composite_bricks.push(*brick);
+          *slot = BrickPtr::Pointer(new_composite_idx).encode();
+        }
+      },
+    }
+
+    if let (Some(old), Some(changes)) = (old, self.changes.as_mut()) {
+      let corner = Self::brick_corner(pos);
+      for (i, (was, now)) in old.0.iter().zip(brick.0.iter()).enumerate() {
+        if was != now {
+          let pos = BlockPos(corner.0 + Brick::idx_to_offset(i));
+          changes.push((pos, was.decode()));
+        }
+      }
+    }
+    self.dirty_bricks.insert(grid_idx);
+    Ok(())
+  }
+
+  /// Fill the entire brick containing `pos` with one foxel.
+  pub fn fill_brick(
+    &mut self,
+    pos: BlockPos,
+    foxel: Foxel,
+  ) -> Result<(), SetFoxelError> {
+    self.set_brick(pos, &Brick::composite_solid(foxel))
+  }
+
+  /// Get the brick containing the given position.
+  pub fn brick_at(&self, pos: BlockPos) -> Option<BrickRef<'_>> {
+    let (grid_idx, _) = decompose_pos(pos)?;
+    self.brick_repr_to_ref(self.brick_ptrs[grid_idx])
+  }
+
+  /// The smallest position in the brick containing `pos`.
+  pub fn brick_corner(pos: BlockPos) -> BlockPos {
+    let fab = Self::FOXELS_ACROSS_BRICK as i32;
+    BlockPos(pos.0.map(|v| v.div_euclid(fab) * fab))
+  }
+
   pub fn composite_brick_count(&self) -> usize {
     self.composite_bricks.len()
   }
@@ -156,6 +368,17 @@ impl Hexadecitree {
     })
   }
 
+  /// Every brick and its smallest position, in grid order.
+  pub fn bricks(&self) -> impl Iterator<Item = (BlockPos, BrickRef<'_>)> + '_ {
+    self
+      .brick_ptrs
+      .iter()
+      .enumerate()
+      .filter_map(|(idx, repr)| {
+        Some((grid_idx_to_corner(idx), self.brick_repr_to_ref(*repr)?))
+      })
+  }
+
   pub fn brick_repr_to_ref(&self, ptr: BrickPtrRepr) -> Option<BrickRef<'_>> {
     match ptr.decode() {
       BrickPtr::Solid(f) => Some(BrickRef::Solid(f)),
@@ -177,8 +400,11 @@ impl Hexadecitree {
     println!("{:?}", &self.composite_bricks[0]);
   }
 
+  /// Forget about everything that's changed, as if it had all been
+  /// uploaded.
   pub fn mark_clean(&mut self) {
-    self.dirty = false;
+    self.dirty_bricks.clear();
+    self.all_dirty = false;
   }
 }
 
@@ -196,6 +422,20 @@ fn is_block_in_range(pos: BlockPos) -> bool {
     .all(|n| (Hexadecitree::MIN_COORD..=Hexadecitree::MAX_COORD).contains(&n))
 }
 
+/// The smallest position in the brick at this index in the grid.
+fn grid_idx_to_corner(grid_idx: usize) -> BlockPos {
+  let baw = Hexadecitree::BRICKS_ACROSS_WORLD as usize;
+  let mut coords = [0; 4];
+  let mut rest = grid_idx;
+  // W is the least significant
+  for coord in coords.iter_mut().rev() {
+    let raw = (rest % baw) as i32 - baw as i32 / 2;
+    *coord = raw * Hexadecitree::FOXELS_ACROSS_BRICK as i32;
+    rest /= baw;
+  }
+  BlockPos(IVec4::from(coords))
+}
+
 /// Return the index of the brick it's in, then (if the brick isn't solid)
 /// the index of the position in the brick
 fn decompose_pos(pos: BlockPos) -> Option<(usize, usize)> {
@@ -209,11 +449,7 @@ fn decompose_pos(pos: BlockPos) -> Option<(usize, usize)> {
     let foxel_pos =
       v.rem_euclid(Hexadecitree::FOXELS_ACROSS_BRICK as i32) as usize;
 
-    let raw_brick_pos = if v >= 0 {
-      v / Hexadecitree::FOXELS_ACROSS_BRICK as i32
-    } else {
-      v / Hexadecitree::FOXELS_ACROSS_BRICK as i32 - 1
-    };
+    let raw_brick_pos = v.div_euclid(Hexadecitree::FOXELS_ACROSS_BRICK as i32);
     // Shift so 0,0 is in the center of the bricks
     let brick_pos =
       raw_brick_pos + Hexadecitree::BRICKS_ACROSS_WORLD as i32 / 2;
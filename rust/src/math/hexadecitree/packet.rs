@@ -0,0 +1,237 @@
+//! Following eight rays at once. Stepping through the grid happens for
+//! all of them together with the wide types, but looking things up in the
+//! tree still goes a ray at a time.
+//!
+//! Each lane goes through exactly the same cells, with exactly the same
+//! times, as a `BrickSkipIter` would on its own.
+
+use ultraviolet::{f32x8, IVec4, Vec4, Vec4x8};
+use wide::CmpEq;
+
+use crate::{extensions::F32Ext, math::BlockPos, Foxel};
+
+use super::{
+  iter::{BrickSkipper, IterItem},
+  raycast::RaycastHit,
+  trace::{face_light, Trace, TraceHit, OPAQUE_ENOUGH},
+  Hexadecitree,
+};
+
+pub const LANES: usize = 8;
+
+pub struct PacketIter<'a> {
+  tree: &'a Hexadecitree,
+  dir_recip: Vec4x8,
+  slope_something: Vec4x8,
+  /// Always whole numbers, but kept as floats to stay wide.
+  cursor: Vec4x8,
+  signums: Vec4x8,
+  cursor_offset: [IVec4; LANES],
+  skippers: [BrickSkipper; LANES],
+  times: [f32; LANES],
+  active: [bool; LANES],
+}
+
+impl<'a> PacketIter<'a> {
+  /// Lanes with a direction that isn't finite start out stopped.
+  pub fn new(tree: &'a Hexadecitree, start: Vec4x8, dir: Vec4x8) -> Self {
+    let starts: [Vec4; LANES] = start.into();
+    let dirs: [Vec4; LANES] = dir.into();
+
+    let signums = dirs.map(|d| Vec4::from(d.as_array().map(f32::good_sign)));
+    let cursor_offset = dirs
+      .map(|d| IVec4::from(d.as_array().map(|v| if v < 0.0 { -1 } else { 0 })));
+    let mut cursor = [Vec4::zero(); LANES];
+    for lane in 0..LANES {
+      let floor = starts[lane].as_array().map(|v| v.floor() as i32);
+      cursor[lane] = Vec4::from(IVec4::from(floor) - cursor_offset[lane]);
+    }
+
+    let dir_recip = Vec4x8::one() / dir;
+    Self {
+      tree,
+      dir_recip,
+      slope_something: -start * dir_recip,
+      cursor: cursor.into(),
+      signums: signums.into(),
+      cursor_offset,
+      skippers: std::array::from_fn(|lane| {
+        BrickSkipper::new(starts[lane], dirs[lane])
+      }),
+      times: [0.0; LANES],
+      active: dirs.map(|d| d.as_array().iter().all(|v| v.is_finite())),
+    }
+  }
+
+  pub fn active(&self) -> [bool; LANES] {
+    self.active
+  }
+
+  /// Stop a lane, so it doesn't give out anything more.
+  pub fn stop(&mut self, lane: usize) {
+    self.active[lane] = false;
+  }
+
+  /// Same as `TreeIter::entry_time`, for each lane.
+  pub fn entry_times(&self) -> [f32; LANES] {
+    self.times
+  }
+}
+
+impl Iterator for PacketIter<'_> {
+  type Item = [Option<IterItem>; LANES];
+
+  /// Step every lane that's still going. Stops once they all have.
+  fn next(&mut self) -> Option<Self::Item> {
+    if !self.active.contains(&true) {
+      return None;
+    }
+
+    let exit_poses = self.cursor + self.signums;
+    let exit_times = exit_poses * self.dir_recip + self.slope_something;
+    // Going nowhere along an axis makes NaNs, which never win
+    let inf = f32x8::splat(f32::INFINITY);
+    let [tx, ty, tz, tw] =
+      [exit_times.x, exit_times.y, exit_times.z, exit_times.w]
+        .map(|t| t.is_nan().blend(inf, t));
+    let exit_time = tx.min(ty).min(tz).min(tw);
+
+    let active =
+      f32x8::new(self.active.map(|a| a as u8 as f32)).cmp_eq(f32x8::splat(1.0));
+    let zero = f32x8::splat(0.0);
+    let inc = |t: f32x8, signum: f32x8| {
+      (t.cmp_eq(exit_time) & active).blend(signum, zero)
+    };
+    let cursor_inc = Vec4x8::new(
+      inc(tx, self.signums.x),
+      inc(ty, self.signums.y),
+      inc(tz, self.signums.z),
+      inc(tw, self.signums.w),
+    );
+    self.cursor += cursor_inc;
+
+    let exit_times: [f32; LANES] = exit_time.to_array();
+    let incs: [Vec4; LANES] = cursor_inc.into();
+    let mut cursors: [Vec4; LANES] = self.cursor.into();
+    let mut jumped = false;
+    let mut items = [None; LANES];
+    for lane in 0..LANES {
+      if !self.active[lane] {
+        continue;
+      }
+      let cursor = IVec4::from(cursors[lane].as_array().map(|v| v as i32));
+      let pos = BlockPos(cursor + self.cursor_offset[lane]);
+      self.times[lane] = exit_times[lane];
+      items[lane] = Some(IterItem {
+        pos,
+        normal: -incs[lane],
+      });
+
+      let skipper = &mut self.skippers[lane];
+      if let Some(cell) = skipper.jump(self.tree, pos, exit_times[lane]) {
+        cursors[lane] = Vec4::from(cell - self.cursor_offset[lane]);
+        jumped = true;
+      }
+    }
+    if jumped {
+      self.cursor = cursors.into();
+    }
+    Some(items)
+  }
+}
+
+impl Hexadecitree {
+  /// `raycast`, but eight at once.
+  pub fn raycast_x8(
+    &self,
+    start: Vec4x8,
+    dir: Vec4x8,
+    max_dist: f32,
+  ) -> [Option<RaycastHit>; LANES] {
+    let dirs: [Vec4; LANES] = dir.into();
+    let dir = Vec4x8::from(dirs.map(|d| d.normalized()));
+
+    let mut hits = [None; LANES];
+    let mut iter = PacketIter::new(self, start, dir);
+    while let Some(items) = iter.next() {
+      let times = iter.entry_times();
+      for (lane, item) in items.into_iter().enumerate() {
+        let Some(item) = item else {
+          continue;
+        };
+        if times[lane] > max_dist {
+          iter.stop(lane);
+          continue;
+        }
+        match self.get(item.pos) {
+          Some(foxel) if foxel != Foxel::AIR => {
+            let step_back = item.normal.as_array().map(|v| v as i32);
+            hits[lane] = Some(RaycastHit {
+              foxel,
+              pos: item.pos,
+              normal: item.normal,
+              dist: times[lane],
+              prev: BlockPos(item.pos.0 + step_back.into()),
+            });
+            iter.stop(lane);
+          }
+          _ => {}
+        }
+      }
+    }
+    hits
+  }
+
+  /// `trace`, but eight at once.
+  pub fn trace_x8(
+    &self,
+    start: Vec4x8,
+    dir: Vec4x8,
+    max_steps: usize,
+  ) -> [Trace; LANES] {
+    self.trace_shaded_x8(start, dir, max_steps, |_, normal| face_light(normal))
+  }
+
+  /// `trace_shaded`, but eight at once.
+  pub fn trace_shaded_x8(
+    &self,
+    start: Vec4x8,
+    dir: Vec4x8,
+    max_steps: usize,
+    mut shade: impl FnMut(BlockPos, Vec4) -> f32,
+  ) -> [Trace; LANES] {
+    let mut traces = [Trace::default(); LANES];
+    let mut iter = PacketIter::new(self, start, dir);
+    for _ in 0..max_steps {
+      let Some(items) = iter.next() else {
+        break;
+      };
+      let times = iter.entry_times();
+      for (lane, item) in items.into_iter().enumerate() {
+        let Some(item) = item else {
+          continue;
+        };
+        let Some(foxel) = self.get(item.pos) else {
+          iter.stop(lane);
+          continue;
+        };
+        if foxel == Foxel::AIR {
+          continue;
+        }
+
+        let trace = &mut traces[lane];
+        let layer = trace.add_layer(foxel, shade(item.pos, item.normal));
+        if layer >= 1.0 || trace.opacity >= OPAQUE_ENOUGH {
+          trace.hit = Some(TraceHit {
+            foxel,
+            pos: item.pos,
+            normal: item.normal,
+            dist: times[lane],
+          });
+          iter.stop(lane);
+        }
+      }
+    }
+    traces
+  }
+}
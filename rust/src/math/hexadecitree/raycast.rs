@@ -0,0 +1,60 @@
+//! Finding the first foxel along a ray, for picking and physics and such.
+
+use ultraviolet::Vec4;
+
+use crate::{math::BlockPos, Foxel};
+
+use super::{iter::BrickSkipIter, Hexadecitree};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RaycastHit {
+  pub foxel: Foxel,
+  pub pos: BlockPos,
+  /// Which way the face the ray went in through points. If it went in
+  /// right through an edge or corner, it's more than one axis.
+  pub normal: Vec4,
+  /// How far along the ray it hit.
+  pub dist: f32,
+  /// The cell the ray went through just before, which is where something
+  /// placed against the hit face goes.
+  pub prev: BlockPos,
+}
+
+impl Hexadecitree {
+  /// Find the first foxel that isn't air within `max_dist` of `start`.
+  /// The cell `start` is in doesn't count, same as `TreeIter`.
+  ///
+  /// Outside the world counts as air, so rays can come in from outside.
+  pub fn raycast(
+    &self,
+    start: Vec4,
+    dir: Vec4,
+    max_dist: f32,
+  ) -> Option<RaycastHit> {
+    let dir = dir.normalized();
+    if !dir.as_array().iter().all(|v| v.is_finite()) {
+      return None;
+    }
+    let mut iter = BrickSkipIter::new(self, start, dir);
+    loop {
+      let item = iter.next()?;
+      let dist = iter.entry_time();
+      if dist > max_dist {
+        return None;
+      }
+      match self.get(item.pos) {
+        Some(foxel) if foxel != Foxel::AIR => {
+          let step_back = item.normal.as_array().map(|v| v as i32);
+          return Some(RaycastHit {
+            foxel,
+            pos: item.pos,
+            normal: item.normal,
+            dist,
+            prev: BlockPos(item.pos.0 + step_back.into()),
+          });
+        }
+        _ => {}
+      }
+    }
+  }
+}
@@ -1,6 +1,7 @@
 //! GPU-friendly representations of stuff
 
 use bytemuck::NoUninit;
+use ultraviolet::IVec4;
 
 use crate::world::foxel::{Foxel, FoxelRepr};
 
@@ -17,7 +18,7 @@ pub enum BrickPtr {
 impl BrickPtr {
   pub fn encode(&self) -> BrickPtrRepr {
     BrickPtrRepr(match self {
-      &BrickPtr::Solid(f) => f as u16,
+      &BrickPtr::Solid(f) => f.id() as u16,
       &BrickPtr::Pointer(ptr) => {
         debug_assert!(ptr < Hexadecitree::COMPOSITE_BRICK_COUNT as usize);
         HIGH_BIT16 | (ptr as u16)
@@ -32,6 +33,16 @@ pub enum BrickRef<'a> {
   Ref(&'a Brick),
 }
 
+impl BrickRef<'_> {
+  /// Copy the brick out, expanding it if it's solid.
+  pub fn to_brick(&self) -> Brick {
+    match self {
+      BrickRef::Solid(f) => Brick::composite_solid(*f),
+      BrickRef::Ref(b) => **b,
+    }
+  }
+}
+
 /// The high bit indicates the type of this thing.
 ///
 /// If it's set, then the remainder is a 15-bit brick index.
@@ -54,10 +65,7 @@ impl BrickPtrRepr {
       let ptr = x & (!HIGH_BIT16);
       BrickPtr::Pointer(ptr as usize)
     } else {
-      let foxel = u8::try_from(x & 0xff)
-        .ok()
-        .and_then(|i| Foxel::try_from(i).ok())
-        .unwrap_or(Foxel::Invalid);
+      let foxel = Foxel::from_id((x & 0xff) as u8);
       BrickPtr::Solid(foxel)
     }
   }
@@ -75,4 +83,46 @@ impl Brick {
         .unwrap(),
     )
   }
+
+  /// Index of a foxel in the brick from its offset from the brick's corner.
+  ///
+  /// Same layout as `decompose_pos`: X is the most significant.
+  #[inline]
+  pub fn offset_to_idx(offset: IVec4) -> usize {
+    let fab = Hexadecitree::FOXELS_ACROSS_BRICK as i32;
+    debug_assert!(
+      offset.as_array().iter().all(|v| (0..fab).contains(v)),
+      "{:?} isn't in a brick",
+      offset
+    );
+    (((offset.x * fab + offset.y) * fab + offset.z) * fab + offset.w) as usize
+  }
+
+  /// Inverse of `offset_to_idx`.
+  #[inline]
+  pub fn idx_to_offset(idx: usize) -> IVec4 {
+    let fab = Hexadecitree::FOXELS_ACROSS_BRICK as usize;
+    IVec4::new(
+      (idx / fab / fab / fab) as i32,
+      (idx / fab / fab % fab) as i32,
+      (idx / fab % fab) as i32,
+      (idx % fab) as i32,
+    )
+  }
+
+  #[inline]
+  pub fn get(&self, offset: IVec4) -> Foxel {
+    self.0[Self::offset_to_idx(offset)].decode()
+  }
+
+  #[inline]
+  pub fn set(&mut self, offset: IVec4, foxel: Foxel) {
+    self.0[Self::offset_to_idx(offset)] = foxel.encode();
+  }
+
+  /// If every foxel in the brick is the same, return it.
+  pub fn uniform(&self) -> Option<Foxel> {
+    let first = self.0[0];
+    self.0.iter().all(|f| *f == first).then(|| first.decode())
+  }
 }
@@ -0,0 +1,116 @@
+use std::io::{self, Read, Write};
+
+use crate::world::{
+  foxel::Foxel,
+  save::{read_u16, read_u32, write_u16, write_u32, LoadError},
+};
+
+use super::{
+  reprs::{Brick, BrickPtr, BrickPtrRepr},
+  state::{FoxelState, StateLayer},
+  Hexadecitree,
+};
+
+impl Hexadecitree {
+  /// Format:
+  /// - the brick pointer grid, run-length encoded: a u32 count of runs, then
+  ///   each run as a u32 length and the u16 `BrickPtrRepr`
+  /// - a u32 count of composite bricks, then each one's raw foxels
+  /// - a u32 count of bricks with state, then for each one its u32 grid
+  ///   index, a u16 count, and that many u16 foxel index and u16 state pairs
+  ///
+  /// Everything's sorted, so the same tree always writes the same bytes.
+  pub fn write_to(&self, w: &mut impl Write) -> io::Result<()> {
+    let mut runs: Vec<(u32, BrickPtrRepr)> = Vec::new();
+    for ptr in self.brick_ptrs.iter() {
+      match runs.last_mut() {
+        Some((len, prev)) if prev.0 == ptr.0 => *len += 1,
+        _ => runs.push((1, *ptr)),
+      }
+    }
+    write_u32(w, runs.len() as u32)?;
+    for (len, ptr) in runs {
+      write_u32(w, len)?;
+      write_u16(w, ptr.0)?;
+    }
+
+    write_u32(w, self.composite_bricks.len() as u32)?;
+    for brick in self.composite_bricks.iter() {
+      w.write_all(bytemuck::cast_slice(&brick.0))?;
+    }
+
+    let mut bricks = self.states.iter().collect::<Vec<_>>();
+    bricks.sort_unstable_by_key(|(grid_idx, _)| *grid_idx);
+    write_u32(w, bricks.len() as u32)?;
+    for (grid_idx, states) in bricks {
+      write_u32(w, grid_idx as u32)?;
+      write_u16(w, states.len() as u16)?;
+      let mut states = states.iter().collect::<Vec<_>>();
+      states.sort_unstable_by_key(|(idx, _)| **idx);
+      for (idx, state) in states {
+        write_u16(w, *idx)?;
+        write_u16(w, state.0)?;
+      }
+    }
+    Ok(())
+  }
+
+  pub fn read_from(r: &mut impl Read) -> Result<Self, LoadError> {
+    let mut tree = Hexadecitree::new();
+
+    let run_count = read_u32(r)?;
+    let mut filled = 0usize;
+    for _ in 0..run_count {
+      let len = read_u32(r)? as usize;
+      let ptr = BrickPtrRepr(read_u16(r)?);
+      let end = filled + len;
+      if end > Self::TOTAL_BRICK_COUNT as usize {
+        return Err(LoadError::Corrupt("too many brick pointers"));
+      }
+      tree.brick_ptrs[filled..end].fill(ptr);
+      filled = end;
+    }
+    if filled != Self::TOTAL_BRICK_COUNT as usize {
+      return Err(LoadError::Corrupt("not enough brick pointers"));
+    }
+
+    let composite_count = read_u32(r)? as usize;
+    if composite_count > Self::COMPOSITE_BRICK_COUNT as usize {
+      return Err(LoadError::Corrupt("too many composite bricks"));
+    }
+    tree.composite_bricks.reserve(composite_count);
+    for _ in 0..composite_count {
+      let mut brick = Brick::composite_solid(Foxel::AIR);
+      r.read_exact(bytemuck::cast_slice_mut(&mut brick.0))?;
+      tree.composite_bricks.push(brick);
+    }
+    let dangling = tree.brick_ptrs.iter().any(|ptr| match ptr.decode() {
+      BrickPtr::Pointer(idx) => idx >= composite_count,
+      BrickPtr::Solid(_) => false,
+    });
+    if dangling {
+      return Err(LoadError::Corrupt("brick pointer past the composites"));
+    }
+
+    let mut states = StateLayer::default();
+    let brick_count = read_u32(r)?;
+    for _ in 0..brick_count {
+      let grid_idx = read_u32(r)? as usize;
+      if grid_idx >= Self::TOTAL_BRICK_COUNT as usize {
+        return Err(LoadError::Corrupt("state brick out of bounds"));
+      }
+      let count = read_u16(r)?;
+      for _ in 0..count {
+        let foxel_idx = read_u16(r)? as usize;
+        let state = FoxelState(read_u16(r)?);
+        if foxel_idx >= Self::FOXELS_PER_BRICK as usize {
+          return Err(LoadError::Corrupt("state foxel out of bounds"));
+        }
+        states.set(grid_idx, foxel_idx, state);
+      }
+    }
+    tree.states = states;
+
+    Ok(tree)
+  }
+}
@@ -0,0 +1,96 @@
+//! Extra per-foxel data that doesn't fit in a `FoxelRepr`,
+//! like which way something faces or how full of water it is.
+//!
+//! Almost every foxel has no state, so it's stored sparsely, brick by brick.
+//! Nothing here goes to the GPU.
+
+use ahash::AHashMap;
+
+/// A small value attached to a single foxel. What it means is up to the
+/// foxel type.
+///
+/// The default of 0 means "no state" and isn't stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct FoxelState(pub u16);
+
+impl FoxelState {
+  pub const NONE: FoxelState = FoxelState(0);
+
+  pub fn is_none(self) -> bool {
+    self == Self::NONE
+  }
+}
+
+/// The states of the foxels in one brick, keyed by index in the brick.
+pub type BrickStates = AHashMap<u16, FoxelState>;
+
+#[derive(Debug, Clone, Default)]
+pub(super) struct StateLayer {
+  /// Keyed by index in the brick grid. Bricks with no state don't have
+  /// an entry.
+  bricks: AHashMap<u32, BrickStates>,
+}
+
+impl StateLayer {
+  pub fn get(&self, grid_idx: usize, foxel_idx: usize) -> FoxelState {
+    self
+      .bricks
+      .get(&(grid_idx as u32))
+      .and_then(|b| b.get(&(foxel_idx as u16)))
+      .copied()
+      .unwrap_or_default()
+  }
+
+  /// Return the previous state
+  pub fn set(
+    &mut self,
+    grid_idx: usize,
+    foxel_idx: usize,
+    state: FoxelState,
+  ) -> FoxelState {
+    let grid_idx = grid_idx as u32;
+    let foxel_idx = foxel_idx as u16;
+    if state.is_none() {
+      let Some(brick) = self.bricks.get_mut(&grid_idx) else {
+        return FoxelState::NONE;
+      };
+      let old = brick.remove(&foxel_idx).unwrap_or_default();
+      if brick.is_empty() {
+        self.bricks.remove(&grid_idx);
+      }
+      old
+    } else {
+      let brick = self.bricks.entry(grid_idx).or_default();
+      brick.insert(foxel_idx, state).unwrap_or_default()
+    }
+  }
+
+  pub fn brick(&self, grid_idx: usize) -> Option<&BrickStates> {
+    self.bricks.get(&(grid_idx as u32))
+  }
+
+  /// Only keep the states in a brick that `keep` says to.
+  pub fn retain_in_brick(
+    &mut self,
+    grid_idx: usize,
+    mut keep: impl FnMut(usize) -> bool,
+  ) {
+    let grid_idx = grid_idx as u32;
+    let Some(brick) = self.bricks.get_mut(&grid_idx) else {
+      return;
+    };
+    brick.retain(|idx, _| keep(*idx as usize));
+    if brick.is_empty() {
+      self.bricks.remove(&grid_idx);
+    }
+  }
+
+  pub fn iter(&self) -> impl Iterator<Item = (usize, &BrickStates)> + '_ {
+    self.bricks.iter().map(|(k, v)| (*k as usize, v))
+  }
+
+  /// How many foxels have state
+  pub fn len(&self) -> usize {
+    self.bricks.values().map(|b| b.len()).sum()
+  }
+}
@@ -0,0 +1,100 @@
+//! Following a ray through the tree and blending together everything it
+//! passes through, like `H_raycast` in the shader does.
+
+use ultraviolet::{Vec3, Vec4};
+
+use crate::{math::BlockPos, Foxel};
+
+use super::{iter::BrickSkipIter, Hexadecitree};
+
+/// Once a ray is this opaque there's no point looking further.
+pub const OPAQUE_ENOUGH: f32 = 0.99;
+
+/// Which way faces get lit from. Has to match `show_world.gdshader`.
+pub fn face_light(normal: Vec4) -> f32 {
+  let light_dir = Vec4::new(-0.5, 0.4, 0.2, 0.1).normalized();
+  (-normal).dot(light_dir).clamp(0.8, 1.0)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Trace {
+  /// Premultiplied by `opacity`.
+  pub colour: Vec3,
+  /// How much of whatever's behind the ray gets covered up, from 0 to 1.
+  pub opacity: f32,
+  /// The foxel that made the ray opaque, if one did.
+  pub hit: Option<TraceHit>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TraceHit {
+  pub foxel: Foxel,
+  pub pos: BlockPos,
+  pub normal: Vec4,
+  /// How far along the ray it is, in lengths of `dir`.
+  pub dist: f32,
+}
+
+impl Trace {
+  /// Put what the ray saw over a background colour.
+  pub fn over(&self, background: Vec3) -> Vec3 {
+    self.colour + background * (1.0 - self.opacity)
+  }
+
+  /// Blend a foxel in behind everything so far, lit by `light`. Gives back
+  /// how opaque the foxel on its own is.
+  pub(super) fn add_layer(&mut self, foxel: Foxel, light: f32) -> f32 {
+    let ty = foxel.ty();
+    let [r, g, b] = ty.colour.map(|c| c as f32 / 255.0);
+    let lit = Vec3::new(r, g, b) * light;
+    let layer = ty.opacity.clamp(0.0, 1.0);
+    self.colour += lit * layer * (1.0 - self.opacity);
+    self.opacity += layer * (1.0 - self.opacity);
+    layer
+  }
+}
+
+impl Hexadecitree {
+  /// Walk at most `max_steps` steps along the ray, blending the colours
+  /// of translucent foxels front to back until something opaque is hit.
+  /// A step is a foxel, or a whole brick of air.
+  pub fn trace(&self, start: Vec4, dir: Vec4, max_steps: usize) -> Trace {
+    self.trace_shaded(start, dir, max_steps, |_, normal| face_light(normal))
+  }
+
+  /// Like `trace`, but `shade` says how brightly lit the face of the foxel
+  /// at a position along a normal is.
+  pub fn trace_shaded(
+    &self,
+    start: Vec4,
+    dir: Vec4,
+    max_steps: usize,
+    mut shade: impl FnMut(BlockPos, Vec4) -> f32,
+  ) -> Trace {
+    let mut trace = Trace::default();
+    let mut iter = BrickSkipIter::new(self, start, dir);
+    for _ in 0..max_steps {
+      let Some(item) = iter.next() else {
+        break;
+      };
+      let Some(foxel) = self.get(item.pos) else {
+        break;
+      };
+      if foxel == Foxel::AIR {
+        continue;
+      }
+
+      let layer = trace.add_layer(foxel, shade(item.pos, item.normal));
+      if layer >= 1.0 || trace.opacity >= OPAQUE_ENOUGH {
+        trace.hit = Some(TraceHit {
+          foxel,
+          pos: item.pos,
+          normal: item.normal,
+          dist: iter.entry_time(),
+        });
+        break;
+      }
+    }
+    trace
+  }
+}
@@ -1,14 +1,110 @@
-use ahash::AHashMap;
-use itertools::Itertools;
-use ultraviolet::Vec4;
+//! Getting the tree onto the GPU, through a big image full of bytes.
+//!
+//! The brick pointer table goes first, then a few composite bricks the
+//! camera can see, then their AO. Only the bits that changed
+//! since last time get written, so small edits make small uploads.
+
+use std::{collections::BTreeSet, ops::Range};
+
+use ahash::AHashSet;
+use itertools::iproduct;
+use ultraviolet::IVec4;
 
 use crate::{
-  godot_bridge::{vec4_to_gd, GdPlayerCamera},
-  math::hexadecitree::{BrickPtr, BrickPtrRepr, BrickRef},
-  world::foxel::Foxel,
+  math::BlockPos,
+  render::view::ViewVolume,
+  world::ao::{AoCache, FaceAo},
+};
+
+use super::{
+  grid_idx_to_corner, Brick, BrickPtr, BrickPtrRepr, BrickRef, Hexadecitree,
 };
 
-use super::{Brick, Hexadecitree};
+const SLOTS: usize = Hexadecitree::GPU_COMPOSITE_BRICKS_COUNT as usize;
+const PTR_BYTES: usize = std::mem::size_of::<BrickPtrRepr>();
+const BRICK_BYTES: usize = std::mem::size_of::<Brick>();
+const AO_BYTES: usize = Hexadecitree::GPU_AO_BYTES / SLOTS;
+
+/// What's been uploaded so far, so the next upload knows what it can skip.
+/// Has to go along with the same bytes every time.
+#[derive(Debug, Clone)]
+pub struct UploadState {
+  /// Grid index of the brick in each composite slot.
+  slots: [Option<usize>; SLOTS],
+  /// Grid index of every composite brick in the tree.
+  composites: BTreeSet<usize>,
+  /// Nothing's been uploaded yet.
+  fresh: bool,
+}
+
+impl UploadState {
+  pub fn new() -> Self {
+    Self {
+      slots: [None; SLOTS],
+      composites: BTreeSet::new(),
+      fresh: true,
+    }
+  }
+
+  /// Corner of the brick in each composite slot.
+  pub fn slots(&self) -> [Option<BlockPos>; SLOTS] {
+    self.slots.map(|slot| slot.map(grid_idx_to_corner))
+  }
+}
+
+impl Default for UploadState {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+/// Which bytes an upload changed, sorted and not overlapping.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UploadPatch {
+  ranges: Vec<Range<usize>>,
+}
+
+impl UploadPatch {
+  fn new(mut ranges: Vec<Range<usize>>) -> Self {
+    ranges.sort_by_key(|r| r.start);
+    let mut merged: Vec<Range<usize>> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+      match merged.last_mut() {
+        Some(last) if range.start <= last.end => {
+          last.end = last.end.max(range.end)
+        }
+        _ => merged.push(range),
+      }
+    }
+    Self { ranges: merged }
+  }
+
+  pub fn ranges(&self) -> &[Range<usize>] {
+    &self.ranges
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.ranges.is_empty()
+  }
+
+  /// How many bytes changed.
+  pub fn len(&self) -> usize {
+    self.ranges.iter().map(|r| r.len()).sum()
+  }
+
+  /// Which rows of the transfer image those bytes are in.
+  pub fn rows(&self) -> Vec<Range<usize>> {
+    let row = Hexadecitree::GPU_TRANSFER_ROW_BYTES;
+    UploadPatch::new(
+      self
+        .ranges
+        .iter()
+        .map(|r| r.start / row..r.end.div_ceil(row))
+        .collect(),
+    )
+    .ranges
+  }
+}
 
 impl Hexadecitree {
   pub const GPU_BRICK_PTRS_COUNT: u32 = Self::TOTAL_BRICK_COUNT;
@@ -19,9 +115,16 @@ impl Hexadecitree {
   pub const GPU_COMPOSITE_BRICKS_BYTES: usize =
     Self::GPU_COMPOSITE_BRICKS_COUNT as usize * std::mem::size_of::<Brick>();
 
-  pub const GPU_TOTAL_BYTES: usize =
+  /// AO for every foxel in the composite bricks, in the same order.
+  /// Solid bricks don't get any.
+  pub const GPU_AO_BYTES: usize = Self::GPU_COMPOSITE_BRICKS_COUNT as usize
+    * Self::FOXELS_PER_BRICK as usize
+    * std::mem::size_of::<FaceAo>();
+  pub const GPU_AO_OFFSET: usize =
     Self::GPU_BRICK_PTRS_BYTES + Self::GPU_COMPOSITE_BRICKS_BYTES;
 
+  pub const GPU_TOTAL_BYTES: usize = Self::GPU_AO_OFFSET + Self::GPU_AO_BYTES;
+
   /// RF encoding means each pixel is 1 8-bit float,
   /// "representing" a monochrome red.
   /// One byte equals one foxel, so one pixel equals 4 foxels.
@@ -31,55 +134,142 @@ impl Hexadecitree {
     (Self::GPU_TOTAL_BYTES / 4).isqrt().next_power_of_two();
   pub const GPU_TRANSFER_IMAGE_SIZE_SQ: usize =
     Self::GPU_TRANSFER_IMAGE_SIZE.pow(2);
+  pub const GPU_TRANSFER_ROW_BYTES: usize = Self::GPU_TRANSFER_IMAGE_SIZE * 4;
 
-  pub fn upload(&self, bytes: &mut [u8], cam: &GdPlayerCamera) {
-    if !self.dirty {
-      return;
-    }
-
+  /// Write whatever's changed since the last upload with `state` into
+  /// `bytes`, and say which bytes those were. Composite bricks outside
+  /// `view` don't get a slot, and get drawn as air.
+  ///
+  /// The first upload, or the first one after the tree gets swapped for
+  /// another, writes everything. After that it's only the pointers for
+  /// bricks that changed or went in or out of a slot, and the slots whose
+  /// bricks (or their neighbours, for AO) changed.
+  pub fn upload(
+    &mut self,
+    bytes: &mut [u8],
+    view: &ViewVolume,
+    ao: &mut AoCache,
+    state: &mut UploadState,
+  ) -> UploadPatch {
     debug_assert!(
       Hexadecitree::GPU_TOTAL_BYTES
         <= Hexadecitree::GPU_TRANSFER_IMAGE_SIZE_SQ * 4
     );
+    assert!(bytes.len() >= Self::GPU_TOTAL_BYTES);
 
-    let mut gpu_composite_bricks = Vec::<Brick>::new();
-
-    let gpu_brick_ptrs = self
-      .brick_ptrs()
-      .map(|(corner, brick_repr)| {
-        let brick_ref = self.brick_repr_to_ref(brick_repr).unwrap();
-        match brick_ref {
-          BrickRef::Solid(_) => brick_repr,
-          BrickRef::Ref(brick_ref) => {
-            let brick_limit_reached = gpu_composite_bricks.len()
-              >= Self::GPU_COMPOSITE_BRICKS_COUNT as usize;
-            // Check if the brick is actually in ambit
-            let player_to_brick = vec4_to_gd(corner.into()) - cam.pos;
-            let player_forward_vec = vec4_to_gd(cam.rot * Vec4::unit_y());
-            let brick_probably_in_fov = player_to_brick.is_zero_approx()
-              || player_to_brick.normalized().dot(player_forward_vec)
-                >= -cam.fov;
-            if brick_limit_reached || !brick_probably_in_fov {
-              BrickPtrRepr::entirely_air()
-            } else {
-              let composite_idx = gpu_composite_bricks.len();
-              gpu_composite_bricks.push(brick_ref.clone());
-              BrickPtr::Pointer(composite_idx).encode()
-            }
-          }
+    let everything = state.fresh || self.all_dirty;
+    let dirty = std::mem::take(&mut self.dirty_bricks);
+    self.all_dirty = false;
+    if everything {
+      *state = UploadState::new();
+      state.fresh = false;
+      state.composites = (self.brick_ptrs.iter().enumerate())
+        .filter(|(_, repr)| matches!(repr.decode(), BrickPtr::Pointer(_)))
+        .map(|(idx, _)| idx)
+        .collect();
+    } else {
+      for &idx in &dirty {
+        if matches!(self.brick_ptrs[idx].decode(), BrickPtr::Pointer(_)) {
+          state.composites.insert(idx);
+        } else {
+          state.composites.remove(&idx);
         }
+      }
+    }
+
+    // The first few composite bricks in grid order that might be on screen.
+    // Ones that were already in a slot stay put.
+    let wanted = (state.composites.iter().copied())
+      .filter(|idx| view.intersects_brick(grid_idx_to_corner(*idx)))
+      .take(SLOTS)
+      .collect::<Vec<_>>();
+    let old_slots = state.slots;
+    let mut slots = old_slots.map(|slot| slot.filter(|s| wanted.contains(s)));
+    for &idx in &wanted {
+      if !slots.contains(&Some(idx)) {
+        let free = slots.iter().position(Option::is_none).unwrap();
+        slots[free] = Some(idx);
+      }
+    }
+    state.slots = slots;
+
+    let mut ranges = Vec::new();
+
+    let ptr_for = |idx: usize| match self.brick_ptrs[idx].decode() {
+      BrickPtr::Solid(_) => self.brick_ptrs[idx],
+      BrickPtr::Pointer(_) => {
+        match slots.iter().position(|s| *s == Some(idx)) {
+          Some(slot) => BrickPtr::Pointer(slot).encode(),
+          None => BrickPtrRepr::entirely_air(),
+        }
+      }
+    };
+    if everything {
+      let ptrs = (0..self.brick_ptrs.len()).map(ptr_for).collect::<Vec<_>>();
+      bytes[..Self::GPU_BRICK_PTRS_BYTES]
+        .copy_from_slice(bytemuck::cast_slice(ptrs.as_slice()));
+      ranges.push(0..Self::GPU_BRICK_PTRS_BYTES);
+    } else {
+      let touched = (dirty.iter().copied())
+        .chain(old_slots.into_iter().flatten())
+        .chain(slots.into_iter().flatten())
+        .collect::<BTreeSet<_>>();
+      for idx in touched {
+        let range = idx * PTR_BYTES..(idx + 1) * PTR_BYTES;
+        let ptr = ptr_for(idx);
+        let new = bytemuck::bytes_of(&ptr);
+        if bytes[range.clone()] != *new {
+          bytes[range.clone()].copy_from_slice(new);
+          ranges.push(range);
+        }
+      }
+    }
+
+    // AO reaches one foxel into the neighbouring bricks
+    let dirty_corners = dirty
+      .iter()
+      .map(|idx| grid_idx_to_corner(*idx))
+      .collect::<AHashSet<_>>();
+    let near_dirty = |corner: BlockPos| {
+      let fab = Self::FOXELS_ACROSS_BRICK as i32;
+      iproduct!(-1..=1, -1..=1, -1..=1, -1..=1).any(|(x, y, z, w)| {
+        let offset = IVec4::new(x, y, z, w) * fab;
+        dirty_corners.contains(&BlockPos(corner.0 + offset))
       })
-      .collect_vec();
+    };
 
-    debug_assert_eq!(self.brick_ptrs.len(), gpu_brick_ptrs.len());
+    let mut stale_ao = Vec::new();
+    for (slot, idx) in slots.iter().enumerate() {
+      let Some(idx) = *idx else { continue };
+      let moved = everything || old_slots[slot] != Some(idx);
+      if moved || dirty.contains(&idx) {
+        let Some(BrickRef::Ref(brick)) =
+          self.brick_repr_to_ref(self.brick_ptrs[idx])
+        else {
+          unreachable!("only composite bricks get slots");
+        };
+        let start = Self::GPU_BRICK_PTRS_BYTES + slot * BRICK_BYTES;
+        bytes[start..start + BRICK_BYTES]
+          .copy_from_slice(bytemuck::bytes_of(brick));
+        ranges.push(start..start + BRICK_BYTES);
+      }
+      let corner = grid_idx_to_corner(idx);
+      if moved || near_dirty(corner) {
+        stale_ao.push((slot, corner));
+      }
+    }
 
-    (&mut bytes[..Hexadecitree::GPU_BRICK_PTRS_BYTES])
-      .copy_from_slice(bytemuck::cast_slice(gpu_brick_ptrs.as_slice()));
+    ao.ensure(self, stale_ao.iter().map(|(_, corner)| *corner));
+    for (slot, corner) in stale_ao {
+      let start = Self::GPU_AO_OFFSET + slot * AO_BYTES;
+      let out = &mut bytes[start..start + AO_BYTES];
+      match ao.brick(self, corner) {
+        Some(faces) => out.copy_from_slice(bytemuck::cast_slice(faces)),
+        None => out.fill(0),
+      }
+      ranges.push(start..start + AO_BYTES);
+    }
 
-    let composite_bricks_bytes: &[u8] =
-      bytemuck::cast_slice(gpu_composite_bricks.as_slice());
-    (&mut bytes[Hexadecitree::GPU_BRICK_PTRS_BYTES
-      ..Hexadecitree::GPU_BRICK_PTRS_BYTES + composite_bricks_bytes.len()])
-      .copy_from_slice(composite_bricks_bytes);
+    UploadPatch::new(ranges)
   }
 }
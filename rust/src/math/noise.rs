@@ -0,0 +1,72 @@
+//! Coherent noise, for world generation, texturing, and anything else
+//! that wants to be random-looking but smooth.
+//!
+//! Everything is 4D, seeded, and works on one `Vec4` at a time or on
+//! eight at once with a `Vec4x8`. Noises can be stacked with the
+//! combinators in [`fractal`].
+
+mod fractal;
+mod simplex;
+mod value;
+mod worley;
+
+pub use fractal::*;
+pub use simplex::*;
+pub use value::*;
+pub use worley::*;
+
+use ultraviolet::{f32x8, Vec4, Vec4x8};
+
+pub trait Noise4: Send + Sync {
+  /// Sample the noise at a point. Generally in about `[-1, 1]`, but check
+  /// the docs of each noise.
+  fn sample(&self, p: Vec4) -> f32;
+
+  /// Sample eight points at once.
+  ///
+  /// By default this just samples each lane one by one.
+  fn sample_x8(&self, p: Vec4x8) -> f32x8 {
+    let ps: [Vec4; 8] = p.into();
+    f32x8::new(ps.map(|p| self.sample(p)))
+  }
+}
+
+impl<N: Noise4 + ?Sized> Noise4 for &N {
+  fn sample(&self, p: Vec4) -> f32 {
+    (**self).sample(p)
+  }
+
+  fn sample_x8(&self, p: Vec4x8) -> f32x8 {
+    (**self).sample_x8(p)
+  }
+}
+
+impl<N: Noise4 + ?Sized> Noise4 for Box<N> {
+  fn sample(&self, p: Vec4) -> f32 {
+    (**self).sample(p)
+  }
+
+  fn sample_x8(&self, p: Vec4x8) -> f32x8 {
+    (**self).sample_x8(p)
+  }
+}
+
+/// Quintic smoothstep, `6t^5 - 15t^4 + 10t^3`.
+#[inline]
+fn fade(t: f32) -> f32 {
+  t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+#[inline]
+fn fade_x8(t: f32x8) -> f32x8 {
+  t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+/// Split eight points into the integer lattice cell each is in,
+/// and the offset into the cell.
+#[inline]
+fn floor_x8(p: Vec4x8) -> ([Vec4; 8], Vec4x8) {
+  let ps: [Vec4; 8] = p.into();
+  let cells = ps.map(|p| p.map(f32::floor));
+  (cells, p - Vec4x8::from(cells))
+}
@@ -0,0 +1,195 @@
+//! Noises made of other noises.
+
+use ultraviolet::{f32x8, Vec4, Vec4x8};
+
+use super::Noise4;
+
+/// Fractal brownian motion: `octaves` layers of the noise, each at
+/// `lacunarity` times the frequency and `gain` times the amplitude of
+/// the last.
+///
+/// Normalized back into the same range as the noise it's made of.
+#[derive(Debug, Clone)]
+pub struct Fbm<N> {
+  pub noise: N,
+  pub octaves: u32,
+  pub lacunarity: f32,
+  pub gain: f32,
+}
+
+impl<N> Fbm<N> {
+  pub fn new(noise: N, octaves: u32) -> Self {
+    Self {
+      noise,
+      octaves,
+      lacunarity: 2.0,
+      gain: 0.5,
+    }
+  }
+
+  /// The sum of the amplitudes of every octave, for normalizing.
+  fn total_amplitude(&self) -> f32 {
+    (0..self.octaves).map(|i| self.gain.powi(i as i32)).sum()
+  }
+}
+
+impl<N: Noise4> Noise4 for Fbm<N> {
+  fn sample(&self, p: Vec4) -> f32 {
+    let mut total = 0.0;
+    let mut amplitude = 1.0;
+    let mut freq = 1.0;
+    for _ in 0..self.octaves {
+      total += amplitude * self.noise.sample(p * freq);
+      amplitude *= self.gain;
+      freq *= self.lacunarity;
+    }
+    normalize(total, self.total_amplitude())
+  }
+
+  fn sample_x8(&self, p: Vec4x8) -> f32x8 {
+    let mut total = f32x8::splat(0.0);
+    let mut amplitude = 1.0;
+    let mut freq = 1.0;
+    for _ in 0..self.octaves {
+      total += self.noise.sample_x8(p * f32x8::splat(freq)) * amplitude;
+      amplitude *= self.gain;
+      freq *= self.lacunarity;
+    }
+    let max = self.total_amplitude();
+    if max == 0.0 {
+      f32x8::splat(0.0)
+    } else {
+      total / max
+    }
+  }
+}
+
+/// Ridged multifractal. Like [`Fbm`], but folds each octave around zero so
+/// the zero-crossings turn into sharp ridges: mountain ranges, cracks,
+/// winding tunnels, that sort of thing.
+///
+/// Each octave is also weighted by the one before it, so the ridges get
+/// detail and the valleys stay smooth.
+///
+/// In `[-1, 1]` if the noise it's made of is.
+#[derive(Debug, Clone)]
+pub struct Ridged<N> {
+  pub noise: N,
+  pub octaves: u32,
+  pub lacunarity: f32,
+  pub gain: f32,
+}
+
+impl<N> Ridged<N> {
+  pub fn new(noise: N, octaves: u32) -> Self {
+    Self {
+      noise,
+      octaves,
+      lacunarity: 2.0,
+      gain: 0.5,
+    }
+  }
+
+  fn total_amplitude(&self) -> f32 {
+    (0..self.octaves).map(|i| self.gain.powi(i as i32)).sum()
+  }
+}
+
+impl<N: Noise4> Noise4 for Ridged<N> {
+  fn sample(&self, p: Vec4) -> f32 {
+    let mut total = 0.0;
+    let mut amplitude = 1.0;
+    let mut freq = 1.0;
+    let mut weight = 1.0;
+    for _ in 0..self.octaves {
+      let ridge = 1.0 - self.noise.sample(p * freq).abs();
+      let ridge = ridge * ridge * weight;
+      weight = ridge.clamp(0.0, 1.0);
+
+      total += ridge * amplitude;
+      amplitude *= self.gain;
+      freq *= self.lacunarity;
+    }
+    normalize(total, self.total_amplitude()) * 2.0 - 1.0
+  }
+
+  fn sample_x8(&self, p: Vec4x8) -> f32x8 {
+    let zero = f32x8::splat(0.0);
+    let one = f32x8::splat(1.0);
+
+    let mut total = zero;
+    let mut amplitude = 1.0;
+    let mut freq = 1.0;
+    let mut weight = one;
+    for _ in 0..self.octaves {
+      let ridge = one - self.noise.sample_x8(p * f32x8::splat(freq)).abs();
+      let ridge = ridge * ridge * weight;
+      weight = ridge.max(zero).min(one);
+
+      total += ridge * amplitude;
+      amplitude *= self.gain;
+      freq *= self.lacunarity;
+    }
+    let max = self.total_amplitude();
+    if max == 0.0 {
+      -one
+    } else {
+      total / max * 2.0 - one
+    }
+  }
+}
+
+/// Looks up `noise` somewhere else than asked, pushed around by `warp`.
+/// Turns blobs into swirls.
+///
+/// Each axis of the offset is `warp` sampled at a different, far-away spot,
+/// times `strength`.
+#[derive(Debug, Clone)]
+pub struct DomainWarp<N, W> {
+  pub noise: N,
+  pub warp: W,
+  pub strength: f32,
+}
+
+impl<N, W> DomainWarp<N, W> {
+  /// Arbitrary and big, so the per-axis warps don't look alike.
+  const AXIS_OFFSETS: [Vec4; 4] = [
+    Vec4::new(0.0, 0.0, 0.0, 0.0),
+    Vec4::new(31.7, -11.3, 57.1, 4.9),
+    Vec4::new(-73.3, 23.9, -5.1, 41.3),
+    Vec4::new(13.1, 67.7, -29.3, -89.9),
+  ];
+
+  pub fn new(noise: N, warp: W, strength: f32) -> Self {
+    Self {
+      noise,
+      warp,
+      strength,
+    }
+  }
+}
+
+impl<N: Noise4, W: Noise4> Noise4 for DomainWarp<N, W> {
+  fn sample(&self, p: Vec4) -> f32 {
+    let [a, b, c, d] =
+      Self::AXIS_OFFSETS.map(|offset| self.warp.sample(p + offset));
+    let offset = Vec4::new(a, b, c, d) * self.strength;
+    self.noise.sample(p + offset)
+  }
+
+  fn sample_x8(&self, p: Vec4x8) -> f32x8 {
+    let [a, b, c, d] = Self::AXIS_OFFSETS
+      .map(|offset| self.warp.sample_x8(p + Vec4x8::splat(offset)));
+    let offset = Vec4x8::new(a, b, c, d) * f32x8::splat(self.strength);
+    self.noise.sample_x8(p + offset)
+  }
+}
+
+#[inline]
+fn normalize(total: f32, max: f32) -> f32 {
+  if max == 0.0 {
+    0.0
+  } else {
+    total / max
+  }
+}
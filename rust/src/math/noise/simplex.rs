@@ -0,0 +1,211 @@
+use ultraviolet::{f32x8, Vec4, Vec4x8};
+use wide::{CmpGe, CmpGt};
+
+use crate::math::rng::SplitMix64;
+
+use super::{floor_x8, Noise4};
+
+/// Seeded 4D simplex noise. In about `[-1, 1]`.
+///
+/// This is more or less a transliteration of Stefan Gustavson's
+/// "Simplex noise demystified," with the permutation table shuffled
+/// from a seed instead of being Ken Perlin's hardcoded one.
+#[derive(Clone)]
+pub struct Simplex4 {
+  perm: [u8; 512],
+}
+
+impl Simplex4 {
+  /// `(sqrt(5) - 1) / 4`
+  const F4: f32 = 0.309_017;
+  /// `(5 - sqrt(5)) / 20`
+  const G4: f32 = 0.138_196_6;
+
+  pub fn new(seed: u64) -> Self {
+    let mut rng = SplitMix64::new(seed);
+    let mut table = [0u8; 256];
+    for (i, v) in table.iter_mut().enumerate() {
+      *v = i as u8;
+    }
+    // Fisher-Yates
+    for i in (1..256).rev() {
+      let j = (rng.next_u64() % (i as u64 + 1)) as usize;
+      table.swap(i, j);
+    }
+
+    let mut perm = [0u8; 512];
+    for (i, v) in perm.iter_mut().enumerate() {
+      *v = table[i & 255];
+    }
+    Self { perm }
+  }
+
+  /// The gradient at the corner `offset` away from the cell, which has
+  /// already been wrapped to `0..256`.
+  #[inline]
+  fn grad(&self, cell: [usize; 4], offset: Vec4) -> Vec4 {
+    let [ci, cj, ck, cl] = cell;
+    let [oi, oj, ok, ol] = offset.as_array().map(|v| v as usize);
+    let hash = self.perm[ci
+      + oi
+      + self.perm
+        [cj + oj + self.perm[ck + ok + self.perm[cl + ol] as usize] as usize]
+        as usize];
+    GRAD4[(hash & 31) as usize]
+  }
+}
+
+impl Noise4 for Simplex4 {
+  fn sample(&self, p: Vec4) -> f32 {
+    // Skew into the simplectic honeycomb and find which cell we're in
+    let s = (p.x + p.y + p.z + p.w) * Self::F4;
+    let cell = (p + Vec4::broadcast(s)).map(f32::floor);
+    let t = (cell.x + cell.y + cell.z + cell.w) * Self::G4;
+    // Unskew back
+    let x0 = p - (cell - Vec4::broadcast(t));
+
+    // Rank the coordinates to figure out which of the 24 simplices
+    // in the cell we're in.
+    let mut rank = [0u8; 4];
+    let c = x0.as_array();
+    for a in 0..4 {
+      for b in (a + 1)..4 {
+        if c[a] > c[b] {
+          rank[a] += 1;
+        } else {
+          rank[b] += 1;
+        }
+      }
+    }
+
+    let corner_offset = |threshold: u8| {
+      Vec4::new(
+        (rank[0] >= threshold) as u8 as f32,
+        (rank[1] >= threshold) as u8 as f32,
+        (rank[2] >= threshold) as u8 as f32,
+        (rank[3] >= threshold) as u8 as f32,
+      )
+    };
+    let offsets = [
+      Vec4::zero(),
+      corner_offset(3),
+      corner_offset(2),
+      corner_offset(1),
+      Vec4::one(),
+    ];
+
+    let cell = cell.as_array().map(|v| (v as i32).rem_euclid(256) as usize);
+
+    let mut total = 0.0;
+    for (n, offset) in offsets.into_iter().enumerate() {
+      let x = x0 - offset + Vec4::broadcast(n as f32 * Self::G4);
+      let falloff = 0.6 - x.mag_sq();
+      if falloff <= 0.0 {
+        continue;
+      }
+
+      let grad = self.grad(cell, offset);
+      let falloff2 = falloff * falloff;
+      total += falloff2 * falloff2 * grad.dot(x);
+    }
+
+    27.0 * total
+  }
+
+  fn sample_x8(&self, p: Vec4x8) -> f32x8 {
+    let (zero, one) = (f32x8::splat(0.0), f32x8::splat(1.0));
+    let s = (p.x + p.y + p.z + p.w) * f32x8::splat(Self::F4);
+    let (cells, _) = floor_x8(p + Vec4x8::new(s, s, s, s));
+    let cell = Vec4x8::from(cells);
+    let t = (cell.x + cell.y + cell.z + cell.w) * f32x8::splat(Self::G4);
+    let x0 = p - (cell - Vec4x8::new(t, t, t, t));
+
+    // Same ranking as `sample`, counted up in every lane at once
+    let mut rank = [zero; 4];
+    let c = [x0.x, x0.y, x0.z, x0.w];
+    for a in 0..4 {
+      for b in (a + 1)..4 {
+        let bigger = c[a].cmp_gt(c[b]);
+        rank[a] += bigger.blend(one, zero);
+        rank[b] += bigger.blend(zero, one);
+      }
+    }
+
+    let corner_offset = |threshold: f32| {
+      let [x, y, z, w] =
+        rank.map(|r| r.cmp_ge(f32x8::splat(threshold)).blend(one, zero));
+      Vec4x8::new(x, y, z, w)
+    };
+    let offsets = [
+      Vec4x8::zero(),
+      corner_offset(3.0),
+      corner_offset(2.0),
+      corner_offset(1.0),
+      Vec4x8::one(),
+    ];
+
+    let cells =
+      cells.map(|c| c.as_array().map(|v| (v as i32).rem_euclid(256) as usize));
+
+    let mut total = zero;
+    for (n, offset) in offsets.into_iter().enumerate() {
+      let x = x0 - offset + Vec4x8::splat(Vec4::broadcast(n as f32 * Self::G4));
+      let falloff = (f32x8::splat(0.6) - x.mag_sq()).max(zero);
+
+      let lane_offsets: [Vec4; 8] = offset.into();
+      let grads: [Vec4; 8] =
+        std::array::from_fn(|lane| self.grad(cells[lane], lane_offsets[lane]));
+      let falloff2 = falloff * falloff;
+      total += falloff2 * falloff2 * Vec4x8::from(grads).dot(x);
+    }
+
+    f32x8::splat(27.0) * total
+  }
+}
+
+impl std::fmt::Debug for Simplex4 {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("Simplex4").finish_non_exhaustive()
+  }
+}
+
+/// The midpoints of the edges of a tesseract.
+const GRAD4: [Vec4; 32] = {
+  const fn v(x: f32, y: f32, z: f32, w: f32) -> Vec4 {
+    Vec4::new(x, y, z, w)
+  }
+  [
+    v(0.0, 1.0, 1.0, 1.0),
+    v(0.0, 1.0, 1.0, -1.0),
+    v(0.0, 1.0, -1.0, 1.0),
+    v(0.0, 1.0, -1.0, -1.0),
+    v(0.0, -1.0, 1.0, 1.0),
+    v(0.0, -1.0, 1.0, -1.0),
+    v(0.0, -1.0, -1.0, 1.0),
+    v(0.0, -1.0, -1.0, -1.0),
+    v(1.0, 0.0, 1.0, 1.0),
+    v(1.0, 0.0, 1.0, -1.0),
+    v(1.0, 0.0, -1.0, 1.0),
+    v(1.0, 0.0, -1.0, -1.0),
+    v(-1.0, 0.0, 1.0, 1.0),
+    v(-1.0, 0.0, 1.0, -1.0),
+    v(-1.0, 0.0, -1.0, 1.0),
+    v(-1.0, 0.0, -1.0, -1.0),
+    v(1.0, 1.0, 0.0, 1.0),
+    v(1.0, 1.0, 0.0, -1.0),
+    v(1.0, -1.0, 0.0, 1.0),
+    v(1.0, -1.0, 0.0, -1.0),
+    v(-1.0, 1.0, 0.0, 1.0),
+    v(-1.0, 1.0, 0.0, -1.0),
+    v(-1.0, -1.0, 0.0, 1.0),
+    v(-1.0, -1.0, 0.0, -1.0),
+    v(1.0, 1.0, 1.0, 0.0),
+    v(1.0, 1.0, -1.0, 0.0),
+    v(1.0, -1.0, 1.0, 0.0),
+    v(1.0, -1.0, -1.0, 0.0),
+    v(-1.0, 1.0, 1.0, 0.0),
+    v(-1.0, 1.0, -1.0, 0.0),
+    v(-1.0, -1.0, 1.0, 0.0),
+    v(-1.0, -1.0, -1.0, 0.0),
+  ]
+};
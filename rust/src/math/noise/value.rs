@@ -0,0 +1,85 @@
+use ultraviolet::{f32x8, IVec4, Vec4, Vec4x8};
+
+use crate::math::rng::hash_pos;
+
+use super::{fade, fade_x8, floor_x8, Noise4};
+
+/// Seeded 4D value noise: a random value at each integer lattice point,
+/// smoothly interpolated in between. In `[-1, 1]`.
+///
+/// Blockier-looking than simplex, but cheap and very predictable.
+#[derive(Debug, Clone)]
+pub struct Value4 {
+  seed: u64,
+}
+
+impl Value4 {
+  pub fn new(seed: u64) -> Self {
+    Self { seed }
+  }
+
+  #[inline]
+  fn lattice(&self, cell: IVec4) -> f32 {
+    let h = hash_pos(self.seed, cell);
+    (h >> 40) as f32 / (1u64 << 23) as f32 - 1.0
+  }
+
+  /// Offset of one of the 16 corners of a tesseract, from its index.
+  #[inline]
+  fn corner(idx: usize) -> IVec4 {
+    IVec4::new(
+      (idx & 1) as i32,
+      (idx >> 1 & 1) as i32,
+      (idx >> 2 & 1) as i32,
+      (idx >> 3 & 1) as i32,
+    )
+  }
+}
+
+impl Noise4 for Value4 {
+  fn sample(&self, p: Vec4) -> f32 {
+    let cell = p.map(f32::floor);
+    let t = (p - cell).map(fade);
+    let cell =
+      IVec4::new(cell.x as i32, cell.y as i32, cell.z as i32, cell.w as i32);
+
+    let mut total = 0.0;
+    for idx in 0..16 {
+      let corner = Self::corner(idx);
+      let mut weight = 1.0;
+      for axis in 0..4 {
+        weight *= if corner[axis] == 1 {
+          t[axis]
+        } else {
+          1.0 - t[axis]
+        };
+      }
+      total += weight * self.lattice(cell + corner);
+    }
+    total
+  }
+
+  fn sample_x8(&self, p: Vec4x8) -> f32x8 {
+    let (cells, frac) = floor_x8(p);
+    let cells =
+      cells.map(|c| IVec4::new(c.x as i32, c.y as i32, c.z as i32, c.w as i32));
+    let t = [frac.x, frac.y, frac.z, frac.w].map(fade_x8);
+    let one = f32x8::splat(1.0);
+
+    let mut total = f32x8::splat(0.0);
+    for idx in 0..16 {
+      let corner = Self::corner(idx);
+      let mut weight = one;
+      for axis in 0..4 {
+        weight *= if corner[axis] == 1 {
+          t[axis]
+        } else {
+          one - t[axis]
+        };
+      }
+      let values = f32x8::new(cells.map(|c| self.lattice(c + corner)));
+      total += weight * values;
+    }
+    total
+  }
+}
@@ -0,0 +1,106 @@
+use itertools::iproduct;
+use ultraviolet::{f32x8, IVec4, Vec4, Vec4x8};
+use wide::CmpLt;
+
+use crate::math::rng::hash_pos;
+
+use super::{floor_x8, Noise4};
+
+/// What [`Worley4`] returns, out of the distances to the nearest
+/// and second-nearest feature points.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WorleyMode {
+  /// Distance to the nearest point. Looks like bubbles.
+  F1,
+  /// Distance to the second-nearest point.
+  F2,
+  /// Looks like the cracks between cells.
+  F2MinusF1,
+}
+
+/// Seeded 4D cellular noise.
+///
+/// Every integer lattice cell has one feature point scattered in it.
+/// Unlike the other noises, this is a distance, so it's never negative;
+/// it's usually under 1, and can't be over 2 unless `jitter` is cranked
+/// past 1.
+#[derive(Debug, Clone)]
+pub struct Worley4 {
+  seed: u64,
+  /// How far feature points can wander from the corner of their cell, from
+  /// 0 (a perfect grid) to 1 (anywhere in the cell).
+  pub jitter: f32,
+  pub mode: WorleyMode,
+}
+
+impl Worley4 {
+  pub fn new(seed: u64, mode: WorleyMode) -> Self {
+    Self {
+      seed,
+      jitter: 1.0,
+      mode,
+    }
+  }
+
+  fn feature_point(&self, cell: IVec4) -> Vec4 {
+    let h = hash_pos(self.seed, cell);
+    let offset = Vec4::new(
+      (h & 0xffff) as f32,
+      (h >> 16 & 0xffff) as f32,
+      (h >> 32 & 0xffff) as f32,
+      (h >> 48 & 0xffff) as f32,
+    ) / 65536.0;
+    Vec4::from(cell) + offset * self.jitter
+  }
+}
+
+impl Noise4 for Worley4 {
+  fn sample(&self, p: Vec4) -> f32 {
+    let cell = p.map(f32::floor);
+    let cell =
+      IVec4::new(cell.x as i32, cell.y as i32, cell.z as i32, cell.w as i32);
+
+    let mut f1 = f32::INFINITY;
+    let mut f2 = f32::INFINITY;
+    for (x, y, z, w) in iproduct!(-1..=1, -1..=1, -1..=1, -1..=1) {
+      let neighbor = cell + IVec4::new(x, y, z, w);
+      let dist_sq = (self.feature_point(neighbor) - p).mag_sq();
+      if dist_sq < f1 {
+        f2 = f1;
+        f1 = dist_sq;
+      } else if dist_sq < f2 {
+        f2 = dist_sq;
+      }
+    }
+
+    match self.mode {
+      WorleyMode::F1 => f1.sqrt(),
+      WorleyMode::F2 => f2.sqrt(),
+      WorleyMode::F2MinusF1 => f2.sqrt() - f1.sqrt(),
+    }
+  }
+
+  fn sample_x8(&self, p: Vec4x8) -> f32x8 {
+    let (cells, _) = floor_x8(p);
+    let cells =
+      cells.map(|c| IVec4::new(c.x as i32, c.y as i32, c.z as i32, c.w as i32));
+
+    let mut f1 = f32x8::splat(f32::INFINITY);
+    let mut f2 = f32x8::splat(f32::INFINITY);
+    for (x, y, z, w) in iproduct!(-1..=1, -1..=1, -1..=1, -1..=1) {
+      let offset = IVec4::new(x, y, z, w);
+      let points = cells.map(|c| self.feature_point(c + offset));
+      let dist_sq = (Vec4x8::from(points) - p).mag_sq();
+      let nearest = dist_sq.cmp_lt(f1);
+      let second = dist_sq.cmp_lt(f2);
+      f2 = nearest.blend(f1, second.blend(dist_sq, f2));
+      f1 = nearest.blend(dist_sq, f1);
+    }
+
+    match self.mode {
+      WorleyMode::F1 => f1.sqrt(),
+      WorleyMode::F2 => f2.sqrt(),
+      WorleyMode::F2MinusF1 => f2.sqrt() - f1.sqrt(),
+    }
+  }
+}
@@ -0,0 +1,58 @@
+//! Tiny deterministic RNG stuff, so world generation is reproducible from
+//! a seed without pulling in a whole crate for it.
+
+use ultraviolet::IVec4;
+
+/// https://prng.di.unimi.it/splitmix64.c
+///
+/// Not remotely cryptographic, but fast, and every seed is a good seed.
+#[derive(Debug, Clone)]
+pub struct SplitMix64(u64);
+
+impl SplitMix64 {
+  pub fn new(seed: u64) -> Self {
+    Self(seed)
+  }
+
+  pub fn next_u64(&mut self) -> u64 {
+    self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+    mix64(self.0)
+  }
+
+  pub fn next_u32(&mut self) -> u32 {
+    (self.next_u64() >> 32) as u32
+  }
+
+  /// In `[0, 1)`
+  pub fn next_f32(&mut self) -> f32 {
+    // 24 bits of mantissa
+    (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+  }
+
+  /// In `[lo, hi)`. Panics if the range is empty.
+  pub fn range_i32(&mut self, lo: i32, hi: i32) -> i32 {
+    assert!(lo < hi, "empty range {}..{}", lo, hi);
+    let span = (hi as i64 - lo as i64) as u64;
+    (lo as i64 + (self.next_u64() % span) as i64) as i32
+  }
+}
+
+/// The splitmix finalizer.
+#[inline]
+pub fn mix64(mut z: u64) -> u64 {
+  z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+  z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+  z ^ (z >> 31)
+}
+
+/// Hash a seed together with a lattice position.
+///
+/// Used anywhere we want "random, but the same every time for this spot."
+#[inline]
+pub fn hash_pos(seed: u64, pos: IVec4) -> u64 {
+  let mut h = mix64(seed);
+  for v in pos.as_array() {
+    h = mix64(h ^ (v as u32 as u64));
+  }
+  h
+}
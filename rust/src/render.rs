@@ -0,0 +1,632 @@
+//! Drawing the world on the CPU, the same way `show_world.gdshader` does,
+//! so it can be looked at without Godot or a GPU.
+
+pub mod golden;
+pub mod view;
+
+use std::{
+  fs::File,
+  io::{self, BufWriter, Read, Write},
+  path::Path,
+  sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+use itertools::iproduct;
+use rayon::prelude::*;
+use ultraviolet::{f32x8, Vec2, Vec3, Vec4, Vec4x8};
+
+use crate::{
+  godot_bridge::{vec4_from_gd, GdPlayerCamera},
+  math::{
+    geo::{Rotor4, Rotor4x8},
+    hexadecitree::{packet::LANES, trace::Trace},
+    BlockPos,
+  },
+  world::World,
+};
+
+/// Where the world gets looked at from. The same as `GdPlayerCamera`, but
+/// without needing Godot around.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Camera {
+  pub pos: Vec4,
+  pub rot: Rotor4,
+  pub fov: f32,
+  pub focal_dist: f32,
+}
+
+impl Camera {
+  pub fn new(pos: Vec4, rot: Rotor4, fov: f32, focal_dist: f32) -> Self {
+    Self {
+      pos,
+      rot,
+      fov,
+      focal_dist,
+    }
+  }
+
+  /// Where the ray through `uv` on a screen `aspect` times wider than it
+  /// is tall starts, and which way it goes. (0, 0) is the top left, like
+  /// `UV` in a Godot shader.
+  ///
+  /// Forward is +Y, up the screen is +X and left is +Z, before the
+  /// rotation.
+  pub fn ray(&self, uv: Vec2, aspect: f32) -> (Vec4, Vec4) {
+    let dir = self.rot * self.unrotated_dir(uv, aspect);
+    (self.pos + dir * self.focal_dist, dir)
+  }
+
+  /// `ray` for eight points on the screen at once.
+  pub fn rays_x8(&self, uvs: [Vec2; LANES], aspect: f32) -> (Vec4x8, Vec4x8) {
+    let unrotated = uvs.map(|uv| self.unrotated_dir(uv, aspect));
+    let dir = Rotor4x8::splat(self.rot) * Vec4x8::from(unrotated);
+    let start = Vec4x8::splat(self.pos) + dir * f32x8::splat(self.focal_dist);
+    (start, dir)
+  }
+
+  fn unrotated_dir(&self, uv: Vec2, aspect: f32) -> Vec4 {
+    let centered = uv - Vec2::broadcast(0.5);
+    let dir2d = centered * self.fov * Vec2::new(aspect, 1.0);
+    Vec4::new(-dir2d.y, 1.0, -dir2d.x, 0.0).normalized()
+  }
+}
+
+impl From<&GdPlayerCamera> for Camera {
+  fn from(cam: &GdPlayerCamera) -> Self {
+    Self::new(vec4_from_gd(cam.pos), cam.rot, cam.fov, cam.focal_dist)
+  }
+}
+
+/// What's behind everything. Has to match `sky` in `show_world.gdshader`.
+pub fn sky(dir: Vec4) -> Vec3 {
+  let squish = dir
+    .abs()
+    .as_array()
+    .map(|v| (8.0 * (v.powi(4) - 1.0) + 1.0).clamp(0.0, 1.0));
+  let x_col = Vec3::new(0.0, 1.0, 1.0) * squish[0];
+  let y_col = Vec3::new(1.0, 0.0, 1.0) * squish[1];
+  let z_col = Vec3::new(1.0, 1.0, 0.0) * squish[2];
+  let w_col = Vec3::new(0.0, 1.0, 0.0) * squish[3];
+  let base = Vec3::new(0.9, 0.9, 0.95);
+  (base - x_col - y_col - z_col - w_col).clamped(Vec3::zero(), Vec3::one())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+  width: u32,
+  height: u32,
+  /// RGBA, row by row from the top.
+  pixels: Vec<[u8; 4]>,
+}
+
+impl Image {
+  pub fn new(width: u32, height: u32) -> Self {
+    Self {
+      width,
+      height,
+      pixels: vec![[0, 0, 0, 255]; width as usize * height as usize],
+    }
+  }
+
+  pub fn width(&self) -> u32 {
+    self.width
+  }
+
+  pub fn height(&self) -> u32 {
+    self.height
+  }
+
+  pub fn get(&self, x: u32, y: u32) -> [u8; 4] {
+    self.pixels[self.idx(x, y)]
+  }
+
+  pub fn set(&mut self, x: u32, y: u32, rgba: [u8; 4]) {
+    let idx = self.idx(x, y);
+    self.pixels[idx] = rgba;
+  }
+
+  pub fn pixels(&self) -> &[[u8; 4]] {
+    &self.pixels
+  }
+
+  pub fn as_bytes(&self) -> &[u8] {
+    bytemuck::cast_slice(&self.pixels)
+  }
+
+  /// Write it out as a binary PPM, which drops the alpha.
+  pub fn write_ppm(&self, mut w: impl Write) -> io::Result<()> {
+    write!(w, "P6\n{} {}\n255\n", self.width, self.height)?;
+    for [r, g, b, _] in &self.pixels {
+      w.write_all(&[*r, *g, *b])?;
+    }
+    w.flush()
+  }
+
+  pub fn save_ppm(&self, path: impl AsRef<Path>) -> io::Result<()> {
+    self.write_ppm(BufWriter::new(File::create(path)?))
+  }
+
+  /// Read a binary PPM like `write_ppm` makes. Alpha comes out as 255.
+  pub fn read_ppm(mut r: impl Read) -> Result<Self, PpmError> {
+    let mut bytes = Vec::new();
+    r.read_to_end(&mut bytes)?;
+
+    // Four things separated by whitespace, then exactly one more
+    // whitespace byte before the pixels
+    let mut fields = Vec::new();
+    let mut at = 0;
+    while fields.len() < 4 {
+      while bytes.get(at).is_some_and(u8::is_ascii_whitespace) {
+        at += 1;
+      }
+      let start = at;
+      while bytes.get(at).is_some_and(|b| !b.is_ascii_whitespace()) {
+        at += 1;
+      }
+      if start == at {
+        return Err(PpmError::Malformed("header ended early"));
+      }
+      fields.push(std::str::from_utf8(&bytes[start..at]).unwrap_or(""));
+    }
+    if fields[0] != "P6" {
+      return Err(PpmError::Malformed("not a binary PPM"));
+    }
+    let (Ok(width), Ok(height)) =
+      (fields[1].parse::<u32>(), fields[2].parse::<u32>())
+    else {
+      return Err(PpmError::Malformed("bad size"));
+    };
+    if fields[3] != "255" {
+      return Err(PpmError::Malformed("not 8 bits per channel"));
+    }
+
+    // Before allocating anything, since the header can claim any size it
+    // likes
+    let data = bytes.get(at + 1..).unwrap_or(&[]);
+    let expected = (width as usize)
+      .checked_mul(height as usize)
+      .and_then(|pixels| pixels.checked_mul(3));
+    if expected != Some(data.len()) {
+      return Err(PpmError::Malformed("wrong amount of pixels"));
+    }
+    let mut image = Self::new(width, height);
+    for (pixel, rgb) in image.pixels.iter_mut().zip(data.chunks_exact(3)) {
+      *pixel = [rgb[0], rgb[1], rgb[2], 255];
+    }
+    Ok(image)
+  }
+
+  pub fn load_ppm(path: impl AsRef<Path>) -> Result<Self, PpmError> {
+    Self::read_ppm(File::open(path)?)
+  }
+
+  /// Compare against what it's meant to look like. Channels can be off by
+  /// up to `tolerance` before a pixel counts as different.
+  ///
+  /// Images of different sizes are different everywhere.
+  pub fn diff(&self, expected: &Image, tolerance: u8) -> ImageDiff {
+    if (self.width, self.height) != (expected.width, expected.height) {
+      return ImageDiff {
+        differing: self.pixels.len().max(expected.pixels.len()),
+        max: 255,
+        image: self.clone(),
+      };
+    }
+
+    let mut out = Image::new(self.width, self.height);
+    let (mut differing, mut max) = (0, 0);
+    for ((got, want), slot) in self
+      .pixels
+      .iter()
+      .zip(&expected.pixels)
+      .zip(&mut out.pixels)
+    {
+      let off = (0..3).map(|c| got[c].abs_diff(want[c])).max().unwrap();
+      max = max.max(off);
+      *slot = if off > tolerance {
+        differing += 1;
+        [255, 0, 255, 255]
+      } else {
+        // Faded out, so the differences stand out
+        let sum = got[0] as u16 + got[1] as u16 + got[2] as u16;
+        let grey = (sum / 6) as u8 + 64;
+        [grey, grey, grey, 255]
+      };
+    }
+    ImageDiff {
+      differing,
+      max,
+      image: out,
+    }
+  }
+
+  fn idx(&self, x: u32, y: u32) -> usize {
+    assert!(
+      x < self.width && y < self.height,
+      "({}, {}) is off the image",
+      x,
+      y
+    );
+    y as usize * self.width as usize + x as usize
+  }
+}
+
+/// How much the sun's light is blocked for whatever's seen through each
+/// pixel, from 0 for none of it to 1 for all of it. Pixels showing the sky
+/// are 0.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShadowMask {
+  width: u32,
+  height: u32,
+  values: Vec<f32>,
+}
+
+impl ShadowMask {
+  pub fn new(width: u32, height: u32) -> Self {
+    Self {
+      width,
+      height,
+      values: vec![0.0; width as usize * height as usize],
+    }
+  }
+
+  pub fn width(&self) -> u32 {
+    self.width
+  }
+
+  pub fn height(&self) -> u32 {
+    self.height
+  }
+
+  pub fn get(&self, x: u32, y: u32) -> f32 {
+    self.values[self.idx(x, y)]
+  }
+
+  pub fn set(&mut self, x: u32, y: u32, shadow: f32) {
+    let idx = self.idx(x, y);
+    self.values[idx] = shadow;
+  }
+
+  pub fn values(&self) -> &[f32] {
+    &self.values
+  }
+
+  /// If at least half the light is blocked.
+  pub fn is_shadowed(&self, x: u32, y: u32) -> bool {
+    self.get(x, y) >= 0.5
+  }
+
+  fn idx(&self, x: u32, y: u32) -> usize {
+    assert!(
+      x < self.width && y < self.height,
+      "({}, {}) is off the mask",
+      x,
+      y
+    );
+    y as usize * self.width as usize + x as usize
+  }
+}
+
+#[derive(Debug)]
+pub enum PpmError {
+  Io(io::Error),
+  /// Says what was wrong with it.
+  Malformed(&'static str),
+}
+
+impl From<io::Error> for PpmError {
+  fn from(e: io::Error) -> Self {
+    PpmError::Io(e)
+  }
+}
+
+/// How two images compare.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageDiff {
+  /// How many pixels were off by more than the tolerance.
+  pub differing: usize,
+  /// The most any channel was off by.
+  pub max: u8,
+  /// Magenta where they differ, and a washed out version of the image
+  /// everywhere else.
+  pub image: Image,
+}
+
+impl ImageDiff {
+  pub fn matches(&self) -> bool {
+    self.differing == 0
+  }
+}
+
+/// Lets other threads watch how far a render's got, and stop it.
+#[derive(Debug, Default)]
+pub struct RenderProgress {
+  tiles_done: AtomicUsize,
+  tiles_total: AtomicUsize,
+  cancelled: AtomicBool,
+}
+
+impl RenderProgress {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn tiles_done(&self) -> usize {
+    self.tiles_done.load(Ordering::Relaxed)
+  }
+
+  /// 0 until the render's started.
+  pub fn tiles_total(&self) -> usize {
+    self.tiles_total.load(Ordering::Relaxed)
+  }
+
+  /// From 0 to 1.
+  pub fn fraction(&self) -> f32 {
+    match self.tiles_total() {
+      0 => 0.0,
+      total => self.tiles_done() as f32 / total as f32,
+    }
+  }
+
+  /// Ask the render to stop. Tiles that are already going still finish.
+  pub fn cancel(&self) {
+    self.cancelled.store(true, Ordering::Relaxed);
+  }
+
+  pub fn is_cancelled(&self) -> bool {
+    self.cancelled.load(Ordering::Relaxed)
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Renderer {
+  pub width: u32,
+  pub height: u32,
+  /// How many steps a ray takes before it gives up and shows the sky.
+  /// A step is a foxel, or a whole brick of air.
+  pub max_steps: usize,
+  /// Send a ray towards the sun from everything that gets hit, and darken
+  /// it if the ray's blocked. On by default.
+  pub shadows: bool,
+  /// How many pixels across the square tiles that get rendered in
+  /// parallel are.
+  pub tile_size: u32,
+}
+
+/// Which pixels a tile covers, as ranges.
+#[derive(Debug, Clone, Copy)]
+struct Tile {
+  x: (u32, u32),
+  y: (u32, u32),
+}
+
+/// The colour and shadow of each pixel in a tile, row by row.
+type TilePixels = Vec<([u8; 4], f32)>;
+
+impl Renderer {
+  pub const DEFAULT_MAX_STEPS: usize = 256;
+  pub const DEFAULT_TILE_SIZE: u32 = 32;
+  /// How much darker something completely in shadow gets.
+  pub const SHADOW_DARKEN: f32 = 0.4;
+
+  pub fn new(width: u32, height: u32) -> Self {
+    Self {
+      width,
+      height,
+      max_steps: Self::DEFAULT_MAX_STEPS,
+      shadows: true,
+      tile_size: Self::DEFAULT_TILE_SIZE,
+    }
+  }
+
+  pub fn aspect(&self) -> f32 {
+    self.width as f32 / self.height as f32
+  }
+
+  /// What `camera` sees of the world on this screen.
+  pub fn view_volume(&self, camera: &Camera) -> view::ViewVolume {
+    camera.view_volume(self.aspect())
+  }
+
+  /// The `UV` of the middle of a pixel.
+  pub fn uv(&self, x: u32, y: u32) -> Vec2 {
+    Vec2::new(
+      (x as f32 + 0.5) / self.width as f32,
+      (y as f32 + 0.5) / self.height as f32,
+    )
+  }
+
+  /// Needs the world mutably to work out AO for bricks it sees.
+  pub fn render(&self, world: &mut World, camera: &Camera) -> Image {
+    self.render_with_shadows(world, camera).0
+  }
+
+  /// `render`, and which pixels are in shadow too. The mask is all 0 if
+  /// `shadows` is off.
+  pub fn render_with_shadows(
+    &self,
+    world: &mut World,
+    camera: &Camera,
+  ) -> (Image, ShadowMask) {
+    self
+      .render_tiles(world, camera, &RenderProgress::new())
+      .expect("nothing else can cancel it")
+  }
+
+  /// `render_with_shadows`, keeping `progress` up to date. Gives back
+  /// `None` if it gets cancelled.
+  ///
+  /// Tiles get rendered in parallel on the rayon pool, each going along
+  /// its rows eight pixels at a time. It comes out exactly the same
+  /// however many threads there are, and the same as doing every `pixel`
+  /// and `shadow` on its own.
+  pub fn render_tiles(
+    &self,
+    world: &mut World,
+    camera: &Camera,
+    progress: &RenderProgress,
+  ) -> Option<(Image, ShadowMask)> {
+    let tiles = self.tiles();
+    progress.tiles_total.store(tiles.len(), Ordering::Relaxed);
+    progress.tiles_done.store(0, Ordering::Relaxed);
+
+    // The AO can't be worked out while the world's shared between
+    // threads, so tiles that needed some that wasn't there yet get
+    // rendered again once it's been done
+    let mut rendered: Vec<Option<TilePixels>> = vec![None; tiles.len()];
+    let mut todo = (0..tiles.len()).collect::<Vec<_>>();
+    while !todo.is_empty() {
+      let shared = &*world;
+      let results = todo
+        .par_iter()
+        .map(|idx| {
+          if progress.is_cancelled() {
+            return None;
+          }
+          let mut missing = Vec::new();
+          let pixels =
+            self.render_tile(shared, camera, tiles[*idx], &mut missing);
+          if missing.is_empty() {
+            progress.tiles_done.fetch_add(1, Ordering::Relaxed);
+          }
+          Some((*idx, pixels, missing))
+        })
+        .collect::<Option<Vec<_>>>()?;
+
+      todo.clear();
+      let mut missing = Vec::new();
+      for (idx, pixels, tile_missing) in results {
+        if tile_missing.is_empty() {
+          rendered[idx] = Some(pixels);
+        } else {
+          todo.push(idx);
+          missing.extend(tile_missing);
+        }
+      }
+      let World { foxels, ao, .. } = world;
+      ao.ensure(foxels, missing);
+    }
+
+    let mut image = Image::new(self.width, self.height);
+    let mut mask = ShadowMask::new(self.width, self.height);
+    for (tile, pixels) in tiles.iter().zip(rendered) {
+      let coords = iproduct!(tile.y.0..tile.y.1, tile.x.0..tile.x.1);
+      for ((y, x), (rgba, shadow)) in coords.zip(pixels?) {
+        image.set(x, y, rgba);
+        mask.set(x, y, shadow);
+      }
+    }
+    Some((image, mask))
+  }
+
+  /// The colour of one pixel, before it gets turned into bytes.
+  pub fn pixel(
+    &self,
+    world: &mut World,
+    camera: &Camera,
+    x: u32,
+    y: u32,
+  ) -> Vec3 {
+    let (start, dir) = camera.ray(self.uv(x, y), self.aspect());
+    let trace = world.trace(start, dir, self.max_steps);
+    let shadow = self.shadow_of(world, start, dir, &trace);
+    shadowed(&trace, shadow, dir)
+  }
+
+  /// How much of the sun's light is blocked for one pixel.
+  pub fn shadow(
+    &self,
+    world: &mut World,
+    camera: &Camera,
+    x: u32,
+    y: u32,
+  ) -> f32 {
+    let (start, dir) = camera.ray(self.uv(x, y), self.aspect());
+    let trace = world.trace(start, dir, self.max_steps);
+    self.shadow_of(world, start, dir, &trace)
+  }
+
+  fn tiles(&self) -> Vec<Tile> {
+    let size = self.tile_size.max(1);
+    let range = |start: u32, end: u32| (start, (start + size).min(end));
+    let ys = (0..self.height).step_by(size as usize);
+    let xs = (0..self.width).step_by(size as usize);
+    iproduct!(ys, xs)
+      .map(|(y, x)| Tile {
+        x: range(x, self.width),
+        y: range(y, self.height),
+      })
+      .collect()
+  }
+
+  fn render_tile(
+    &self,
+    world: &World,
+    camera: &Camera,
+    tile: Tile,
+    missing: &mut Vec<BlockPos>,
+  ) -> TilePixels {
+    let (x_start, x_end) = tile.x;
+    let mut out = Vec::new();
+    for y in tile.y.0..tile.y.1 {
+      for x0 in (x_start..x_end).step_by(LANES) {
+        // Past the end of the row, just do the last pixel again
+        let xs: [u32; LANES] =
+          std::array::from_fn(|lane| (x0 + lane as u32).min(x_end - 1));
+        let (start, dir) =
+          camera.rays_x8(xs.map(|x| self.uv(x, y)), self.aspect());
+        let traces = world.trace_x8_cached(start, dir, self.max_steps, missing);
+        let starts: [Vec4; LANES] = start.into();
+        let dirs: [Vec4; LANES] = dir.into();
+        let mut shadows = [0.0; LANES];
+        if self.shadows {
+          let hits = std::array::from_fn(|lane| {
+            hit_point(starts[lane], dirs[lane], &traces[lane])
+          });
+          shadows = world.shadow_x8(hits, self.max_steps);
+        }
+        for lane in 0..LANES.min((x_end - x0) as usize) {
+          let colour = shadowed(&traces[lane], shadows[lane], dirs[lane]);
+          out.push((to_rgba(colour), shadows[lane]));
+        }
+      }
+    }
+    out
+  }
+
+  fn shadow_of(
+    &self,
+    world: &World,
+    start: Vec4,
+    dir: Vec4,
+    trace: &Trace,
+  ) -> f32 {
+    match hit_point(start, dir, trace) {
+      Some((point, normal)) if self.shadows => {
+        world.shadow(point, normal, self.max_steps)
+      }
+      _ => 0.0,
+    }
+  }
+}
+
+/// Where on which face the ray stopped, if it hit anything.
+fn hit_point(start: Vec4, dir: Vec4, trace: &Trace) -> Option<(Vec4, Vec4)> {
+  trace.hit.map(|hit| (start + dir * hit.dist, hit.normal))
+}
+
+fn shadowed(trace: &Trace, shadow: f32, dir: Vec4) -> Vec3 {
+  let darken = 1.0 - shadow * Renderer::SHADOW_DARKEN;
+  let trace = Trace {
+    colour: trace.colour * darken,
+    ..*trace
+  };
+  trace.over(sky(dir))
+}
+
+pub fn to_rgba(colour: Vec3) -> [u8; 4] {
+  let [r, g, b] = colour
+    .as_array()
+    .map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8);
+  [r, g, b, 255]
+}
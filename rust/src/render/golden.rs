@@ -0,0 +1,155 @@
+//! Checking renders of scenes against reference images that are checked
+//! in, so changes to how things look (or to the rotor maths, or the
+//! traversal) don't sneak through.
+//!
+//! Set `TESSERACTORY_UPDATE_GOLDEN` to write out new references instead of
+//! checking against the old ones. `just update-golden` does that.
+
+use std::{env, fs, io, path::PathBuf};
+
+use ultraviolet::Vec4;
+
+use crate::world::scenes::{SceneError, SceneRegistry, SceneSpec};
+
+use super::{Camera, Image, PpmError, Renderer};
+
+/// A scene, looked at from somewhere in particular.
+#[derive(Debug, Clone)]
+pub struct GoldenCase {
+  /// What the reference image is called.
+  pub name: String,
+  /// Like `--scene`, so `terrain:1234` works.
+  pub scene: String,
+  pub camera: Camera,
+  pub sun_dir: Vec4,
+  pub width: u32,
+  pub height: u32,
+}
+
+impl GoldenCase {
+  pub const DEFAULT_WIDTH: u32 = 64;
+  pub const DEFAULT_HEIGHT: u32 = 48;
+
+  /// Small, and lit the same way the game is.
+  pub fn new(
+    name: impl Into<String>,
+    scene: impl Into<String>,
+    camera: Camera,
+  ) -> Self {
+    Self {
+      name: name.into(),
+      scene: scene.into(),
+      camera,
+      sun_dir: Vec4::new(-0.5, 0.4, 0.2, 0.1).normalized(),
+      width: Self::DEFAULT_WIDTH,
+      height: Self::DEFAULT_HEIGHT,
+    }
+  }
+
+  pub fn render(&self, scenes: &SceneRegistry) -> Result<Image, SceneError> {
+    let spec = self.scene.parse::<SceneSpec>()?;
+    let mut world = scenes.build(&spec, self.sun_dir)?;
+    let renderer = Renderer::new(self.width, self.height);
+    Ok(renderer.render(&mut world, &self.camera))
+  }
+}
+
+pub struct Golden {
+  /// Where the reference images live.
+  pub dir: PathBuf,
+  /// Where what actually got rendered, and the diff, go when they don't
+  /// match.
+  pub out_dir: PathBuf,
+  /// How far off a channel can be before a pixel counts as different.
+  pub tolerance: u8,
+  /// Write new references instead of checking against them.
+  pub update: bool,
+}
+
+#[derive(Debug)]
+pub enum GoldenError {
+  /// There's no reference image to check against yet.
+  Missing(PathBuf),
+  Mismatch {
+    differing: usize,
+    max: u8,
+    actual: PathBuf,
+    diff: PathBuf,
+  },
+  Scene(SceneError),
+  Ppm(PpmError),
+  Io(io::Error),
+}
+
+impl From<io::Error> for GoldenError {
+  fn from(e: io::Error) -> Self {
+    GoldenError::Io(e)
+  }
+}
+
+impl Golden {
+  pub const UPDATE_VAR: &'static str = "TESSERACTORY_UPDATE_GOLDEN";
+  pub const DEFAULT_TOLERANCE: u8 = 2;
+
+  /// Updates instead of checking if `UPDATE_VAR` is set.
+  pub fn new(dir: impl Into<PathBuf>, out_dir: impl Into<PathBuf>) -> Self {
+    Self {
+      dir: dir.into(),
+      out_dir: out_dir.into(),
+      tolerance: Self::DEFAULT_TOLERANCE,
+      update: env::var_os(Self::UPDATE_VAR).is_some(),
+    }
+  }
+
+  pub fn reference_path(&self, name: &str) -> PathBuf {
+    self.dir.join(format!("{}.ppm", name))
+  }
+
+  /// Check `image` against the reference called `name`, or replace the
+  /// reference with it if updating.
+  pub fn check(&self, name: &str, image: &Image) -> Result<(), GoldenError> {
+    let reference = self.reference_path(name);
+    if self.update {
+      fs::create_dir_all(&self.dir)?;
+      return image.save_ppm(&reference).map_err(GoldenError::Io);
+    }
+
+    let expected = match Image::load_ppm(&reference) {
+      Ok(it) => it,
+      Err(PpmError::Io(e)) if e.kind() == io::ErrorKind::NotFound => {
+        return Err(GoldenError::Missing(reference))
+      }
+      Err(ono) => return Err(GoldenError::Ppm(ono)),
+    };
+    let diff = image.diff(&expected, self.tolerance);
+    if diff.matches() {
+      return Ok(());
+    }
+
+    fs::create_dir_all(&self.out_dir)?;
+    let actual = self.out_path(name, "actual");
+    let diff_path = self.out_path(name, "diff");
+    image.save_ppm(&actual)?;
+    diff.image.save_ppm(&diff_path)?;
+    Err(GoldenError::Mismatch {
+      differing: diff.differing,
+      max: diff.max,
+      actual,
+      diff: diff_path,
+    })
+  }
+
+  /// Render the case and check it.
+  pub fn check_case(
+    &self,
+    scenes: &SceneRegistry,
+    case: &GoldenCase,
+  ) -> Result<(), GoldenError> {
+    let image = case.render(scenes).map_err(GoldenError::Scene)?;
+    self.check(&case.name, &image)
+  }
+
+  fn out_path(&self, name: &str, kind: &str) -> PathBuf {
+    self.out_dir.join(format!("{}.{}.ppm", name, kind))
+  }
+}
@@ -0,0 +1,99 @@
+//! The bit of the world a camera can actually see, for culling bricks
+//! before they get uploaded or drawn.
+//!
+//! The screen only spans the camera's X, Y and Z, so what it sees is a 3D
+//! pyramid lying flat in the hyperplane through the camera where its W is
+//! 0, from `focal_dist` out to forever.
+
+use ultraviolet::Vec4;
+
+use crate::math::{hexadecitree::Hexadecitree, BlockPos};
+
+use super::Camera;
+
+/// How far outside the volume something can be and still count, so rays
+/// that wobble off the hyperplane through rounding don't get lost.
+const SLACK: f32 = 1e-3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ViewVolume {
+  pos: Vec4,
+  /// Unit normals pointing into the volume, and how far along each one the
+  /// edge is from the camera.
+  planes: [(Vec4, f32); 7],
+}
+
+impl ViewVolume {
+  /// What `camera` sees on a screen `aspect` times wider than it is tall,
+  /// going by the same rays `Camera::ray` makes.
+  pub fn new(camera: &Camera, aspect: f32) -> Self {
+    let [up, forward, left, ana] = [
+      Vec4::unit_x(),
+      Vec4::unit_y(),
+      Vec4::unit_z(),
+      Vec4::unit_w(),
+    ]
+    .map(|axis| camera.rot * axis);
+    // How far off to the side the edges of the screen are, for every bit
+    // forward
+    let (half_height, half_width) =
+      (camera.fov * 0.5, camera.fov * aspect * 0.5);
+    // Rays start `focal_dist` along themselves, and the ones out to the
+    // corners go forward the least
+    let near = camera.focal_dist
+      / (1.0 + half_height * half_height + half_width * half_width).sqrt();
+
+    let side = |normal: Vec4| (normal.normalized(), 0.0);
+    Self {
+      pos: camera.pos,
+      planes: [
+        (forward, near),
+        side(forward * half_height - up),
+        side(forward * half_height + up),
+        side(forward * half_width - left),
+        side(forward * half_width + left),
+        (ana, 0.0),
+        (-ana, 0.0),
+      ],
+    }
+  }
+
+  pub fn contains(&self, point: Vec4) -> bool {
+    let offset = point - self.pos;
+    (self.planes.iter())
+      .all(|(normal, dist)| normal.dot(offset) >= dist - SLACK)
+  }
+
+  /// Whether any of the box between `min` and `max` might be seen.
+  ///
+  /// Only checks the box against each side of the volume one at a time, so
+  /// a box out past a corner can get let through. It never says no to one
+  /// that can be seen, though.
+  pub fn intersects_box(&self, min: Vec4, max: Vec4) -> bool {
+    self.planes.iter().all(|(normal, dist)| {
+      // The corner furthest into the volume
+      let corner = Vec4::from(std::array::from_fn::<f32, 4, _>(|axis| {
+        if normal[axis] > 0.0 {
+          max[axis]
+        } else {
+          min[axis]
+        }
+      }));
+      normal.dot(corner - self.pos) >= dist - SLACK
+    })
+  }
+
+  /// `intersects_box` for the whole brick with its smallest corner at
+  /// `corner`.
+  pub fn intersects_brick(&self, corner: BlockPos) -> bool {
+    let min = Vec4::from(corner.0);
+    let fab = Hexadecitree::FOXELS_ACROSS_BRICK as f32;
+    self.intersects_box(min, min + Vec4::broadcast(fab))
+  }
+}
+
+impl Camera {
+  pub fn view_volume(&self, aspect: f32) -> ViewVolume {
+    ViewVolume::new(self, aspect)
+  }
+}
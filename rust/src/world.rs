@@ -1,32 +1,216 @@
+pub mod ao;
+pub mod entity;
 pub mod foxel;
+pub mod gen;
+pub mod light;
+pub mod pick;
+pub mod save;
+pub mod scenes;
+pub mod sim;
 
-use ultraviolet::Vec4;
+use ultraviolet::{Vec4, Vec4x8};
 
-use crate::math::{hexadecitree::Hexadecitree, BlockPos};
+use crate::math::{
+  hexadecitree::{
+    packet::LANES,
+    trace::{face_light, Trace},
+    Hexadecitree, SetFoxelError,
+  },
+  BlockPos,
+};
 
-use self::foxel::Foxel;
+use self::{
+  ao::{AoCache, FaceAo},
+  entity::EntityStore,
+  foxel::Foxel,
+  gen::{
+    CaveGen, CaveParams, DecorationParams, Decorator, TerrainGen, TerrainParams,
+  },
+  light::LightMap,
+  sim::{Automaton, FluidSim, GranularSim},
+};
 
 pub struct World {
   pub foxels: Hexadecitree,
+  pub entities: EntityStore,
+  pub light: LightMap,
+  pub ao: AoCache,
+  pub fluids: FluidSim,
+  pub granular: GranularSim,
+  /// Stepped every tick, if there is one.
+  pub automaton: Option<Automaton>,
   pub sun_dir: Vec4,
 }
 
 impl World {
   pub fn new(sun_dir: Vec4) -> World {
-    let foxels = Hexadecitree::new();
-    Self { foxels, sun_dir }
-  }
-
-  pub fn setup_sample_scene(&mut self) {
-    let f = &mut self.foxels;
-    f.set(BlockPos::new(0, 0, 0, 0), Foxel::White).unwrap();
-    for v in 1..10 {
-      f.set(BlockPos::new(v, 0, 0, 0), Foxel::Red).unwrap();
-      f.set(BlockPos::new(0, v, 0, 0), Foxel::Green).unwrap();
-      f.set(BlockPos::new(0, 0, v, 0), Foxel::Blue).unwrap();
-      f.set(BlockPos::new(0, 0, 0, v), Foxel::RB).unwrap();
+    let mut foxels = Hexadecitree::new();
+    foxels.track_changes(true);
+    Self {
+      foxels,
+      entities: EntityStore::new(),
+      light: LightMap::new(),
+      ao: AoCache::new(),
+      fluids: FluidSim::new(),
+      granular: GranularSim::new(),
+      automaton: None,
+      sun_dir,
     }
+  }
+
+  /// Run the simulations for one fixed tick.
+  pub fn tick(&mut self) {
+    self.wake_changed();
+    if let Err(ono) = self.fluids.step(&mut self.foxels) {
+      log::warn!("some fluid couldn't flow: {:?}", ono);
+    }
+    if let Err(ono) = self.granular.step(&mut self.foxels) {
+      log::warn!("some grains couldn't fall: {:?}", ono);
+    }
+    if let Some(ca) = self.automaton.as_ref() {
+      if let Err(ono) = ca.step(&mut self.foxels) {
+        log::error!("automaton step failed, stopping it: {:?}", ono);
+        self.automaton = None;
+      }
+    }
+    // So the light and AO are right for drawing straight away
+    self.wake_changed();
+  }
+
+  /// Let the entities, light and simulations know about everything that's
+  /// changed in the tree since last time.
+  pub fn wake_changed(&mut self) {
+    for (pos, old) in self.foxels.take_changes() {
+      if let Some(new) = self.foxels.get(pos) {
+        self.entities.foxel_changed(pos, old, new);
+      }
+      self.light.foxel_changed(&self.foxels, pos);
+      self.ao.foxel_changed(pos);
+      self.fluids.wake(pos);
+      self.granular.wake(pos);
+    }
+  }
+
+  /// Place or remove a foxel, making or destroying its entity to match,
+  /// and updating the light and simulations straight away.
+  ///
+  /// Setting foxels on the tree directly does all that too, but only on
+  /// the next tick.
+  pub fn set_foxel(
+    &mut self,
+    pos: BlockPos,
+    foxel: Foxel,
+  ) -> Result<Foxel, SetFoxelError> {
+    let old = self.foxels.set(pos, foxel)?;
+    self.wake_changed();
+    Ok(old)
+  }
+
+  /// Like `Hexadecitree::trace`, with the baked AO darkening faces.
+  pub fn trace(&mut self, start: Vec4, dir: Vec4, max_steps: usize) -> Trace {
+    let Self { foxels, ao, .. } = self;
+    foxels.trace_shaded(start, dir, max_steps, |pos, normal| {
+      face_light(normal) * ao.shade(foxels, pos, normal)
+    })
+  }
+
+  /// `trace`, but eight at once.
+  pub fn trace_x8(
+    &mut self,
+    start: Vec4x8,
+    dir: Vec4x8,
+    max_steps: usize,
+  ) -> [Trace; LANES] {
+    let Self { foxels, ao, .. } = self;
+    foxels.trace_shaded_x8(start, dir, max_steps, |pos, normal| {
+      face_light(normal) * ao.shade(foxels, pos, normal)
+    })
+  }
+
+  /// `trace_x8` without needing the world mutably. It only uses AO that's
+  /// already been worked out, and adds the positions of any faces it
+  /// didn't have AO for to `missing`. They come out unoccluded.
+  pub fn trace_x8_cached(
+    &self,
+    start: Vec4x8,
+    dir: Vec4x8,
+    max_steps: usize,
+    missing: &mut Vec<BlockPos>,
+  ) -> [Trace; LANES] {
+    self
+      .foxels
+      .trace_shaded_x8(start, dir, max_steps, |pos, normal| {
+        let faces = self.ao.get(pos).unwrap_or_else(|| {
+          missing.push(pos);
+          FaceAo::default()
+        });
+        face_light(normal) * faces.shade(normal)
+      })
+  }
+
+  /// How much of the sun's light doesn't get to `point`, on a face
+  /// pointing along `normal`. 0 is out in the open and 1 is completely
+  /// blocked, with translucent foxels in between. There's no shadow at
+  /// all if `sun_dir` is zero.
+  ///
+  /// Faces pointing away from the sun aren't counted as shadowed, since
+  /// `face_light` already darkens them.
+  pub fn shadow(&self, point: Vec4, normal: Vec4, max_steps: usize) -> f32 {
+    match self.shadow_ray(point, normal) {
+      Some((start, dir)) => self.foxels.trace(start, dir, max_steps).opacity,
+      None => 0.0,
+    }
+  }
+
+  /// `shadow`, but eight at once. Lanes that are `None` get 0.
+  pub fn shadow_x8(
+    &self,
+    points: [Option<(Vec4, Vec4)>; LANES],
+    max_steps: usize,
+  ) -> [f32; LANES] {
+    let rays = points.map(|p| p.and_then(|(p, n)| self.shadow_ray(p, n)));
+    // A NaN direction keeps the lane from doing anything
+    let starts = rays.map(|r| r.map_or(Vec4::zero(), |r| r.0));
+    let dirs = rays.map(|r| r.map_or(Vec4::broadcast(f32::NAN), |r| r.1));
+    let traces =
+      self
+        .foxels
+        .trace_x8(Vec4x8::from(starts), Vec4x8::from(dirs), max_steps);
+    std::array::from_fn(|lane| match rays[lane] {
+      Some(_) => traces[lane].opacity,
+      None => 0.0,
+    })
+  }
+
+  /// Starts just off the face, so it doesn't hit the foxel it's leaving.
+  /// There isn't one for faces pointing away from the sun.
+  fn shadow_ray(&self, point: Vec4, normal: Vec4) -> Option<(Vec4, Vec4)> {
+    const BIAS: f32 = 1e-3;
+    let to_sun = -self.sun_dir;
+    (to_sun.mag_sq() > 0.0 && normal.dot(to_sun) > 0.0)
+      .then(|| (point + normal * BIAS, to_sun.normalized()))
+  }
+
+  pub fn generate_terrain(
+    &mut self,
+    params: TerrainParams,
+  ) -> Result<(), SetFoxelError> {
+    TerrainGen::new(params).generate(&mut self.foxels)
+  }
+
+  pub fn carve_caves(
+    &mut self,
+    params: CaveParams,
+  ) -> Result<(), SetFoxelError> {
+    CaveGen::new(params).carve(&mut self.foxels)
+  }
 
-    f.set(BlockPos::new(3, 3, 3, 0), Foxel::GB).unwrap();
+  /// Scatter trees and such over ground made with `terrain`.
+  pub fn decorate(
+    &mut self,
+    params: DecorationParams,
+    terrain: TerrainParams,
+  ) -> Result<(), SetFoxelError> {
+    Decorator::new(params, TerrainGen::new(terrain)).decorate(&mut self.foxels)
   }
 }
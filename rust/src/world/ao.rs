@@ -0,0 +1,261 @@
+//! Ambient occlusion baked onto foxel faces.
+//!
+//! A face gets darker the more opaque foxels there are around the foxel in
+//! front of it. Looking out along the face's normal, that's the 26 foxels
+//! surrounding it in the 3D slice across the normal.
+//!
+//! It's worked out a brick at a time the first time it's asked for, and
+//! thrown away when anything close enough to the brick changes.
+
+use ahash::AHashMap;
+use bytemuck::{Pod, Zeroable};
+use itertools::{iproduct, Itertools};
+use rayon::prelude::*;
+use ultraviolet::{IVec4, Vec4};
+
+use crate::math::{
+  hexadecitree::{
+    reprs::{Brick, BrickRef},
+    Hexadecitree,
+  },
+  BlockPos,
+};
+
+use super::foxel::{Foxel, FoxelRegistry};
+
+const FAB: i32 = Hexadecitree::FOXELS_ACROSS_BRICK as i32;
+const PER_BRICK: usize = Hexadecitree::FOXELS_PER_BRICK as usize;
+/// A brick plus one foxel all the way round.
+const PADDED: i32 = FAB + 2;
+
+/// How occluded each of a foxel's 8 faces is, 2 bits each.
+///
+/// Face `2 * axis` points along -axis and `2 * axis + 1` along +axis.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Pod, Zeroable)]
+#[repr(transparent)]
+pub struct FaceAo(u16);
+
+impl FaceAo {
+  pub const MAX_LEVEL: u8 = 3;
+  /// How much each level darkens a face by. Has to match `H_faceAo` in
+  /// the shader.
+  pub const DARKEN_PER_LEVEL: f32 = 0.15;
+
+  pub fn face(axis: usize, positive: bool) -> usize {
+    axis * 2 + positive as usize
+  }
+
+  /// The face pointing (mostly) along `normal`.
+  pub fn face_of(normal: Vec4) -> usize {
+    let axis = (0..4)
+      .max_by(|a, b| normal[*a].abs().total_cmp(&normal[*b].abs()))
+      .unwrap();
+    Self::face(axis, normal[axis] > 0.0)
+  }
+
+  pub fn level(self, face: usize) -> u8 {
+    (self.0 >> (face * 2)) as u8 & Self::MAX_LEVEL
+  }
+
+  pub fn with_level(self, face: usize, level: u8) -> Self {
+    let shift = face * 2;
+    let level = level.min(Self::MAX_LEVEL) as u16;
+    Self(self.0 & !(0b11 << shift) | level << shift)
+  }
+
+  /// What to multiply the colour of the face along `normal` by.
+  pub fn shade(self, normal: Vec4) -> f32 {
+    1.0 - self.level(Self::face_of(normal)) as f32 * Self::DARKEN_PER_LEVEL
+  }
+
+  pub fn to_bits(self) -> u16 {
+    self.0
+  }
+}
+
+pub struct AoCache {
+  /// `None` if the brick's been worked out and nothing in it is occluded.
+  bricks: AHashMap<BlockPos, Option<Box<[FaceAo; PER_BRICK]>>>,
+  opaque: Box<[bool; 256]>,
+}
+
+impl AoCache {
+  /// An empty cache, with opacities from the builtin registry.
+  pub fn new() -> Self {
+    Self::with_registry(FoxelRegistry::builtin())
+  }
+
+  pub fn with_registry(reg: &FoxelRegistry) -> Self {
+    let mut opaque = Box::new([false; 256]);
+    for (id, slot) in opaque.iter_mut().enumerate() {
+      *slot = reg.get(Foxel::from_id(id as u8)).opacity >= 1.0;
+    }
+    Self {
+      bricks: AHashMap::new(),
+      opaque,
+    }
+  }
+
+  /// How many bricks have been worked out.
+  pub fn cached_bricks(&self) -> usize {
+    self.bricks.len()
+  }
+
+  /// The occlusion at `pos`, if its brick has been worked out already.
+  pub fn get(&self, pos: BlockPos) -> Option<FaceAo> {
+    let corner = Hexadecitree::brick_corner(pos);
+    let brick = self.bricks.get(&corner)?;
+    Some(match brick {
+      Some(faces) => faces[Brick::offset_to_idx(pos.0 - corner.0)],
+      None => FaceAo::default(),
+    })
+  }
+
+  /// The occlusion for the brick containing `pos`, laid out like the brick
+  /// itself. `None` if nothing in it is occluded.
+  pub fn brick(
+    &mut self,
+    tree: &Hexadecitree,
+    pos: BlockPos,
+  ) -> Option<&[FaceAo; PER_BRICK]> {
+    let corner = Hexadecitree::brick_corner(pos);
+    if !self.bricks.contains_key(&corner) {
+      let faces = self.work_out(tree, corner);
+      self.bricks.insert(corner, faces);
+    }
+    self.bricks[&corner].as_deref()
+  }
+
+  /// Work out all the bricks containing `positions` that aren't already,
+  /// in parallel.
+  pub fn ensure(
+    &mut self,
+    tree: &Hexadecitree,
+    positions: impl IntoIterator<Item = BlockPos>,
+  ) {
+    let missing = positions
+      .into_iter()
+      .map(Hexadecitree::brick_corner)
+      .filter(|corner| !self.bricks.contains_key(corner))
+      .unique()
+      .collect::<Vec<_>>();
+    let this = &*self;
+    let done = missing
+      .into_par_iter()
+      .map(|corner| (corner, this.work_out(tree, corner)))
+      .collect::<Vec<_>>();
+    self.bricks.extend(done);
+  }
+
+  /// What to multiply the colour of the face of `pos` along `normal` by.
+  pub fn shade(
+    &mut self,
+    tree: &Hexadecitree,
+    pos: BlockPos,
+    normal: Vec4,
+  ) -> f32 {
+    let offset = pos.0 - Hexadecitree::brick_corner(pos).0;
+    self
+      .brick(tree, pos)
+      .map_or(FaceAo::default(), |faces| {
+        faces[Brick::offset_to_idx(offset)]
+      })
+      .shade(normal)
+  }
+
+  /// Forget every brick the change at `pos` could have made a difference
+  /// to.
+  pub fn foxel_changed(&mut self, pos: BlockPos) {
+    self.box_changed(pos, pos);
+  }
+
+  /// Forget every brick anything between the two corners (inclusive) could
+  /// have made a difference to.
+  pub fn box_changed(&mut self, a: BlockPos, b: BlockPos) {
+    let lo = Hexadecitree::brick_corner(BlockPos(
+      a.0.min_by_component(b.0) - IVec4::one(),
+    ));
+    let hi = Hexadecitree::brick_corner(BlockPos(
+      a.0.max_by_component(b.0) + IVec4::one(),
+    ));
+    let range = |axis: usize| (lo[axis]..=hi[axis]).step_by(FAB as usize);
+    for (x, y, z, w) in iproduct!(range(0), range(1), range(2), range(3)) {
+      self.bricks.remove(&BlockPos::new(x, y, z, w));
+    }
+  }
+
+  pub fn clear(&mut self) {
+    self.bricks.clear();
+  }
+
+  fn work_out(
+    &self,
+    tree: &Hexadecitree,
+    corner: BlockPos,
+  ) -> Option<Box<[FaceAo; PER_BRICK]>> {
+    let brick = match tree.brick_at(corner)? {
+      BrickRef::Solid(Foxel::AIR) => return None,
+      other => other.to_brick(),
+    };
+
+    let mut opaque = vec![false; PADDED.pow(4) as usize];
+    for (x, y, z, w) in iproduct!(-1..=FAB, -1..=FAB, -1..=FAB, -1..=FAB) {
+      let offset = IVec4::new(x, y, z, w);
+      opaque[padded_idx(offset)] = tree
+        .get(BlockPos(corner.0 + offset))
+        .is_some_and(|f| self.opaque[f.id() as usize]);
+    }
+
+    let mut faces = Box::new([FaceAo::default(); PER_BRICK]);
+    let mut any = false;
+    for (idx, out) in faces.iter_mut().enumerate() {
+      let offset = Brick::idx_to_offset(idx);
+      if brick.get(offset) == Foxel::AIR {
+        continue;
+      }
+      for (axis, positive) in iproduct!(0..4, [false, true]) {
+        let mut front = offset;
+        front[axis] += if positive { 1 } else { -1 };
+        if opaque[padded_idx(front)] {
+          continue;
+        }
+        let level = occlusion_level(&opaque, front, axis);
+        *out = out.with_level(FaceAo::face(axis, positive), level);
+      }
+      any |= *out != FaceAo::default();
+    }
+    any.then_some(faces)
+  }
+}
+
+impl Default for AoCache {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+fn padded_idx(offset: IVec4) -> usize {
+  let [x, y, z, w] = (offset + IVec4::one()).as_array();
+  (((x * PADDED + y) * PADDED + z) * PADDED + w) as usize
+}
+
+/// Count up the opaque foxels around `front` across `axis`. Tucked into
+/// the corner of three walls is as dark as it gets.
+fn occlusion_level(opaque: &[bool], front: IVec4, axis: usize) -> u8 {
+  let mut others = [0; 3];
+  for (slot, other) in others.iter_mut().zip((0..4).filter(|a| *a != axis)) {
+    *slot = other;
+  }
+  let mut count = 0;
+  for (a, b, c) in iproduct!(-1..=1, -1..=1, -1..=1) {
+    if (a, b, c) == (0, 0, 0) {
+      continue;
+    }
+    let mut pos = front;
+    pos[others[0]] += a;
+    pos[others[1]] += b;
+    pos[others[2]] += c;
+    count += opaque[padded_idx(pos)] as u32;
+  }
+  (count * 3 / 19).min(FaceAo::MAX_LEVEL as u32) as u8
+}
@@ -0,0 +1,388 @@
+//! Extra data for foxels that need more than a `FoxelState`, like
+//! containers and signs.
+//!
+//! A foxel entity is a bag of typed components, stored in the world by
+//! the position of the foxel that owns it. Which foxels get one is decided
+//! by their tags; see `EntityStore::register_kind`.
+
+use std::{
+  any::{Any, TypeId},
+  fmt,
+  io::{self, Read, Write},
+};
+
+use ahash::AHashMap;
+use log::warn;
+
+use crate::math::{hexadecitree::Hexadecitree, BlockPos};
+
+use super::{
+  foxel::Foxel,
+  save::{
+    read_i32, read_u16, read_u32, write_i32, write_u16, write_u32, LoadError,
+  },
+};
+
+/// Something that can be attached to a foxel entity.
+pub trait Component: Any + Send + Sync + fmt::Debug {
+  /// Written into saves to find the component again, so don't change it.
+  const NAME: &'static str;
+
+  fn save(&self, out: &mut Vec<u8>);
+  /// Return `None` if the bytes don't make sense.
+  fn load(bytes: &[u8]) -> Option<Self>
+  where
+    Self: Sized;
+}
+
+/// Object-safe half of `Component`.
+trait ErasedComponent: Any + Send + Sync + fmt::Debug {
+  fn name(&self) -> &'static str;
+  fn save(&self, out: &mut Vec<u8>);
+  fn as_any(&self) -> &dyn Any;
+  fn as_any_mut(&mut self) -> &mut dyn Any;
+  fn into_any(self: Box<Self>) -> Box<dyn Any>;
+}
+
+impl<T: Component> ErasedComponent for T {
+  fn name(&self) -> &'static str {
+    T::NAME
+  }
+
+  fn save(&self, out: &mut Vec<u8>) {
+    Component::save(self, out)
+  }
+
+  fn as_any(&self) -> &dyn Any {
+    self
+  }
+
+  fn as_any_mut(&mut self) -> &mut dyn Any {
+    self
+  }
+
+  fn into_any(self: Box<Self>) -> Box<dyn Any> {
+    self
+  }
+}
+
+#[derive(Debug, Default)]
+pub struct FoxelEntity {
+  /// There's only ever a handful, so a list is faster than a map.
+  components: Vec<Box<dyn ErasedComponent>>,
+}
+
+impl FoxelEntity {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn with<T: Component>(mut self, component: T) -> Self {
+    self.insert(component);
+    self
+  }
+
+  /// Return the component of the same type that was already there.
+  pub fn insert<T: Component>(&mut self, component: T) -> Option<T> {
+    let old = self.remove::<T>();
+    self.components.push(Box::new(component));
+    old
+  }
+
+  pub fn remove<T: Component>(&mut self) -> Option<T> {
+    let idx = self.position::<T>()?;
+    let boxed = self.components.remove(idx).into_any();
+    boxed.downcast().ok().map(|b| *b)
+  }
+
+  pub fn get<T: Component>(&self) -> Option<&T> {
+    let idx = self.position::<T>()?;
+    self.components[idx].as_any().downcast_ref()
+  }
+
+  pub fn get_mut<T: Component>(&mut self) -> Option<&mut T> {
+    let idx = self.position::<T>()?;
+    self.components[idx].as_any_mut().downcast_mut()
+  }
+
+  pub fn has<T: Component>(&self) -> bool {
+    self.position::<T>().is_some()
+  }
+
+  pub fn component_count(&self) -> usize {
+    self.components.len()
+  }
+
+  fn position<T: Component>(&self) -> Option<usize> {
+    self
+      .components
+      .iter()
+      .position(|c| c.as_any().type_id() == TypeId::of::<T>())
+  }
+}
+
+type LoadFn = fn(&[u8]) -> Option<Box<dyn ErasedComponent>>;
+
+/// All the foxel entities in a world.
+pub struct EntityStore {
+  /// Grouped by the corner of the brick they're in, to make region
+  /// queries quick.
+  bricks: AHashMap<BlockPos, AHashMap<BlockPos, FoxelEntity>>,
+  loaders: AHashMap<&'static str, LoadFn>,
+  /// Foxels with the tag get an entity from the function when placed.
+  kinds: Vec<(String, fn() -> FoxelEntity)>,
+}
+
+impl EntityStore {
+  /// A store that knows about the builtin components and kinds.
+  pub fn new() -> Self {
+    let mut store = Self {
+      bricks: AHashMap::new(),
+      loaders: AHashMap::new(),
+      kinds: Vec::new(),
+    };
+    store.register_component::<Inventory>();
+    store.register_component::<SignText>();
+    store.register_kind("container", || {
+      FoxelEntity::new().with(Inventory::default())
+    });
+    store
+      .register_kind("sign", || FoxelEntity::new().with(SignText::default()));
+    store
+  }
+
+  /// Let components of this type be loaded from saves.
+  pub fn register_component<T: Component>(&mut self) {
+    self.loaders.insert(T::NAME, |bytes| {
+      T::load(bytes).map(|c| Box::new(c) as Box<dyn ErasedComponent>)
+    });
+  }
+
+  /// Placing a foxel with the tag makes an entity with `make`.
+  pub fn register_kind(&mut self, tag: &str, make: fn() -> FoxelEntity) {
+    self.kinds.retain(|(t, _)| t != tag);
+    self.kinds.push((tag.to_owned(), make));
+  }
+
+  /// Keep entities in line with a foxel changing from `old` to `new`.
+  ///
+  /// The old entity goes away, and the new foxel gets a fresh one if its
+  /// type wants one.
+  pub fn foxel_changed(&mut self, pos: BlockPos, old: Foxel, new: Foxel) {
+    if old == new {
+      return;
+    }
+    self.remove(pos);
+    let ty = new.ty();
+    let make = self.kinds.iter().find(|(tag, _)| ty.has_tag(tag));
+    if let Some((_, make)) = make {
+      let entity = make();
+      self.insert(pos, entity);
+    }
+  }
+
+  pub fn get(&self, pos: BlockPos) -> Option<&FoxelEntity> {
+    self.bricks.get(&Hexadecitree::brick_corner(pos))?.get(&pos)
+  }
+
+  pub fn get_mut(&mut self, pos: BlockPos) -> Option<&mut FoxelEntity> {
+    (self.bricks.get_mut(&Hexadecitree::brick_corner(pos))?).get_mut(&pos)
+  }
+
+  pub fn component<T: Component>(&self, pos: BlockPos) -> Option<&T> {
+    self.get(pos)?.get()
+  }
+
+  pub fn component_mut<T: Component>(
+    &mut self,
+    pos: BlockPos,
+  ) -> Option<&mut T> {
+    self.get_mut(pos)?.get_mut()
+  }
+
+  /// Return the entity that was already there.
+  pub fn insert(
+    &mut self,
+    pos: BlockPos,
+    entity: FoxelEntity,
+  ) -> Option<FoxelEntity> {
+    let brick = self.bricks.entry(Hexadecitree::brick_corner(pos));
+    brick.or_default().insert(pos, entity)
+  }
+
+  pub fn remove(&mut self, pos: BlockPos) -> Option<FoxelEntity> {
+    let corner = Hexadecitree::brick_corner(pos);
+    let brick = self.bricks.get_mut(&corner)?;
+    let removed = brick.remove(&pos);
+    if brick.is_empty() {
+      self.bricks.remove(&corner);
+    }
+    removed
+  }
+
+  pub fn len(&self) -> usize {
+    self.bricks.values().map(|b| b.len()).sum()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.bricks.is_empty()
+  }
+
+  /// Every entity, in no particular order.
+  pub fn iter(&self) -> impl Iterator<Item = (BlockPos, &FoxelEntity)> + '_ {
+    self.bricks.values().flatten().map(|(pos, e)| (*pos, e))
+  }
+
+  /// Every entity between the corners, inclusive, in no particular order.
+  pub fn in_region(
+    &self,
+    min: BlockPos,
+    max: BlockPos,
+  ) -> impl Iterator<Item = (BlockPos, &FoxelEntity)> + '_ {
+    let fab = Hexadecitree::FOXELS_ACROSS_BRICK as i32;
+    // Whether anything from `pos` to `pos + reach` is in the region
+    let inside = move |pos: BlockPos, reach: i32| {
+      (pos.as_array().into_iter())
+        .zip(min.as_array().into_iter().zip(max.as_array()))
+        .all(|(v, (lo, hi))| lo - reach <= v && v <= hi)
+    };
+    self
+      .bricks
+      .iter()
+      .filter(move |(corner, _)| inside(**corner, fab - 1))
+      .flat_map(|(_, brick)| brick.iter())
+      .filter(move |(pos, _)| inside(**pos, 0))
+      .map(|(pos, e)| (*pos, e))
+  }
+
+  /// Format:
+  /// - a u32 count of entities
+  /// - for each one, its position as 4 i32s, then a u16 count of components
+  /// - for each component, a u8 length and its name, then a u32 length and
+  ///   whatever it saved
+  ///
+  /// Entities are sorted by position, so the same store always writes the
+  /// same bytes.
+  pub fn write_to(&self, w: &mut impl Write) -> io::Result<()> {
+    let mut entities = self.iter().collect::<Vec<_>>();
+    entities.sort_unstable_by_key(|(pos, _)| pos.as_array());
+
+    write_u32(w, entities.len() as u32)?;
+    let mut scratch = Vec::new();
+    for (pos, entity) in entities {
+      for v in pos.as_array() {
+        write_i32(w, v)?;
+      }
+      write_u16(w, entity.components.len() as u16)?;
+      for component in entity.components.iter() {
+        let name = component.name();
+        w.write_all(&[name.len() as u8])?;
+        w.write_all(name.as_bytes())?;
+
+        scratch.clear();
+        component.save(&mut scratch);
+        write_u32(w, scratch.len() as u32)?;
+        w.write_all(&scratch)?;
+      }
+    }
+    Ok(())
+  }
+
+  /// Replace everything in the store with what's read. Components this
+  /// store doesn't know about are skipped.
+  pub fn read_from(&mut self, r: &mut impl Read) -> Result<(), LoadError> {
+    self.bricks.clear();
+
+    let count = read_u32(r)?;
+    for _ in 0..count {
+      let pos =
+        BlockPos::new(read_i32(r)?, read_i32(r)?, read_i32(r)?, read_i32(r)?);
+      let mut entity = FoxelEntity::new();
+      for _ in 0..read_u16(r)? {
+        let mut name_len = [0];
+        r.read_exact(&mut name_len)?;
+        let mut name = vec![0; name_len[0] as usize];
+        r.read_exact(&mut name)?;
+        // Don't trust the length enough to allocate it all up front
+        let len = read_u32(r)? as usize;
+        let mut bytes = Vec::new();
+        r.by_ref().take(len as u64).read_to_end(&mut bytes)?;
+        if bytes.len() != len {
+          return Err(LoadError::Corrupt("component cut short"));
+        }
+
+        let name = String::from_utf8_lossy(&name);
+        let Some(load) = self.loaders.get(name.as_ref()) else {
+          warn!("skipping unknown component {:?} at {:?}", name, pos);
+          continue;
+        };
+        let component =
+          load(&bytes).ok_or(LoadError::Corrupt("bad component"))?;
+        entity.components.push(component);
+      }
+      self.insert(pos, entity);
+    }
+    Ok(())
+  }
+}
+
+impl Default for EntityStore {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl fmt::Debug for EntityStore {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("EntityStore")
+      .field("entities", &self.len())
+      .finish_non_exhaustive()
+  }
+}
+
+/// Foxels stored inside something, and how many of each.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Inventory {
+  pub items: Vec<(Foxel, u32)>,
+}
+
+impl Component for Inventory {
+  const NAME: &'static str = "inventory";
+
+  fn save(&self, out: &mut Vec<u8>) {
+    for (foxel, count) in self.items.iter() {
+      out.push(foxel.id());
+      out.extend_from_slice(&count.to_le_bytes());
+    }
+  }
+
+  fn load(bytes: &[u8]) -> Option<Self> {
+    // A foxel id and a u32 count each
+    let chunks = bytes.chunks_exact(5);
+    if !chunks.remainder().is_empty() {
+      return None;
+    }
+    let items = chunks
+      .map(|c| {
+        let count = u32::from_le_bytes([c[1], c[2], c[3], c[4]]);
+        (Foxel::from_id(c[0]), count)
+      })
+      .collect();
+    Some(Self { items })
+  }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SignText(pub String);
+
+impl Component for SignText {
+  const NAME: &'static str = "sign_text";
+
+  fn save(&self, out: &mut Vec<u8>) {
+    out.extend_from_slice(self.0.as_bytes());
+  }
+
+  fn load(bytes: &[u8]) -> Option<Self> {
+    String::from_utf8(bytes.to_vec()).ok().map(Self)
+  }
+}
@@ -1,35 +1,59 @@
+mod registry;
+
+pub use registry::*;
+
+use std::fmt;
+
 use bytemuck::{Pod, Zeroable};
-use num_enum::TryFromPrimitive;
 
 /// Foxes are imaginary creatures that exist only in dreams.
 /// For reasons they can't explain, everyone knows what a fox looks like,
 /// but no one can ever remember having seen one.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, TryFromPrimitive, Hash)]
-#[repr(u8)]
-pub enum Foxel {
-  Air,
-  Red,
-  Green,
-  Blue,
-  RG,
-  GB,
-  RB,
-  Black,
-  White,
-
-  Invalid = 255,
-}
+///
+/// What each foxel actually is lives in the [`FoxelRegistry`];
+/// this is just its id.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Foxel(u8);
 
 impl Foxel {
+  /// The registry always puts air first.
+  pub const AIR: Foxel = Foxel(0);
+  /// Never handed out by the registry.
+  pub const INVALID: Foxel = Foxel(u8::MAX);
+
+  pub fn from_id(id: u8) -> Self {
+    Self(id)
+  }
+
+  pub fn id(self) -> u8 {
+    self.0
+  }
+
+  /// Look up a foxel by name in the builtin registry.
+  ///
+  /// Panics if there isn't one, so only use this for names you know are
+  /// in the data file.
+  pub fn named(name: &str) -> Self {
+    FoxelRegistry::builtin().expect(name)
+  }
+
+  /// What this foxel is, according to the builtin registry.
+  pub fn ty(self) -> &'static FoxelType {
+    FoxelRegistry::builtin().get(self)
+  }
+
   pub fn transparent(&self) -> bool {
-    match self {
-      Foxel::Air => true,
-      _ => false,
-    }
+    self.ty().opacity < 1.0
   }
 
   pub fn encode(self) -> FoxelRepr {
-    FoxelRepr(self as u8)
+    FoxelRepr(self.0)
+  }
+}
+
+impl fmt::Debug for Foxel {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "Foxel({}:{})", self.0, self.ty().name)
   }
 }
 
@@ -40,6 +64,6 @@ pub struct FoxelRepr(u8);
 
 impl FoxelRepr {
   pub fn decode(self) -> Foxel {
-    Foxel::try_from(self.0).unwrap_or(Foxel::Invalid)
+    Foxel(self.0)
   }
 }
@@ -0,0 +1,224 @@
+use std::sync::OnceLock;
+
+use ahash::AHashMap;
+
+use super::Foxel;
+
+/// Everything there is to know about one kind of foxel.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FoxelType {
+  pub name: String,
+  /// RGB
+  pub colour: [u8; 3],
+  /// 0 is invisible, 1 blocks all light.
+  pub opacity: f32,
+  /// How much light it gives off, from 0 to 1.
+  pub emissive: f32,
+  /// Whether things collide with it.
+  pub solid: bool,
+  pub tags: Vec<String>,
+}
+
+impl FoxelType {
+  pub fn has_tag(&self, tag: &str) -> bool {
+    self.tags.iter().any(|t| t == tag)
+  }
+}
+
+/// All the kinds of foxel, and the ids they get.
+///
+/// Ids are assigned in the order the types are listed in the data file.
+/// See `data/foxels.txt` for the format.
+#[derive(Debug, Clone)]
+pub struct FoxelRegistry {
+  types: Vec<FoxelType>,
+  by_name: AHashMap<String, Foxel>,
+  /// Handed back for any id with no type.
+  invalid: FoxelType,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegistryError {
+  /// 1-indexed
+  pub line: usize,
+  pub kind: RegistryErrorKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RegistryErrorKind {
+  MissingField(&'static str),
+  BadColour(String),
+  BadNumber(String),
+  /// Has to be `yes` or `no`
+  BadSolidity(String),
+  DuplicateName(String),
+  FirstIsNotAir,
+  TooManyTypes,
+}
+
+impl FoxelRegistry {
+  /// The palette is a square texture with one pixel per id.
+  pub const PALETTE_SIZE: usize = 16;
+
+  /// The registry built from the data file that ships with the game.
+  pub fn builtin() -> &'static FoxelRegistry {
+    static BUILTIN: OnceLock<FoxelRegistry> = OnceLock::new();
+    BUILTIN.get_or_init(|| {
+      let src = include_str!("../../../data/foxels.txt");
+      match FoxelRegistry::parse(src) {
+        Ok(it) => it,
+        Err(ono) => panic!("the builtin foxel data is broken: {:?}", ono),
+      }
+    })
+  }
+
+  pub fn parse(src: &str) -> Result<Self, RegistryError> {
+    let mut types = Vec::new();
+    let mut by_name = AHashMap::new();
+
+    for (idx, line) in src.lines().enumerate() {
+      // Colours start with # too, so comments have to be whole lines
+      let line = line.trim();
+      if line.is_empty() || line.starts_with('#') {
+        continue;
+      }
+
+      let err = |kind| RegistryError {
+        line: idx + 1,
+        kind,
+      };
+      let ty = parse_type(line).map_err(err)?;
+
+      if types.is_empty() && ty.name != "air" {
+        return Err(err(RegistryErrorKind::FirstIsNotAir));
+      }
+      if types.len() >= Foxel::INVALID.id() as usize {
+        return Err(err(RegistryErrorKind::TooManyTypes));
+      }
+      let foxel = Foxel::from_id(types.len() as u8);
+      if by_name.insert(ty.name.clone(), foxel).is_some() {
+        return Err(err(RegistryErrorKind::DuplicateName(ty.name)));
+      }
+      types.push(ty);
+    }
+
+    if types.is_empty() {
+      return Err(RegistryError {
+        line: 0,
+        kind: RegistryErrorKind::FirstIsNotAir,
+      });
+    }
+
+    Ok(Self {
+      types,
+      by_name,
+      invalid: FoxelType {
+        name: "invalid".to_owned(),
+        colour: [255, 156, 88],
+        opacity: 1.0,
+        emissive: 0.0,
+        solid: true,
+        tags: Vec::new(),
+      },
+    })
+  }
+
+  pub fn get(&self, foxel: Foxel) -> &FoxelType {
+    self.types.get(foxel.id() as usize).unwrap_or(&self.invalid)
+  }
+
+  pub fn by_name(&self, name: &str) -> Option<Foxel> {
+    self.by_name.get(name).copied()
+  }
+
+  /// Panics if there's nothing with that name.
+  pub fn expect(&self, name: &str) -> Foxel {
+    match self.by_name(name) {
+      Some(it) => it,
+      None => panic!("no foxel type named {:?}", name),
+    }
+  }
+
+  pub fn len(&self) -> usize {
+    self.types.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.types.is_empty()
+  }
+
+  pub fn iter(&self) -> impl Iterator<Item = (Foxel, &FoxelType)> + '_ {
+    (self.types.iter().enumerate())
+      .map(|(id, ty)| (Foxel::from_id(id as u8), ty))
+  }
+
+  pub fn with_tag<'a>(
+    &'a self,
+    tag: &'a str,
+  ) -> impl Iterator<Item = Foxel> + 'a {
+    self
+      .iter()
+      .filter(move |(_, ty)| ty.has_tag(tag))
+      .map(|(f, _)| f)
+  }
+
+  /// RGBA8 pixels for the shader's palette texture, `PALETTE_SIZE` on a
+  /// side, indexed by foxel id. Alpha is opacity.
+  pub fn palette_rgba(&self) -> Vec<u8> {
+    let count = Self::PALETTE_SIZE * Self::PALETTE_SIZE;
+    let mut out = Vec::with_capacity(count * 4);
+    for id in 0..count {
+      let ty = self.get(Foxel::from_id(id as u8));
+      let [r, g, b] = ty.colour;
+      let a = (ty.opacity.clamp(0.0, 1.0) * 255.0).round() as u8;
+      out.extend_from_slice(&[r, g, b, a]);
+    }
+    out
+  }
+}
+
+fn parse_type(line: &str) -> Result<FoxelType, RegistryErrorKind> {
+  let mut fields = line.split_whitespace();
+  let mut next =
+    |name| fields.next().ok_or(RegistryErrorKind::MissingField(name));
+
+  let name = next("name")?.to_owned();
+  let colour = parse_colour(next("colour")?)?;
+  let opacity = parse_number(next("opacity")?)?;
+  let emissive = parse_number(next("emissive")?)?;
+  let solid = match next("solid")? {
+    "yes" => true,
+    "no" => false,
+    other => return Err(RegistryErrorKind::BadSolidity(other.to_owned())),
+  };
+  let tags = fields.map(str::to_owned).collect();
+
+  Ok(FoxelType {
+    name,
+    colour,
+    opacity,
+    emissive,
+    solid,
+    tags,
+  })
+}
+
+fn parse_colour(s: &str) -> Result<[u8; 3], RegistryErrorKind> {
+  let bad = || RegistryErrorKind::BadColour(s.to_owned());
+  let hex = s
+    .strip_prefix('#')
+    .filter(|h| h.len() == 6)
+    .ok_or_else(bad)?;
+  let channel = |i: usize| {
+    u8::from_str_radix(hex.get(i * 2..i * 2 + 2).ok_or_else(bad)?, 16)
+      .map_err(|_| bad())
+  };
+  Ok([channel(0)?, channel(1)?, channel(2)?])
+}
+
+fn parse_number(s: &str) -> Result<f32, RegistryErrorKind> {
+  s.parse()
+    .ok()
+    .filter(|v: &f32| v.is_finite())
+    .ok_or_else(|| RegistryErrorKind::BadNumber(s.to_owned()))
+}
@@ -0,0 +1,33 @@
+//! Procedural world generation.
+//!
+//! Everything in here is a pure function of its params, seed included,
+//! so the same params always make the same world.
+
+mod caves;
+mod decorate;
+mod structures;
+mod terrain;
+
+pub use caves::*;
+pub use decorate::*;
+pub use structures::*;
+pub use terrain::*;
+
+use itertools::iproduct;
+
+use crate::math::{hexadecitree::Hexadecitree, BlockPos};
+
+/// The corner of every brick touching the area between `min` and `max`,
+/// inclusive.
+fn bricks_in(min: BlockPos, max: BlockPos) -> impl Iterator<Item = BlockPos> {
+  let fab = Hexadecitree::FOXELS_ACROSS_BRICK as usize;
+  let min = Hexadecitree::brick_corner(min).0;
+  let max = Hexadecitree::brick_corner(max).0;
+  iproduct!(
+    (min.x..=max.x).step_by(fab),
+    (min.y..=max.y).step_by(fab),
+    (min.z..=max.z).step_by(fab),
+    (min.w..=max.w).step_by(fab)
+  )
+  .map(|(x, y, z, w)| BlockPos::new(x, y, z, w))
+}
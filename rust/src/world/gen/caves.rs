@@ -0,0 +1,284 @@
+use std::ops::RangeInclusive;
+
+use itertools::iproduct;
+use rayon::prelude::*;
+use ultraviolet::{f32x8, IVec4, Vec4, Vec4x8};
+
+use crate::{
+  math::{
+    hexadecitree::{
+      reprs::{Brick, BrickRef},
+      Hexadecitree, SetFoxelError,
+    },
+    noise::{Fbm, Noise4, Simplex4},
+    rng::{mix64, SplitMix64},
+    BlockPos,
+  },
+  world::foxel::Foxel,
+};
+
+use super::bricks_in;
+
+const FAB: i32 = Hexadecitree::FOXELS_ACROSS_BRICK as i32;
+
+#[derive(Debug, Clone)]
+pub struct CaveParams {
+  pub seed: u64,
+  /// X coordinates that caves can be carved between. Remember +X is up.
+  pub depth: RangeInclusive<i32>,
+  /// Corners of the area to carve in, inclusive. Together with `depth`,
+  /// nothing outside of this gets touched.
+  pub min: BlockPos,
+  pub max: BlockPos,
+
+  /// How many worm tunnels to dig.
+  pub worm_count: u32,
+  /// How many steps each worm takes.
+  pub worm_length: u32,
+  /// The radius of a tunnel wobbles between these.
+  pub worm_radius: (f32, f32),
+  /// How hard worms turn. 0 is dead straight.
+  pub worm_curviness: f32,
+
+  /// Frequency of the cavern noise, in cycles per foxel.
+  pub cavern_frequency: f32,
+  /// Cavern noise above this is hollowed out. Higher means fewer, smaller
+  /// caverns; at 1 or above there are none.
+  pub cavern_threshold: f32,
+}
+
+impl Default for CaveParams {
+  fn default() -> Self {
+    Self {
+      seed: 0,
+      depth: -40..=-6,
+      min: BlockPos::new(Hexadecitree::MIN_COORD, -32, -32, -32),
+      max: BlockPos::new(Hexadecitree::MAX_COORD, 31, 31, 31),
+
+      worm_count: 8,
+      worm_length: 96,
+      worm_radius: (1.5, 3.0),
+      worm_curviness: 0.35,
+
+      cavern_frequency: 1.0 / 20.0,
+      cavern_threshold: 0.55,
+    }
+  }
+}
+
+/// Hollows out tunnels and caverns in whatever's already there.
+///
+/// Tunnels are "worms" that wander along a random path through all four
+/// dimensions, carving a 4-ball as they go. Caverns are wherever some 4D
+/// noise is over a threshold, so they're blobby in W too, instead of
+/// being the same 3D cave extruded.
+#[derive(Debug, Clone)]
+pub struct CaveGen {
+  params: CaveParams,
+  cavern_noise: Fbm<Simplex4>,
+  steer_noise: Simplex4,
+}
+
+impl CaveGen {
+  pub fn new(params: CaveParams) -> Self {
+    let cavern_noise = Fbm::new(Simplex4::new(params.seed), 3);
+    let steer_noise = Simplex4::new(mix64(params.seed ^ 0xca7e));
+    Self {
+      params,
+      cavern_noise,
+      steer_noise,
+    }
+  }
+
+  pub fn params(&self) -> &CaveParams {
+    &self.params
+  }
+
+  pub fn carve(&self, tree: &mut Hexadecitree) -> Result<(), SetFoxelError> {
+    self.carve_caverns(tree)?;
+    for idx in 0..self.params.worm_count {
+      for (center, radius) in self.worm_path(idx) {
+        self.carve_ball(tree, center, radius)?;
+      }
+    }
+    Ok(())
+  }
+
+  /// Whether this spot is allowed to be carved at all.
+  pub fn in_bounds(&self, pos: BlockPos) -> bool {
+    let p = &self.params;
+    p.depth.contains(&pos.x)
+      && (p.min.x..=p.max.x).contains(&pos.x)
+      && (p.min.y..=p.max.y).contains(&pos.y)
+      && (p.min.z..=p.max.z).contains(&pos.z)
+      && (p.min.w..=p.max.w).contains(&pos.w)
+  }
+
+  /// Whether the cavern noise hollows this spot out.
+  pub fn is_cavern(&self, pos: BlockPos) -> bool {
+    let sample = Vec4::from(pos.0) * self.params.cavern_frequency;
+    self.cavern_noise.sample(sample) > self.params.cavern_threshold
+  }
+
+  /// The centers and radii of the balls the `idx`th worm carves out.
+  pub fn worm_path(&self, idx: u32) -> Vec<(Vec4, f32)> {
+    let p = &self.params;
+    let mut rng = SplitMix64::new(mix64(p.seed ^ mix64(idx as u64 + 1)));
+
+    let x_lo = (*p.depth.start()).max(p.min.x);
+    let x_hi = (*p.depth.end()).min(p.max.x);
+    if x_lo > x_hi {
+      return Vec::new();
+    }
+    let mut pos = Vec4::new(
+      rng.range_i32(x_lo, x_hi + 1) as f32,
+      rng.range_i32(p.min.y, p.max.y + 1) as f32,
+      rng.range_i32(p.min.z, p.max.z + 1) as f32,
+      rng.range_i32(p.min.w, p.max.w + 1) as f32,
+    );
+    let mut dir = random_dir(&mut rng);
+
+    let (r_lo, r_hi) = p.worm_radius;
+    let mut path = Vec::with_capacity(p.worm_length as usize);
+    for step in 0..p.worm_length {
+      let t = step as f32 * 0.08;
+      // Sample the steering noise along a line unique to this worm,
+      // so the turns are smooth.
+      let steer = |axis: u32| {
+        self.steer_noise.sample(Vec4::new(
+          t,
+          idx as f32 * 17.3,
+          axis as f32 * 31.1,
+          0.0,
+        ))
+      };
+
+      let wobble =
+        (self.steer_noise.sample(Vec4::new(t, idx as f32, 0.0, 9.9)) + 1.0)
+          / 2.0;
+      let radius = r_lo + (r_hi - r_lo) * wobble;
+      path.push((pos, radius));
+
+      let turn = Vec4::new(steer(0), steer(1), steer(2), steer(3));
+      dir = (dir + turn * p.worm_curviness).normalized();
+      // Bounce off the top and bottom of the allowed depth
+      if (pos.x <= x_lo as f32 && dir.x < 0.0)
+        || (pos.x >= x_hi as f32 && dir.x > 0.0)
+      {
+        dir.x = -dir.x;
+      }
+      pos += dir * (radius * 0.5).max(0.5);
+    }
+    path
+  }
+
+  fn carve_ball(
+    &self,
+    tree: &mut Hexadecitree,
+    center: Vec4,
+    radius: f32,
+  ) -> Result<(), SetFoxelError> {
+    let lo = (center - Vec4::broadcast(radius)).map(f32::floor);
+    let hi = (center + Vec4::broadcast(radius)).map(f32::ceil);
+    for (x, y, z, w) in iproduct!(
+      lo.x as i32..=hi.x as i32,
+      lo.y as i32..=hi.y as i32,
+      lo.z as i32..=hi.z as i32,
+      lo.w as i32..=hi.w as i32
+    ) {
+      let pos = BlockPos::new(x, y, z, w);
+      let foxel_center = Vec4::from(pos.0) + Vec4::broadcast(0.5);
+      if (foxel_center - center).mag_sq() > radius * radius
+        || !self.in_bounds(pos)
+      {
+        continue;
+      }
+      match tree.set(pos, Foxel::AIR) {
+        Ok(_) | Err(SetFoxelError::OutOfBounds) => {}
+        Err(e) => return Err(e),
+      }
+    }
+    Ok(())
+  }
+
+  fn carve_caverns(
+    &self,
+    tree: &mut Hexadecitree,
+  ) -> Result<(), SetFoxelError> {
+    let p = &self.params;
+    let min =
+      BlockPos::new(p.min.x.max(*p.depth.start()), p.min.y, p.min.z, p.min.w);
+    let max =
+      BlockPos::new(p.max.x.min(*p.depth.end()), p.max.y, p.max.z, p.max.w);
+    if min.x > max.x {
+      return Ok(());
+    }
+
+    let tree_ro = &*tree;
+    let carved = bricks_in(min, max)
+      .collect::<Vec<_>>()
+      .into_par_iter()
+      .filter_map(|corner| {
+        let existing = tree_ro.brick_at(corner)?;
+        if matches!(existing, BrickRef::Solid(Foxel::AIR)) {
+          return None;
+        }
+        let mut brick = existing.to_brick();
+        self
+          .carve_brick(corner, &mut brick)
+          .then_some((corner, brick))
+      })
+      .collect::<Vec<_>>();
+
+    for (corner, brick) in carved {
+      tree.set_brick(corner, &brick)?;
+    }
+    Ok(())
+  }
+
+  /// Return if anything was carved.
+  fn carve_brick(&self, corner: BlockPos, brick: &mut Brick) -> bool {
+    let lanes = Vec4x8::from(
+      [0, 1, 2, 3, 4, 5, 6, 7].map(|w| Vec4::new(0.0, 0.0, 0.0, w as f32)),
+    );
+
+    let mut any = false;
+    // W is the least significant index in a brick, so do a whole row
+    // of it at once.
+    for (dx, dy, dz) in iproduct!(0..FAB, 0..FAB, 0..FAB) {
+      let row = corner.0 + IVec4::new(dx, dy, dz, 0);
+      let sample = (Vec4x8::splat(Vec4::from(row)) + lanes)
+        * f32x8::splat(self.params.cavern_frequency);
+      let noise = self.cavern_noise.sample_x8(sample).to_array();
+
+      for (dw, n) in noise.into_iter().enumerate() {
+        let offset = IVec4::new(dx, dy, dz, dw as i32);
+        if n > self.params.cavern_threshold
+          && self.in_bounds(BlockPos(corner.0 + offset))
+          && brick.get(offset) != Foxel::AIR
+        {
+          brick.set(offset, Foxel::AIR);
+          any = true;
+        }
+      }
+    }
+    any
+  }
+}
+
+/// A uniformly random direction.
+fn random_dir(rng: &mut SplitMix64) -> Vec4 {
+  // Rejection-sample the unit 4-ball so it isn't biased to the corners
+  loop {
+    let v = Vec4::new(
+      rng.next_f32() * 2.0 - 1.0,
+      rng.next_f32() * 2.0 - 1.0,
+      rng.next_f32() * 2.0 - 1.0,
+      rng.next_f32() * 2.0 - 1.0,
+    );
+    let mag_sq = v.mag_sq();
+    if mag_sq > 1e-4 && mag_sq <= 1.0 {
+      return v / mag_sq.sqrt();
+    }
+  }
+}
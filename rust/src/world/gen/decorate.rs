@@ -0,0 +1,266 @@
+use itertools::iproduct;
+use rayon::prelude::*;
+use ultraviolet::IVec4;
+
+use crate::{
+  math::{
+    hexadecitree::{Hexadecitree, SetFoxelError},
+    rng::{hash_pos, SplitMix64},
+    BlockPos,
+  },
+  world::foxel::Foxel,
+};
+
+use super::{Schematic, TerrainGen};
+
+/// The biggest procedural tree or rock, along any axis.
+const MAX_PROCEDURAL_SIZE: i32 = 11;
+
+#[derive(Debug, Clone)]
+pub struct DecorationParams {
+  pub seed: u64,
+  /// Corners of the area to decorate, inclusive. Nothing sticking out of
+  /// this gets placed.
+  pub min: BlockPos,
+  pub max: BlockPos,
+
+  /// The Y/Z/W area is split into cubes this many foxels across,
+  /// and each gets its own attempts.
+  pub cell_size: i32,
+  /// How many things each cell tries to place. Some will get thrown out
+  /// for overlapping.
+  pub attempts_per_cell: u32,
+
+  pub tree_weight: u32,
+  pub rock_weight: u32,
+  /// Imported schematics, and how likely each one is to get picked
+  /// compared to trees and rocks.
+  pub schematics: Vec<(Schematic, u32)>,
+}
+
+impl Default for DecorationParams {
+  fn default() -> Self {
+    Self {
+      seed: 0,
+      min: BlockPos::new(Hexadecitree::MIN_COORD, -32, -32, -32),
+      max: BlockPos::new(Hexadecitree::MAX_COORD, 31, 31, 31),
+
+      cell_size: 16,
+      attempts_per_cell: 6,
+
+      tree_weight: 3,
+      rock_weight: 1,
+      schematics: Vec::new(),
+    }
+  }
+}
+
+/// Something that's going to get stamped into the world.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Placement {
+  pub origin: BlockPos,
+  pub schematic: Schematic,
+}
+
+impl Placement {
+  /// Inclusive corners of the box this takes up.
+  pub fn bounds(&self) -> (IVec4, IVec4) {
+    let min = self.origin.0;
+    (min, min + self.schematic.size() - IVec4::one())
+  }
+
+  /// Whether the two boxes overlap, or come within `gap` foxels of each
+  /// other.
+  pub fn overlaps(&self, other: &Placement, gap: i32) -> bool {
+    let (a_lo, a_hi) = self.bounds();
+    let (b_lo, b_hi) = other.bounds();
+    (a_lo.as_array().into_iter())
+      .zip(a_hi.as_array())
+      .zip(b_lo.as_array().into_iter().zip(b_hi.as_array()))
+      .all(|((a_lo, a_hi), (b_lo, b_hi))| {
+        a_lo <= b_hi + gap && b_lo <= a_hi + gap
+      })
+  }
+}
+
+/// A placement that hasn't been checked for overlaps yet.
+struct Candidate {
+  placement: Placement,
+  /// Higher wins when two candidates overlap. The cell and attempt index
+  /// break ties.
+  priority: (u64, [i32; 4], u32),
+}
+
+/// Scatters trees, rocks and schematics over the terrain.
+///
+/// Each cell of the Y/Z/W plane seeds its own RNG from its position, so
+/// what lands in one cell doesn't depend on what order the cells are
+/// decorated in, or which other cells get decorated at all. When two
+/// candidates overlap, the one with the lower priority loses; that only
+/// needs the candidates from the neighbouring cells, so it's
+/// order-independent too.
+#[derive(Debug, Clone)]
+pub struct Decorator {
+  params: DecorationParams,
+  terrain: TerrainGen,
+  /// How many cells away a candidate could possibly overlap from.
+  reach: i32,
+}
+
+impl Decorator {
+  pub fn new(params: DecorationParams, terrain: TerrainGen) -> Self {
+    assert!(params.cell_size > 0, "cell size must be positive");
+    // How far anything can stick out below and above its anchor, along
+    // Y/Z/W. Trees and rocks are anchored in the middle, but imported
+    // schematics can be anchored anywhere.
+    let (mut below, mut above) = (
+      MAX_PROCEDURAL_SIZE / 2,
+      MAX_PROCEDURAL_SIZE - MAX_PROCEDURAL_SIZE / 2,
+    );
+    for (schem, _) in &params.schematics {
+      let (size, anchor) = (schem.size().as_array(), schem.anchor().as_array());
+      for axis in 1..4 {
+        below = below.max(anchor[axis]);
+        above = above.max(size[axis] - anchor[axis]);
+      }
+    }
+    // Two anchors can't be further apart than one's bit below plus the
+    // other's bit above and still come within a foxel of each other
+    let reach = (below + above + params.cell_size - 1) / params.cell_size;
+    Self {
+      params,
+      terrain,
+      reach,
+    }
+  }
+
+  pub fn params(&self) -> &DecorationParams {
+    &self.params
+  }
+
+  /// Every cell touching the area. The X coordinate is always 0.
+  pub fn cells(&self) -> impl Iterator<Item = IVec4> {
+    let p = &self.params;
+    let lo = self.cell_of(p.min);
+    let hi = self.cell_of(p.max);
+    iproduct!(lo.y..=hi.y, lo.z..=hi.z, lo.w..=hi.w)
+      .map(|(y, z, w)| IVec4::new(0, y, z, w))
+  }
+
+  pub fn cell_of(&self, pos: BlockPos) -> IVec4 {
+    let size = self.params.cell_size;
+    IVec4::new(
+      0,
+      pos.y.div_euclid(size),
+      pos.z.div_euclid(size),
+      pos.w.div_euclid(size),
+    )
+  }
+
+  /// What actually gets placed in this cell, after overlaps are thrown out.
+  pub fn placements_in_cell(&self, cell: IVec4) -> Vec<Placement> {
+    let r = self.reach;
+    let neighbours = iproduct!(-r..=r, -r..=r, -r..=r)
+      .flat_map(|(y, z, w)| {
+        self.candidates_in_cell(cell + IVec4::new(0, y, z, w))
+      })
+      .collect::<Vec<_>>();
+
+    self
+      .candidates_in_cell(cell)
+      .into_iter()
+      .filter(|c| {
+        !neighbours.iter().any(|other| {
+          other.priority > c.priority
+            && other.placement.overlaps(&c.placement, 1)
+        })
+      })
+      .map(|c| c.placement)
+      .collect()
+  }
+
+  pub fn decorate(&self, tree: &mut Hexadecitree) -> Result<(), SetFoxelError> {
+    let placements = self
+      .cells()
+      .collect::<Vec<_>>()
+      .into_par_iter()
+      .flat_map_iter(|cell| self.placements_in_cell(cell))
+      .collect::<Vec<_>>();
+
+    for placement in placements {
+      // Caves might have eaten the ground out from under it
+      let below =
+        placement.origin.0 + placement.schematic.anchor() - IVec4::unit_x();
+      if matches!(tree.get(BlockPos(below)), None | Some(Foxel::AIR)) {
+        continue;
+      }
+      placement.schematic.place(tree, placement.origin)?;
+    }
+    Ok(())
+  }
+
+  fn candidates_in_cell(&self, cell: IVec4) -> Vec<Candidate> {
+    let p = &self.params;
+    let mut rng = SplitMix64::new(hash_pos(p.seed, cell));
+    let corner = cell * p.cell_size;
+
+    let total_weight = p.tree_weight
+      + p.rock_weight
+      + p.schematics.iter().map(|(_, w)| w).sum::<u32>();
+    if total_weight == 0 {
+      return Vec::new();
+    }
+
+    let mut out = Vec::new();
+    for idx in 0..p.attempts_per_cell {
+      // Always pull the same numbers in the same order, so throwing one
+      // candidate out doesn't change the rest.
+      let y = corner.y + rng.range_i32(0, p.cell_size);
+      let z = corner.z + rng.range_i32(0, p.cell_size);
+      let w = corner.w + rng.range_i32(0, p.cell_size);
+      let priority = rng.next_u64();
+      let mut pick = rng.range_i32(0, total_weight as i32) as u32;
+      let mut shape_rng = SplitMix64::new(rng.next_u64());
+
+      let schematic = if pick < p.tree_weight {
+        Schematic::tree(&mut shape_rng)
+      } else if pick < p.tree_weight + p.rock_weight {
+        Schematic::rock(&mut shape_rng)
+      } else {
+        pick -= p.tree_weight + p.rock_weight;
+        p.schematics
+          .iter()
+          .find(|(_, w)| {
+            let found = pick < *w;
+            pick = pick.saturating_sub(*w);
+            found
+          })
+          .map(|(s, _)| s.clone())
+          .unwrap()
+      };
+
+      let surface = self.terrain.height_at(y, z, w);
+      let ground = IVec4::new(surface + 1, y, z, w);
+      let placement = Placement {
+        origin: BlockPos(ground - schematic.anchor()),
+        schematic,
+      };
+      if self.contains(&placement) {
+        out.push(Candidate {
+          placement,
+          priority: (priority, cell.as_array(), idx),
+        });
+      }
+    }
+    out
+  }
+
+  fn contains(&self, placement: &Placement) -> bool {
+    let (lo, hi) = placement.bounds();
+    let p = &self.params;
+    (lo.as_array().into_iter())
+      .zip(hi.as_array())
+      .zip(p.min.as_array().into_iter().zip(p.max.as_array()))
+      .all(|((lo, hi), (min, max))| min <= lo && hi <= max)
+  }
+}
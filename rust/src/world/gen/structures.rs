@@ -0,0 +1,246 @@
+use std::cmp::Ordering;
+
+use itertools::iproduct;
+use ultraviolet::{IVec4, Vec4};
+
+use crate::{
+  math::{
+    hexadecitree::{Hexadecitree, SetFoxelError},
+    rng::SplitMix64,
+    BlockPos,
+  },
+  world::foxel::{Foxel, FoxelRepr},
+};
+
+/// A chunk of foxels that can be stamped into the world.
+///
+/// Air in a schematic means "leave it alone," not "carve this out."
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schematic {
+  size: IVec4,
+  /// Same layout as a brick: X is the most significant.
+  foxels: Vec<Foxel>,
+  /// The spot in the schematic that goes right on top of the ground.
+  anchor: IVec4,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SchematicError {
+  BadMagic,
+  UnknownVersion(u8),
+  /// The header says there's more data than there is
+  Truncated,
+  /// There's more data than the header says there is
+  TrailingBytes,
+  /// A side is 0, or it's too big to index
+  BadSize,
+  /// The anchor isn't inside the schematic
+  BadAnchor,
+}
+
+impl Schematic {
+  const MAGIC: &'static [u8; 4] = b"FXSC";
+  const VERSION: u8 = 1;
+  const HEADER_LEN: usize = 4 + 1 + 8 + 8;
+
+  /// An all-air schematic.
+  pub fn new(size: IVec4, anchor: IVec4) -> Self {
+    assert!(
+      size.as_array().iter().all(|v| *v > 0),
+      "schematic size {:?} must be positive",
+      size
+    );
+    let volume = size.as_array().iter().product::<i32>() as usize;
+    Self {
+      size,
+      foxels: vec![Foxel::AIR; volume],
+      anchor,
+    }
+  }
+
+  pub fn size(&self) -> IVec4 {
+    self.size
+  }
+
+  pub fn anchor(&self) -> IVec4 {
+    self.anchor
+  }
+
+  fn idx(&self, offset: IVec4) -> Option<usize> {
+    let in_bounds = (offset.as_array().into_iter())
+      .zip(self.size.as_array())
+      .all(|(v, max)| (0..max).contains(&v));
+    in_bounds.then(|| {
+      (((offset.x * self.size.y + offset.y) * self.size.z + offset.z)
+        * self.size.w
+        + offset.w) as usize
+    })
+  }
+
+  pub fn get(&self, offset: IVec4) -> Option<Foxel> {
+    self.idx(offset).map(|i| self.foxels[i])
+  }
+
+  /// Out-of-bounds sets are ignored.
+  pub fn set(&mut self, offset: IVec4, foxel: Foxel) {
+    if let Some(i) = self.idx(offset) {
+      self.foxels[i] = foxel;
+    }
+  }
+
+  /// Every non-air foxel and its offset.
+  pub fn iter(&self) -> impl Iterator<Item = (IVec4, Foxel)> + '_ {
+    let s = self.size;
+    iproduct!(0..s.x, 0..s.y, 0..s.z, 0..s.w)
+      .map(|(x, y, z, w)| IVec4::new(x, y, z, w))
+      .zip(self.foxels.iter().copied())
+      .filter(|(_, f)| *f != Foxel::AIR)
+  }
+
+  /// Stamp this into the tree with its corner at `origin`, only overwriting
+  /// air.
+  pub fn place(
+    &self,
+    tree: &mut Hexadecitree,
+    origin: BlockPos,
+  ) -> Result<(), SetFoxelError> {
+    for (offset, foxel) in self.iter() {
+      let pos = BlockPos(origin.0 + offset);
+      // Skip anything out of bounds, or with something in the way
+      if tree.get(pos) == Some(Foxel::AIR) {
+        tree.set(pos, foxel)?;
+      }
+    }
+    Ok(())
+  }
+
+  /// Format:
+  /// - `FXSC`
+  /// - version byte
+  /// - size, as four little-endian u16s
+  /// - anchor, as four little-endian u16s
+  /// - one byte per foxel, in the same order as a brick
+  pub fn to_bytes(&self) -> Vec<u8> {
+    let mut out = Vec::with_capacity(Self::HEADER_LEN + self.foxels.len());
+    out.extend_from_slice(Self::MAGIC);
+    out.push(Self::VERSION);
+    for v in self
+      .size
+      .as_array()
+      .into_iter()
+      .chain(self.anchor.as_array())
+    {
+      out.extend_from_slice(&(v as u16).to_le_bytes());
+    }
+    out.extend(
+      self
+        .foxels
+        .iter()
+        .map(|f| bytemuck::must_cast::<FoxelRepr, u8>(f.encode())),
+    );
+    out
+  }
+
+  pub fn from_bytes(bytes: &[u8]) -> Result<Self, SchematicError> {
+    if bytes.len() < Self::HEADER_LEN {
+      return Err(SchematicError::Truncated);
+    }
+    if &bytes[..4] != Self::MAGIC {
+      return Err(SchematicError::BadMagic);
+    }
+    if bytes[4] != Self::VERSION {
+      return Err(SchematicError::UnknownVersion(bytes[4]));
+    }
+
+    let mut nums = bytes[5..Self::HEADER_LEN]
+      .chunks_exact(2)
+      .map(|b| u16::from_le_bytes([b[0], b[1]]) as i32);
+    let mut next4 = || {
+      IVec4::new(
+        nums.next().unwrap(),
+        nums.next().unwrap(),
+        nums.next().unwrap(),
+        nums.next().unwrap(),
+      )
+    };
+    let size = next4();
+    let anchor = next4();
+    if size.as_array().contains(&0) {
+      return Err(SchematicError::BadSize);
+    }
+    // Check it all adds up before allocating anything
+    let volume = (size.as_array().iter())
+      .try_fold(1u64, |acc, v| acc.checked_mul(*v as u64))
+      .filter(|v| *v <= i32::MAX as u64)
+      .ok_or(SchematicError::BadSize)?;
+    let body = &bytes[Self::HEADER_LEN..];
+    match (body.len() as u64).cmp(&volume) {
+      Ordering::Less => return Err(SchematicError::Truncated),
+      Ordering::Greater => return Err(SchematicError::TrailingBytes),
+      Ordering::Equal => {}
+    }
+    if (anchor.as_array().iter().zip(size.as_array())).any(|(a, s)| *a >= s) {
+      return Err(SchematicError::BadAnchor);
+    }
+
+    let mut schem = Schematic::new(size, anchor);
+    for (slot, b) in schem.foxels.iter_mut().zip(body) {
+      *slot = bytemuck::must_cast::<u8, FoxelRepr>(*b).decode();
+    }
+    Ok(schem)
+  }
+
+  /// A 4D tree: a trunk going up +X, and a hyperspherical clump of leaves.
+  pub fn tree(rng: &mut SplitMix64) -> Self {
+    let trunk = rng.range_i32(4, 8);
+    let radius = rng.range_i32(2, 4);
+    let side = radius * 2 + 1;
+    let size = IVec4::new(trunk + radius + 1, side, side, side);
+    let anchor = IVec4::new(0, radius, radius, radius);
+    let mut schem = Schematic::new(size, anchor);
+
+    let (wood, leaves) = (Foxel::named("wood"), Foxel::named("leaves"));
+    let canopy = Vec4::from(anchor + IVec4::unit_x() * trunk);
+    let r_sq = (radius as f32 + 0.5).powi(2);
+    for (x, y, z, w) in iproduct!(0..size.x, 0..side, 0..side, 0..side) {
+      let offset = IVec4::new(x, y, z, w);
+      if (Vec4::from(offset) - canopy).mag_sq() <= r_sq {
+        schem.set(offset, leaves);
+      }
+    }
+    for x in 0..trunk {
+      schem.set(anchor + IVec4::unit_x() * x, wood);
+    }
+    schem
+  }
+
+  /// A lumpy 4D boulder, half-sunk into the ground.
+  pub fn rock(rng: &mut SplitMix64) -> Self {
+    let radii = Vec4::new(
+      1.0 + rng.next_f32() * 1.5,
+      1.0 + rng.next_f32() * 1.5,
+      1.0 + rng.next_f32() * 1.5,
+      1.0 + rng.next_f32() * 1.5,
+    );
+    let half = IVec4::new(
+      radii.x.ceil() as i32,
+      radii.y.ceil() as i32,
+      radii.z.ceil() as i32,
+      radii.w.ceil() as i32,
+    );
+    let size = half * 2 + IVec4::one();
+    let mut schem = Schematic::new(size, half);
+    let stone = Foxel::named("stone");
+
+    for (x, y, z, w) in iproduct!(0..size.x, 0..size.y, 0..size.z, 0..size.w) {
+      let offset = IVec4::new(x, y, z, w);
+      let from_center = Vec4::from(offset - half) / radii;
+      // Roughen it up a bit
+      let lumpiness = 0.8 + 0.2 * rng.next_f32();
+      if from_center.mag_sq() <= lumpiness {
+        schem.set(offset, stone);
+      }
+    }
+    schem
+  }
+}
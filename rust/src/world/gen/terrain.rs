@@ -0,0 +1,178 @@
+use itertools::iproduct;
+use rayon::prelude::*;
+use ultraviolet::{IVec4, Vec4};
+
+use crate::{
+  math::{
+    hexadecitree::{reprs::Brick, Hexadecitree, SetFoxelError},
+    noise::{Fbm, Noise4, Simplex4},
+    BlockPos,
+  },
+  world::foxel::Foxel,
+};
+
+const FAB: i32 = Hexadecitree::FOXELS_ACROSS_BRICK as i32;
+
+#[derive(Debug, Clone)]
+pub struct TerrainParams {
+  pub seed: u64,
+  /// The X coordinate the ground undulates around.
+  pub base_height: f32,
+  /// How far above and below `base_height` the ground can get.
+  pub amplitude: f32,
+  /// Frequency of the first octave, in cycles per foxel.
+  pub frequency: f32,
+  pub octaves: u32,
+  /// How many foxels of dirt are between the grass and the stone.
+  pub dirt_depth: i32,
+  pub grass: Foxel,
+  pub dirt: Foxel,
+  pub stone: Foxel,
+  /// Corners of the area to generate, inclusive.
+  /// Rounded outwards to whole bricks.
+  pub min: BlockPos,
+  pub max: BlockPos,
+}
+
+impl Default for TerrainParams {
+  fn default() -> Self {
+    Self {
+      seed: 0,
+      base_height: 0.0,
+      amplitude: 10.0,
+      frequency: 1.0 / 48.0,
+      octaves: 4,
+      dirt_depth: 3,
+      grass: Foxel::named("grass"),
+      dirt: Foxel::named("dirt"),
+      stone: Foxel::named("stone"),
+      min: BlockPos::new(Hexadecitree::MIN_COORD, -32, -32, -32),
+      max: BlockPos::new(Hexadecitree::MAX_COORD, 31, 31, 31),
+    }
+  }
+}
+
+/// Makes rolling hills, with +X being up.
+///
+/// The ground is a heightmap over Y/Z/W. Every column gets a layer
+/// of grass on top, `dirt_depth` foxels of dirt, and stone the rest of
+/// the way down.
+#[derive(Debug, Clone)]
+pub struct TerrainGen {
+  params: TerrainParams,
+  noise: Fbm<Simplex4>,
+}
+
+impl TerrainGen {
+  pub fn new(params: TerrainParams) -> Self {
+    let noise = Fbm::new(Simplex4::new(params.seed), params.octaves);
+    Self { params, noise }
+  }
+
+  pub fn params(&self) -> &TerrainParams {
+    &self.params
+  }
+
+  /// X coordinate of the topmost solid foxel in the given column.
+  pub fn height_at(&self, y: i32, z: i32, w: i32) -> i32 {
+    let p = self.params();
+    let sample_pos = Vec4::new(0.0, y as f32, z as f32, w as f32);
+    let n = self.noise.sample(sample_pos * p.frequency);
+    (p.base_height + n * p.amplitude).round() as i32
+  }
+
+  /// What goes at `x` in a column whose top is at `height`.
+  pub fn layer_at(&self, x: i32, height: i32) -> Foxel {
+    let p = self.params();
+    if x > height {
+      Foxel::AIR
+    } else if x == height {
+      p.grass
+    } else if x >= height - p.dirt_depth {
+      p.dirt
+    } else {
+      p.stone
+    }
+  }
+
+  pub fn generate(&self, tree: &mut Hexadecitree) -> Result<(), SetFoxelError> {
+    let p = self.params();
+    let min = Hexadecitree::brick_corner(p.min).0;
+    let max = Hexadecitree::brick_corner(p.max).0;
+
+    let columns = iproduct!(
+      (min.y..=max.y).step_by(FAB as usize),
+      (min.z..=max.z).step_by(FAB as usize),
+      (min.w..=max.w).step_by(FAB as usize)
+    )
+    .collect::<Vec<_>>();
+
+    // The noise is the slow part, so do that in parallel,
+    // then write everything in one go.
+    let bricks = columns
+      .into_par_iter()
+      .flat_map_iter(|(y, z, w)| {
+        let heights = self.column_heights(y, z, w);
+        (min.x..=max.x)
+          .step_by(FAB as usize)
+          .map(move |x| {
+            let corner = BlockPos::new(x, y, z, w);
+            (corner, self.brick(corner, &heights))
+          })
+          .collect::<Vec<_>>()
+      })
+      .collect::<Vec<_>>();
+
+    for (corner, contents) in bricks {
+      match contents {
+        BrickContents::Solid(f) => tree.fill_brick(corner, f)?,
+        BrickContents::Composite(brick) => tree.set_brick(corner, &brick)?,
+      }
+    }
+
+    Ok(())
+  }
+
+  /// Heights of all the columns in a brick, indexed by `[y][z][w]`
+  fn column_heights(&self, y: i32, z: i32, w: i32) -> ColumnHeights {
+    let mut heights = [[[0; FAB as usize]; FAB as usize]; FAB as usize];
+    for (dy, dz, dw) in iproduct!(0..FAB, 0..FAB, 0..FAB) {
+      heights[dy as usize][dz as usize][dw as usize] =
+        self.height_at(y + dy, z + dz, w + dw);
+    }
+    heights
+  }
+
+  fn brick(&self, corner: BlockPos, heights: &ColumnHeights) -> BrickContents {
+    let flat = heights.iter().flatten().flatten();
+    let lowest = *flat.clone().min().unwrap();
+    let highest = *flat.max().unwrap();
+
+    let bottom = corner.x;
+    let top = corner.x + FAB - 1;
+    if bottom > highest {
+      return BrickContents::Solid(Foxel::AIR);
+    }
+    if top < lowest - self.params.dirt_depth {
+      return BrickContents::Solid(self.params.stone);
+    }
+
+    let mut brick = Brick::composite_solid(Foxel::AIR);
+    for (dx, dy, dz, dw) in iproduct!(0..FAB, 0..FAB, 0..FAB, 0..FAB) {
+      let height = heights[dy as usize][dz as usize][dw as usize];
+      let foxel = self.layer_at(corner.x + dx, height);
+      brick.set(IVec4::new(dx, dy, dz, dw), foxel);
+    }
+    match brick.uniform() {
+      Some(f) => BrickContents::Solid(f),
+      None => BrickContents::Composite(Box::new(brick)),
+    }
+  }
+}
+
+type ColumnHeights = [[[i32; FAB as usize]; FAB as usize]; FAB as usize];
+
+enum BrickContents {
+  Solid(Foxel),
+  Composite(Box<Brick>),
+}
@@ -0,0 +1,446 @@
+//! How brightly lit every foxel is, by flood fill.
+//!
+//! There's two channels. Sky light comes down from +X at full strength
+//! through air, and block light comes out of emissive foxels. Both lose a
+//! level per foxel as they spread sideways, and an extra one going through
+//! anything translucent. Opaque foxels stop light dead.
+//!
+//! Light is kept per brick. Most bricks are boring, so they don't store
+//! anything: a brick of solid air with nothing but air above it is open to
+//! the sky and fully lit, and any other solid brick is dark except for what
+//! it glows. Only bricks where that isn't true get a full array.
+
+use std::collections::VecDeque;
+
+use ahash::AHashMap;
+use bytemuck::{Pod, Zeroable};
+use itertools::iproduct;
+use ultraviolet::IVec4;
+
+use crate::math::{
+  hexadecitree::{
+    reprs::{Brick, BrickRef},
+    Hexadecitree,
+  },
+  BlockPos,
+};
+
+use super::foxel::{Foxel, FoxelRegistry};
+
+const FAB: i32 = Hexadecitree::FOXELS_ACROSS_BRICK as i32;
+const PER_BRICK: usize = Hexadecitree::FOXELS_PER_BRICK as usize;
+
+/// Sky light in the high 4 bits, block light in the low 4.
+#[derive(Clone, Copy, PartialEq, Eq, Default, Pod, Zeroable)]
+#[repr(transparent)]
+pub struct Light(u8);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LightChannel {
+  Sky,
+  Block,
+}
+
+impl Light {
+  pub const MAX: u8 = 15;
+  pub const DARK: Light = Light(0);
+  pub const SKY: Light = Light(Self::MAX << 4);
+
+  pub fn new(sky: u8, block: u8) -> Self {
+    Self(sky.min(Self::MAX) << 4 | block.min(Self::MAX))
+  }
+
+  pub fn sky(self) -> u8 {
+    self.0 >> 4
+  }
+
+  pub fn block(self) -> u8 {
+    self.0 & 0xf
+  }
+
+  pub fn get(self, channel: LightChannel) -> u8 {
+    match channel {
+      LightChannel::Sky => self.sky(),
+      LightChannel::Block => self.block(),
+    }
+  }
+
+  pub fn with(self, channel: LightChannel, level: u8) -> Self {
+    match channel {
+      LightChannel::Sky => Self::new(level, self.block()),
+      LightChannel::Block => Self::new(self.sky(), level),
+    }
+  }
+
+  /// The packed byte, for shipping to the GPU.
+  pub fn to_bits(self) -> u8 {
+    self.0
+  }
+}
+
+impl std::fmt::Debug for Light {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "Light(sky {}, block {})", self.sky(), self.block())
+  }
+}
+
+/// The light in one brick.
+#[derive(Debug, Clone, Copy)]
+pub enum BrickLight<'a> {
+  Uniform(Light),
+  /// Indexed like `Brick`.
+  Mixed(&'a [Light; PER_BRICK]),
+}
+
+/// What light cares about in a foxel type.
+#[derive(Debug, Clone, Copy, Default)]
+struct LightProps {
+  opaque: bool,
+  /// How many extra levels get lost going through it.
+  extra_loss: u8,
+  emits: u8,
+}
+
+#[derive(Debug, Clone)]
+pub struct LightMap {
+  bricks: AHashMap<BlockPos, Box<[Light; PER_BRICK]>>,
+  /// For each column of bricks, keyed by its corner's Y/Z/W, the X of the
+  /// lowest brick that's open to the sky. Columns that are open all the
+  /// way down aren't in here.
+  sky_floors: AHashMap<[i32; 3], i32>,
+  props: Box<[LightProps; 256]>,
+}
+
+const DIRS: [IVec4; 8] = [
+  IVec4::new(1, 0, 0, 0),
+  IVec4::new(-1, 0, 0, 0),
+  IVec4::new(0, 1, 0, 0),
+  IVec4::new(0, -1, 0, 0),
+  IVec4::new(0, 0, 1, 0),
+  IVec4::new(0, 0, -1, 0),
+  IVec4::new(0, 0, 0, 1),
+  IVec4::new(0, 0, 0, -1),
+];
+const DOWN: IVec4 = IVec4::new(-1, 0, 0, 0);
+
+impl LightMap {
+  /// Light for an empty tree, with properties from the builtin registry.
+  pub fn new() -> Self {
+    Self::with_registry(FoxelRegistry::builtin())
+  }
+
+  pub fn with_registry(reg: &FoxelRegistry) -> Self {
+    let mut props = Box::new([LightProps::default(); 256]);
+    for (id, slot) in props.iter_mut().enumerate() {
+      let ty = reg.get(Foxel::from_id(id as u8));
+      *slot = LightProps {
+        opaque: ty.opacity >= 1.0,
+        extra_loss: (ty.opacity > 0.0 && ty.opacity < 1.0) as u8,
+        emits: (ty.emissive.clamp(0.0, 1.0) * Light::MAX as f32).round() as u8,
+      };
+    }
+    Self {
+      bricks: AHashMap::new(),
+      sky_floors: AHashMap::new(),
+      props,
+    }
+  }
+
+  pub fn get(&self, tree: &Hexadecitree, pos: BlockPos) -> Option<Light> {
+    let corner = Hexadecitree::brick_corner(pos);
+    match self.bricks.get(&corner) {
+      Some(brick) => Some(brick[Brick::offset_to_idx(pos.0 - corner.0)]),
+      None => self.default_light(tree, corner),
+    }
+  }
+
+  /// The light in the brick containing `pos`, laid out like the brick
+  /// itself so it can go to the GPU next to it.
+  pub fn brick(&self, tree: &Hexadecitree, pos: BlockPos) -> BrickLight<'_> {
+    let corner = Hexadecitree::brick_corner(pos);
+    match self.bricks.get(&corner) {
+      Some(brick) => BrickLight::Mixed(brick),
+      None => BrickLight::Uniform(
+        self.default_light(tree, corner).unwrap_or(Light::DARK),
+      ),
+    }
+  }
+
+  /// How many bricks store their light foxel by foxel.
+  pub fn stored_bricks(&self) -> usize {
+    self.bricks.len()
+  }
+
+  /// Work out all the light from scratch.
+  pub fn rebuild(&mut self, tree: &Hexadecitree) {
+    self.bricks.clear();
+    self.sky_floors.clear();
+    let lowest = Hexadecitree::MIN_COORD;
+    for column in brick_columns() {
+      let floor = find_sky_floor(tree, column);
+      if floor != lowest {
+        self.sky_floors.insert(column, floor);
+      }
+    }
+
+    // Sky comes in wherever an open brick touches one that isn't
+    let mut sky = VecDeque::new();
+    for column in brick_columns() {
+      let [y, z, w] = column;
+      let floor = self.sky_floor(column);
+      if floor > Hexadecitree::MAX_COORD {
+        continue;
+      }
+      if floor > lowest {
+        push_face(&mut sky, BlockPos::new(floor, y, z, w), 0, false);
+      }
+      for (axis, positive) in iproduct!(1..4, [false, true]) {
+        let mut other = column;
+        other[axis - 1] += if positive { FAB } else { -FAB };
+        let other_floor = self.sky_floor(other);
+        for x in (floor..other_floor).step_by(FAB as usize) {
+          push_face(&mut sky, BlockPos::new(x, y, z, w), axis, positive);
+        }
+      }
+    }
+    self.spread(tree, LightChannel::Sky, sky);
+
+    let mut block = VecDeque::new();
+    for (corner, brick) in tree.bricks() {
+      match brick {
+        BrickRef::Solid(f) if self.props(f).emits > 0 => {
+          for (axis, positive) in iproduct!(0..4, [false, true]) {
+            push_face(&mut block, corner, axis, positive);
+          }
+        }
+        BrickRef::Solid(_) => {}
+        BrickRef::Ref(b) => {
+          for (idx, f) in b.0.iter().enumerate() {
+            let emits = self.props(f.decode()).emits;
+            if emits > 0 {
+              let pos = BlockPos(corner.0 + Brick::idx_to_offset(idx));
+              self.set(tree, pos, LightChannel::Block, emits);
+              block.push_back(pos);
+            }
+          }
+        }
+      }
+    }
+    self.spread(tree, LightChannel::Block, block);
+  }
+
+  /// Fix up the light after the foxel at `pos` changed, which it already
+  /// has in `tree`.
+  ///
+  /// After writing whole bricks, call this for every foxel that changed,
+  /// like `World::wake_changed` does.
+  pub fn foxel_changed(&mut self, tree: &Hexadecitree, pos: BlockPos) {
+    let corner = Hexadecitree::brick_corner(pos);
+    let column = [corner.y, corner.z, corner.w];
+    let old_floor = self.sky_floor(column);
+    let new_floor = find_sky_floor(tree, column);
+    if new_floor != old_floor {
+      // Bricks that just got covered up were fully lit, so write that
+      // down before they stop counting as open
+      for x in (old_floor..new_floor).step_by(FAB as usize) {
+        let covered = BlockPos::new(x, column[0], column[1], column[2]);
+        self
+          .bricks
+          .entry(covered)
+          .or_insert(Box::new([Light::SKY; PER_BRICK]));
+      }
+      if new_floor == Hexadecitree::MIN_COORD {
+        self.sky_floors.remove(&column);
+      } else {
+        self.sky_floors.insert(column, new_floor);
+      }
+    }
+
+    let Some(foxel) = tree.get(pos) else {
+      return;
+    };
+    for channel in [LightChannel::Sky, LightChannel::Block] {
+      let mut refill = self.unspread(tree, channel, pos);
+      if channel == LightChannel::Block && self.props(foxel).emits > 0 {
+        self.set(tree, pos, channel, self.props(foxel).emits);
+        refill.push_back(pos);
+      }
+      // Let whatever's around light it back up
+      refill.extend(DIRS.iter().map(|d| BlockPos(pos.0 + *d)));
+      self.spread(tree, channel, refill);
+    }
+  }
+
+  /// Darken `pos` and everything that got its light through it. Return
+  /// the lit foxels around the edge of the darkness, to spread back in from.
+  fn unspread(
+    &mut self,
+    tree: &Hexadecitree,
+    channel: LightChannel,
+    pos: BlockPos,
+  ) -> VecDeque<BlockPos> {
+    let mut refill = VecDeque::new();
+    let Some(old) = self.get(tree, pos) else {
+      return refill;
+    };
+    let mut dark = VecDeque::from([(pos, old.get(channel))]);
+    self.set(tree, pos, channel, 0);
+    while let Some((pos, level)) = dark.pop_front() {
+      for dir in DIRS {
+        let next = BlockPos(pos.0 + dir);
+        let Some(light) = self.get(tree, next) else {
+          continue;
+        };
+        let next_level = light.get(channel);
+        if next_level == 0 {
+          continue;
+        }
+        let straight_down = channel == LightChannel::Sky
+          && dir == DOWN
+          && level == Light::MAX
+          && next_level == Light::MAX;
+        if next_level < level || straight_down {
+          self.set(tree, next, channel, 0);
+          dark.push_back((next, next_level));
+          // Glowing things keep glowing
+          let emits = tree.get(next).map_or(0, |f| self.props(f).emits);
+          if channel == LightChannel::Block && emits > 0 {
+            self.set(tree, next, channel, emits);
+            refill.push_back(next);
+          }
+        } else {
+          refill.push_back(next);
+        }
+      }
+    }
+    refill
+  }
+
+  fn spread(
+    &mut self,
+    tree: &Hexadecitree,
+    channel: LightChannel,
+    mut queue: VecDeque<BlockPos>,
+  ) {
+    while let Some(pos) = queue.pop_front() {
+      let Some(level) = self.get(tree, pos).map(|l| l.get(channel)) else {
+        continue;
+      };
+      if level <= 1 {
+        continue;
+      }
+      for dir in DIRS {
+        let next = BlockPos(pos.0 + dir);
+        let Some(foxel) = tree.get(next) else {
+          continue;
+        };
+        let props = self.props(foxel);
+        if props.opaque {
+          continue;
+        }
+        let straight_down = channel == LightChannel::Sky
+          && dir == DOWN
+          && level == Light::MAX
+          && props.extra_loss == 0;
+        let new_level = if straight_down {
+          Light::MAX
+        } else {
+          level.saturating_sub(1 + props.extra_loss)
+        };
+        let current = self.get(tree, next).unwrap_or_default().get(channel);
+        if new_level > current {
+          self.set(tree, next, channel, new_level);
+          queue.push_back(next);
+        }
+      }
+    }
+  }
+
+  fn set(
+    &mut self,
+    tree: &Hexadecitree,
+    pos: BlockPos,
+    channel: LightChannel,
+    level: u8,
+  ) {
+    let corner = Hexadecitree::brick_corner(pos);
+    if !self.bricks.contains_key(&corner) {
+      let fill = self.default_light(tree, corner).unwrap_or(Light::DARK);
+      self.bricks.insert(corner, Box::new([fill; PER_BRICK]));
+    }
+    let brick = self.bricks.get_mut(&corner).unwrap();
+    let slot = &mut brick[Brick::offset_to_idx(pos.0 - corner.0)];
+    *slot = slot.with(channel, level);
+  }
+
+  /// The light of a brick that isn't stored.
+  fn default_light(
+    &self,
+    tree: &Hexadecitree,
+    corner: BlockPos,
+  ) -> Option<Light> {
+    Some(match tree.brick_at(corner)? {
+      BrickRef::Solid(f) if f == Foxel::AIR => {
+        if corner.x >= self.sky_floor([corner.y, corner.z, corner.w]) {
+          Light::SKY
+        } else {
+          Light::DARK
+        }
+      }
+      BrickRef::Solid(f) => Light::new(0, self.props(f).emits),
+      BrickRef::Ref(_) => Light::DARK,
+    })
+  }
+
+  fn sky_floor(&self, column: [i32; 3]) -> i32 {
+    (self.sky_floors.get(&column).copied()).unwrap_or(Hexadecitree::MIN_COORD)
+  }
+
+  fn props(&self, foxel: Foxel) -> LightProps {
+    self.props[foxel.id() as usize]
+  }
+}
+
+impl Default for LightMap {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+/// The Y/Z/W of the corner of every column of bricks in the world.
+fn brick_columns() -> impl Iterator<Item = [i32; 3]> {
+  let baw = Hexadecitree::BRICKS_ACROSS_WORLD as i32;
+  iproduct!(0..baw, 0..baw, 0..baw)
+    .map(|(y, z, w)| [y, z, w].map(|v| v * FAB + Hexadecitree::MIN_COORD))
+}
+
+/// The X of the lowest brick in the column with only air bricks above it,
+/// or just past the top of the world if the top brick isn't air.
+fn find_sky_floor(tree: &Hexadecitree, [y, z, w]: [i32; 3]) -> i32 {
+  let mut floor = Hexadecitree::MAX_COORD + 1;
+  while floor > Hexadecitree::MIN_COORD {
+    let below = BlockPos::new(floor - FAB, y, z, w);
+    match tree.brick_at(below) {
+      Some(BrickRef::Solid(f)) if f == Foxel::AIR => floor -= FAB,
+      _ => break,
+    }
+  }
+  floor
+}
+
+/// Queue every foxel on one face of the brick at `corner`.
+fn push_face(
+  queue: &mut VecDeque<BlockPos>,
+  corner: BlockPos,
+  axis: usize,
+  positive: bool,
+) {
+  let range = |a: usize| match (a == axis, positive) {
+    (false, _) => 0..=FAB - 1,
+    (true, false) => 0..=0,
+    (true, true) => FAB - 1..=FAB - 1,
+  };
+  for (x, y, z, w) in iproduct!(range(0), range(1), range(2), range(3)) {
+    queue.push_back(BlockPos(corner.0 + IVec4::new(x, y, z, w)));
+  }
+}
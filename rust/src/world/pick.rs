@@ -0,0 +1,88 @@
+//! Working out which foxel is under a point on the screen, for placing and
+//! breaking.
+
+use ultraviolet::{Vec2, Vec4};
+
+use crate::{
+  math::{hexadecitree::SetFoxelError, BlockPos},
+  render::Camera,
+};
+
+use super::{foxel::Foxel, World};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pick {
+  pub foxel: Foxel,
+  pub pos: BlockPos,
+  /// Which way the face that got looked at points.
+  pub normal: Vec4,
+  /// In front of that face, where something placed would go.
+  pub place_pos: BlockPos,
+  /// How far it is from the camera.
+  pub dist: f32,
+}
+
+impl World {
+  /// How far away things can be picked from by default.
+  pub const REACH: f32 = 8.0;
+
+  /// The foxel under `uv` on a screen `aspect` times wider than it is
+  /// tall, if there's one within `reach` of the camera. Goes along the same
+  /// ray `show_world.gdshader` draws that point with, so it's whatever's
+  /// drawn there.
+  ///
+  /// Anything translucent counts, even if the shader draws what's behind it
+  /// through it.
+  pub fn pick(
+    &self,
+    camera: &Camera,
+    uv: Vec2,
+    aspect: f32,
+    reach: f32,
+  ) -> Option<Pick> {
+    let (start, dir) = camera.ray(uv, aspect);
+    let hit = self.foxels.raycast(start, dir, reach - camera.focal_dist)?;
+    Some(Pick {
+      foxel: hit.foxel,
+      pos: hit.pos,
+      normal: hit.normal,
+      place_pos: hit.prev,
+      dist: hit.dist + camera.focal_dist,
+    })
+  }
+
+  /// Break whatever `pick` finds, giving back what it was.
+  pub fn break_picked(
+    &mut self,
+    camera: &Camera,
+    uv: Vec2,
+    aspect: f32,
+    reach: f32,
+  ) -> Result<Option<Foxel>, SetFoxelError> {
+    let Some(pick) = self.pick(camera, uv, aspect, reach) else {
+      return Ok(None);
+    };
+    self.set_foxel(pick.pos, Foxel::AIR)?;
+    Ok(Some(pick.foxel))
+  }
+
+  /// Put `foxel` against the face `pick` finds, giving back where it went.
+  /// Nothing happens if there's something there already.
+  pub fn place_picked(
+    &mut self,
+    camera: &Camera,
+    uv: Vec2,
+    aspect: f32,
+    reach: f32,
+    foxel: Foxel,
+  ) -> Result<Option<BlockPos>, SetFoxelError> {
+    let Some(pick) = self.pick(camera, uv, aspect, reach) else {
+      return Ok(None);
+    };
+    if self.foxels.get(pick.place_pos) != Some(Foxel::AIR) {
+      return Ok(None);
+    }
+    self.set_foxel(pick.place_pos, foxel)?;
+    Ok(Some(pick.place_pos))
+  }
+}
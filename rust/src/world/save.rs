@@ -0,0 +1,148 @@
+//! Saving and loading worlds.
+//!
+//! The format is a little hand-rolled binary thing, all little-endian:
+//! - `TSRW`
+//! - version, as a u16
+//! - the sun direction, as 4 f32s
+//! - the foxels, see `Hexadecitree::write_to`
+//! - the foxel entities, see `EntityStore::write_to`. Version 1 saves
+//!   don't have these.
+
+use std::{
+  fs::File,
+  io::{self, BufReader, BufWriter, Read, Write},
+  path::Path,
+};
+
+use ultraviolet::Vec4;
+
+use crate::math::hexadecitree::Hexadecitree;
+
+use super::{
+  ao::AoCache,
+  entity::EntityStore,
+  light::LightMap,
+  sim::{FluidSim, GranularSim},
+  World,
+};
+
+const MAGIC: &[u8; 4] = b"TSRW";
+const VERSION: u16 = 2;
+
+#[derive(Debug)]
+pub enum LoadError {
+  Io(io::Error),
+  BadMagic,
+  UnknownVersion(u16),
+  /// The data doesn't make sense; says what was wrong with it.
+  Corrupt(&'static str),
+}
+
+impl From<io::Error> for LoadError {
+  fn from(e: io::Error) -> Self {
+    match e.kind() {
+      io::ErrorKind::UnexpectedEof => LoadError::Corrupt("ended early"),
+      _ => LoadError::Io(e),
+    }
+  }
+}
+
+impl World {
+  pub fn save(&self, mut w: impl Write) -> io::Result<()> {
+    w.write_all(MAGIC)?;
+    write_u16(&mut w, VERSION)?;
+    for v in self.sun_dir.as_array() {
+      write_f32(&mut w, *v)?;
+    }
+    self.foxels.write_to(&mut w)?;
+    self.entities.write_to(&mut w)?;
+    w.flush()
+  }
+
+  pub fn load(mut r: impl Read) -> Result<World, LoadError> {
+    let mut magic = [0; 4];
+    r.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+      return Err(LoadError::BadMagic);
+    }
+    let version = read_u16(&mut r)?;
+    if !(1..=VERSION).contains(&version) {
+      return Err(LoadError::UnknownVersion(version));
+    }
+
+    let sun_dir = Vec4::new(
+      read_f32(&mut r)?,
+      read_f32(&mut r)?,
+      read_f32(&mut r)?,
+      read_f32(&mut r)?,
+    );
+    let mut foxels = Hexadecitree::read_from(&mut r)?;
+    foxels.track_changes(true);
+    let mut entities = EntityStore::new();
+    if version >= 2 {
+      entities.read_from(&mut r)?;
+    }
+    let mut fluids = FluidSim::new();
+    fluids.wake_all(&foxels);
+    let mut light = LightMap::new();
+    light.rebuild(&foxels);
+    Ok(World {
+      foxels,
+      entities,
+      light,
+      ao: AoCache::new(),
+      fluids,
+      granular: GranularSim::new(),
+      automaton: None,
+      sun_dir,
+    })
+  }
+
+  pub fn save_to_file(&self, path: impl AsRef<Path>) -> io::Result<()> {
+    self.save(BufWriter::new(File::create(path)?))
+  }
+
+  pub fn load_from_file(path: impl AsRef<Path>) -> Result<World, LoadError> {
+    World::load(BufReader::new(File::open(path)?))
+  }
+}
+
+pub(crate) fn write_u16(w: &mut impl Write, v: u16) -> io::Result<()> {
+  w.write_all(&v.to_le_bytes())
+}
+
+pub(crate) fn write_u32(w: &mut impl Write, v: u32) -> io::Result<()> {
+  w.write_all(&v.to_le_bytes())
+}
+
+pub(crate) fn write_i32(w: &mut impl Write, v: i32) -> io::Result<()> {
+  w.write_all(&v.to_le_bytes())
+}
+
+pub(crate) fn write_f32(w: &mut impl Write, v: f32) -> io::Result<()> {
+  w.write_all(&v.to_le_bytes())
+}
+
+pub(crate) fn read_u16(r: &mut impl Read) -> io::Result<u16> {
+  let mut buf = [0; 2];
+  r.read_exact(&mut buf)?;
+  Ok(u16::from_le_bytes(buf))
+}
+
+pub(crate) fn read_u32(r: &mut impl Read) -> io::Result<u32> {
+  let mut buf = [0; 4];
+  r.read_exact(&mut buf)?;
+  Ok(u32::from_le_bytes(buf))
+}
+
+pub(crate) fn read_i32(r: &mut impl Read) -> io::Result<i32> {
+  let mut buf = [0; 4];
+  r.read_exact(&mut buf)?;
+  Ok(i32::from_le_bytes(buf))
+}
+
+pub(crate) fn read_f32(r: &mut impl Read) -> io::Result<f32> {
+  let mut buf = [0; 4];
+  r.read_exact(&mut buf)?;
+  Ok(f32::from_le_bytes(buf))
+}
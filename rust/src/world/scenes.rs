@@ -0,0 +1,304 @@
+//! Named ways to fill up a fresh world.
+//!
+//! The game picks one with `--scene name[:seed]` after the `--` on the
+//! command line, or the `TESSERACTORY_SCENE` environment variable.
+//! Tests can build exactly the one they want by name.
+
+use std::str::FromStr;
+
+use itertools::iproduct;
+use ultraviolet::Vec4;
+
+use crate::math::{hexadecitree::SetFoxelError, BlockPos};
+
+use super::{
+  foxel::Foxel,
+  gen::{CaveParams, DecorationParams, TerrainParams},
+  sim::Automaton,
+  World,
+};
+
+pub type SceneBuildFn = fn(&mut World, seed: u64) -> Result<(), SetFoxelError>;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Scene {
+  pub name: &'static str,
+  pub description: &'static str,
+  pub build: SceneBuildFn,
+}
+
+#[derive(Debug, Clone)]
+pub struct SceneRegistry {
+  scenes: Vec<Scene>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SceneError {
+  Unknown(String),
+  BadSeed(String),
+  Build(SetFoxelError),
+}
+
+impl SceneRegistry {
+  pub const DEFAULT_SCENE: &'static str = "axis-cross";
+  pub const ENV_VAR: &'static str = "TESSERACTORY_SCENE";
+
+  pub fn empty() -> Self {
+    Self { scenes: Vec::new() }
+  }
+
+  /// All the scenes that come with the game.
+  pub fn builtin() -> Self {
+    let mut reg = Self::empty();
+    reg.register(Scene {
+      name: "empty",
+      description: "Nothing at all",
+      build: |_, _| Ok(()),
+    });
+    reg.register(Scene {
+      name: "axis-cross",
+      description: "A line of foxels down each axis, for getting your bearings",
+      build: axis_cross,
+    });
+    reg.register(Scene {
+      name: "fractal",
+      description: "A 4D Menger sponge",
+      build: fractal,
+    });
+    reg.register(Scene {
+      name: "terrain",
+      description: "Hills, caves and trees. Takes a seed",
+      build: terrain,
+    });
+    reg.register(Scene {
+      name: "pools",
+      description: "Water and lava pouring into a stone basin",
+      build: pools,
+    });
+    reg.register(Scene {
+      name: "life",
+      description: "A 4D Life soup that keeps running. Takes a seed",
+      build: life,
+    });
+    reg
+  }
+
+  /// Replaces any scene already registered with the same name.
+  pub fn register(&mut self, scene: Scene) {
+    match self.scenes.iter_mut().find(|s| s.name == scene.name) {
+      Some(slot) => *slot = scene,
+      None => self.scenes.push(scene),
+    }
+  }
+
+  pub fn get(&self, name: &str) -> Option<&Scene> {
+    self.scenes.iter().find(|s| s.name == name)
+  }
+
+  pub fn scenes(&self) -> &[Scene] {
+    &self.scenes
+  }
+
+  /// Build the scene into a world that's already been made.
+  pub fn build_into(
+    &self,
+    spec: &SceneSpec,
+    world: &mut World,
+  ) -> Result<(), SceneError> {
+    let scene = self
+      .get(&spec.name)
+      .ok_or_else(|| SceneError::Unknown(spec.name.clone()))?;
+    // Building makes far too many changes to bother remembering
+    world.foxels.track_changes(false);
+    let built = (scene.build)(world, spec.seed).map_err(SceneError::Build);
+    world.foxels.track_changes(true);
+    built?;
+    world.fluids.wake_all(&world.foxels);
+    world.light.rebuild(&world.foxels);
+    world.ao.clear();
+    Ok(())
+  }
+
+  pub fn build(
+    &self,
+    spec: &SceneSpec,
+    sun_dir: Vec4,
+  ) -> Result<World, SceneError> {
+    let mut world = World::new(sun_dir);
+    self.build_into(spec, &mut world)?;
+    Ok(world)
+  }
+}
+
+impl Default for SceneRegistry {
+  fn default() -> Self {
+    Self::builtin()
+  }
+}
+
+/// Which scene to build, and the seed to build it with.
+///
+/// Written as `name` or `name:seed`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SceneSpec {
+  pub name: String,
+  pub seed: u64,
+}
+
+impl SceneSpec {
+  pub fn new(name: impl Into<String>, seed: u64) -> Self {
+    Self {
+      name: name.into(),
+      seed,
+    }
+  }
+
+  /// Find the scene to use from the command line, falling back to the
+  /// environment variable, and then to the default.
+  ///
+  /// `args` should be just the user arguments, not the whole command line.
+  pub fn from_config(
+    args: impl IntoIterator<Item = String>,
+    env: Option<String>,
+  ) -> Result<Self, SceneError> {
+    let mut args = args.into_iter();
+    let mut from_args = None;
+    while let Some(arg) = args.next() {
+      if let Some(spec) = arg.strip_prefix("--scene=") {
+        from_args = Some(spec.to_owned());
+      } else if arg == "--scene" {
+        from_args = args.next();
+      }
+    }
+
+    match from_args.or(env) {
+      Some(spec) => spec.parse(),
+      None => Ok(Self::default()),
+    }
+  }
+}
+
+impl Default for SceneSpec {
+  fn default() -> Self {
+    Self::new(SceneRegistry::DEFAULT_SCENE, 0)
+  }
+}
+
+impl FromStr for SceneSpec {
+  type Err = SceneError;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let s = s.trim();
+    match s.split_once(':') {
+      None => Ok(Self::new(s, 0)),
+      Some((name, seed)) => {
+        let seed = seed
+          .parse()
+          .map_err(|_| SceneError::BadSeed(seed.to_owned()))?;
+        Ok(Self::new(name, seed))
+      }
+    }
+  }
+}
+
+fn axis_cross(world: &mut World, _seed: u64) -> Result<(), SetFoxelError> {
+  let f = &mut world.foxels;
+  let [red, green, blue, rb] = ["red", "green", "blue", "rb"].map(Foxel::named);
+  f.set(BlockPos::new(0, 0, 0, 0), Foxel::named("white"))?;
+  for v in 1..10 {
+    f.set(BlockPos::new(v, 0, 0, 0), red)?;
+    f.set(BlockPos::new(0, v, 0, 0), green)?;
+    f.set(BlockPos::new(0, 0, v, 0), blue)?;
+    f.set(BlockPos::new(0, 0, 0, v), rb)?;
+  }
+
+  f.set(BlockPos::new(3, 3, 3, 0), Foxel::named("gb"))?;
+  Ok(())
+}
+
+fn pools(world: &mut World, _seed: u64) -> Result<(), SetFoxelError> {
+  const HALF: i32 = 6;
+  let f = &mut world.foxels;
+  let [stone, water, lava] = ["stone", "water", "lava"].map(Foxel::named);
+  for (x, y, z, w) in iproduct!(-1..4, -HALF..=HALF, -HALF..=HALF, -HALF..=HALF)
+  {
+    let wall = [y, z, w].iter().any(|v| v.abs() == HALF);
+    if x == -1 || wall {
+      f.set(BlockPos::new(x, y, z, w), stone)?;
+    }
+  }
+  // A divider down the middle keeps them apart
+  for (x, z, w) in iproduct!(0..4, -HALF..=HALF, -HALF..=HALF) {
+    f.set(BlockPos::new(x, 0, z, w), stone)?;
+  }
+  for x in 4..12 {
+    f.set(BlockPos::new(x, -3, 0, 0), water)?;
+    f.set(BlockPos::new(x, 3, 0, 0), lava)?;
+  }
+  Ok(())
+}
+
+fn life(world: &mut World, seed: u64) -> Result<(), SetFoxelError> {
+  const HALF: i32 = 12;
+  let rule = match "B12-14/S10-16".parse() {
+    Ok(it) => it,
+    Err(ono) => panic!("the life scene's rule is broken: {:?}", ono),
+  };
+  let ca = Automaton::new(
+    rule,
+    Foxel::named("green"),
+    BlockPos::new(-HALF, -HALF, -HALF, -HALF),
+    BlockPos::new(HALF - 1, HALF - 1, HALF - 1, HALF - 1),
+  );
+  ca.seed(&mut world.foxels, seed, 0.15)?;
+  world.automaton = Some(ca);
+  Ok(())
+}
+
+fn fractal(world: &mut World, _seed: u64) -> Result<(), SetFoxelError> {
+  // 3^3 across; any bigger and it runs out of composite bricks
+  const SIZE: i32 = 27;
+  let f = &mut world.foxels;
+  let tints = ["red", "rg", "green", "gb", "blue"].map(Foxel::named);
+  for (x, y, z, w) in iproduct!(0..SIZE, 0..SIZE, 0..SIZE, 0..SIZE) {
+    if !in_menger_sponge([x, y, z, w]) {
+      continue;
+    }
+    // Tint by W, so you can tell which slice you're looking at
+    let foxel = tints[(w * 5 / SIZE) as usize];
+    f.set(BlockPos::new(x - SIZE / 2, y, z, w - SIZE / 2), foxel)?;
+  }
+  Ok(())
+}
+
+/// At every level, a cell is knocked out if two or more of its
+/// coordinates are in the middle third.
+fn in_menger_sponge(mut coords: [i32; 4]) -> bool {
+  while coords.iter().any(|c| *c > 0) {
+    let middles = coords.iter().filter(|c| **c % 3 == 1).count();
+    if middles >= 2 {
+      return false;
+    }
+    coords = coords.map(|c| c / 3);
+  }
+  true
+}
+
+fn terrain(world: &mut World, seed: u64) -> Result<(), SetFoxelError> {
+  let terrain = TerrainParams {
+    seed,
+    ..Default::default()
+  };
+  world.generate_terrain(terrain.clone())?;
+  world.carve_caves(CaveParams {
+    seed,
+    ..Default::default()
+  })?;
+  world.decorate(
+    DecorationParams {
+      seed,
+      ..Default::default()
+    },
+    terrain,
+  )
+}
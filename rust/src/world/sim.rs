@@ -0,0 +1,55 @@
+//! Things that change the world by themselves, a tick at a time.
+//!
+//! Ticks happen at a fixed rate, however fast or slow the game is
+//! drawing, so the same world always ends up the same way.
+
+pub mod automaton;
+pub mod fluid;
+pub mod granular;
+
+pub use automaton::*;
+pub use fluid::*;
+pub use granular::*;
+
+/// How many times a second the world ticks.
+pub const TICKS_PER_SECOND: f32 = 20.0;
+
+/// Turns however long each frame took into a whole number of ticks.
+#[derive(Debug, Clone)]
+pub struct FixedTicker {
+  tick_len: f32,
+  /// Time that hasn't been spent on a tick yet.
+  leftover: f32,
+  /// If a frame takes so long it'd need more than this, the rest is
+  /// dropped instead of trying to catch up forever.
+  max_ticks: u32,
+}
+
+impl FixedTicker {
+  pub fn new(ticks_per_second: f32) -> Self {
+    Self {
+      tick_len: ticks_per_second.recip(),
+      leftover: 0.0,
+      max_ticks: 8,
+    }
+  }
+
+  /// Return how many ticks to run for a frame that took `delta` seconds.
+  pub fn advance(&mut self, delta: f32) -> u32 {
+    self.leftover += delta.max(0.0);
+    let ticks = (self.leftover / self.tick_len).floor();
+    self.leftover -= ticks * self.tick_len;
+    if ticks > self.max_ticks as f32 {
+      self.leftover = 0.0;
+      self.max_ticks
+    } else {
+      ticks as u32
+    }
+  }
+}
+
+impl Default for FixedTicker {
+  fn default() -> Self {
+    Self::new(TICKS_PER_SECOND)
+  }
+}
@@ -0,0 +1,372 @@
+//! Birth/survival cellular automata, like Life but in 4D, over a box of the
+//! tree.
+//!
+//! Only one kind of foxel counts as alive. Air is dead, and anything else
+//! is in the way: it never changes, and counts as dead to its neighbours.
+//!
+//! A step reads the whole box into a buffer, works out the next generation
+//! into another one in parallel, then writes back a brick at a time.
+
+use std::str::FromStr;
+
+use itertools::iproduct;
+use rayon::prelude::*;
+
+use crate::{
+  math::{
+    hexadecitree::{Hexadecitree, SetFoxelError},
+    rng::hash_pos,
+    BlockPos,
+  },
+  world::foxel::Foxel,
+};
+
+const FAB: i32 = Hexadecitree::FOXELS_ACROSS_BRICK as i32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Neighbourhood {
+  /// Everything touching, even at a corner. There's 80 of them.
+  Moore,
+  /// Just the foxels sharing a face. There's 8 of them.
+  VonNeumann,
+}
+
+impl Neighbourhood {
+  pub fn size(self) -> u32 {
+    match self {
+      Neighbourhood::Moore => 80,
+      Neighbourhood::VonNeumann => 8,
+    }
+  }
+}
+
+/// Which neighbour counts make a dead foxel come alive, and which keep a
+/// live one alive.
+///
+/// Written like `B4/S4,5` or `B1-3/S2/VN`: comma-separated counts or
+/// ranges of counts, then optionally `/M` for Moore (the default) or `/VN`
+/// for von Neumann.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rule {
+  /// Bit `n` is set if `n` neighbours cause a birth.
+  birth: u128,
+  survival: u128,
+  pub neighbourhood: Neighbourhood,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RuleError {
+  /// Doesn't look like `B.../S...`
+  Malformed(String),
+  BadCount(String),
+  /// More neighbours than the neighbourhood has.
+  TooMany(u32),
+}
+
+impl Rule {
+  pub fn new(
+    birth: impl IntoIterator<Item = u32>,
+    survival: impl IntoIterator<Item = u32>,
+    neighbourhood: Neighbourhood,
+  ) -> Result<Self, RuleError> {
+    Ok(Self {
+      birth: count_mask(birth, neighbourhood)?,
+      survival: count_mask(survival, neighbourhood)?,
+      neighbourhood,
+    })
+  }
+
+  pub fn births(&self, neighbours: u32) -> bool {
+    neighbours < 128 && self.birth & 1 << neighbours != 0
+  }
+
+  pub fn survives(&self, neighbours: u32) -> bool {
+    neighbours < 128 && self.survival & 1 << neighbours != 0
+  }
+}
+
+impl FromStr for Rule {
+  type Err = RuleError;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let malformed = || RuleError::Malformed(s.to_owned());
+    let mut parts = s.split('/');
+    let birth = parts.next().and_then(|p| p.strip_prefix('B'));
+    let survival = parts.next().and_then(|p| p.strip_prefix('S'));
+    let (Some(birth), Some(survival)) = (birth, survival) else {
+      return Err(malformed());
+    };
+    let neighbourhood = match parts.next() {
+      None | Some("M") => Neighbourhood::Moore,
+      Some("VN") => Neighbourhood::VonNeumann,
+      Some(_) => return Err(malformed()),
+    };
+    if parts.next().is_some() {
+      return Err(malformed());
+    }
+    Rule::new(parse_counts(birth)?, parse_counts(survival)?, neighbourhood)
+  }
+}
+
+fn count_mask(
+  counts: impl IntoIterator<Item = u32>,
+  neighbourhood: Neighbourhood,
+) -> Result<u128, RuleError> {
+  counts.into_iter().try_fold(0, |mask, n| {
+    if n > neighbourhood.size() {
+      Err(RuleError::TooMany(n))
+    } else {
+      Ok(mask | 1 << n)
+    }
+  })
+}
+
+fn parse_counts(s: &str) -> Result<Vec<u32>, RuleError> {
+  let mut out = Vec::new();
+  for part in s.split(',').filter(|p| !p.is_empty()) {
+    let bad = || RuleError::BadCount(part.to_owned());
+    let num = |n: &str| n.parse::<u32>().map_err(|_| bad());
+    match part.split_once('-') {
+      Some((lo, hi)) => out.extend(num(lo)?..=num(hi)?),
+      None => out.push(num(part)?),
+    }
+  }
+  Ok(out)
+}
+
+/// A rule running over a box of the tree.
+#[derive(Debug, Clone)]
+pub struct Automaton {
+  pub rule: Rule,
+  pub alive: Foxel,
+  min: BlockPos,
+  max: BlockPos,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StepStats {
+  pub births: usize,
+  pub deaths: usize,
+  pub bricks_written: usize,
+}
+
+/// What's in one foxel of the box, as far as the rule cares.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Cell {
+  Dead,
+  Alive,
+  InTheWay,
+}
+
+impl Automaton {
+  /// Runs between the two corners, inclusive.
+  pub fn new(rule: Rule, alive: Foxel, a: BlockPos, b: BlockPos) -> Self {
+    Self {
+      rule,
+      alive,
+      min: BlockPos(a.0.min_by_component(b.0)),
+      max: BlockPos(a.0.max_by_component(b.0)),
+    }
+  }
+
+  pub fn min(&self) -> BlockPos {
+    self.min
+  }
+
+  pub fn max(&self) -> BlockPos {
+    self.max
+  }
+
+  /// Bring air in the box to life with the given chance, the same way
+  /// every time for the same seed.
+  pub fn seed(
+    &self,
+    tree: &mut Hexadecitree,
+    seed: u64,
+    density: f32,
+  ) -> Result<(), SetFoxelError> {
+    let threshold = (density.clamp(0.0, 1.0) as f64 * u64::MAX as f64) as u64;
+    for idx in 0..self.len() {
+      let pos = self.pos_of(idx);
+      if hash_pos(seed, pos.0) < threshold && tree.get(pos) == Some(Foxel::AIR)
+      {
+        tree.set(pos, self.alive)?;
+      }
+    }
+    Ok(())
+  }
+
+  /// Run one generation.
+  pub fn step(
+    &self,
+    tree: &mut Hexadecitree,
+  ) -> Result<StepStats, SetFoxelError> {
+    let cells = (0..self.len())
+      .into_par_iter()
+      .map(|idx| match tree.get(self.pos_of(idx)) {
+        Some(f) if f == self.alive => Cell::Alive,
+        Some(f) if f == Foxel::AIR => Cell::Dead,
+        _ => Cell::InTheWay,
+      })
+      .collect::<Vec<_>>();
+    let counts = self.neighbour_counts(&cells);
+
+    let next = (cells.par_iter().zip(counts.par_iter()))
+      .map(|(cell, count)| match cell {
+        Cell::Alive if !self.rule.survives(*count as u32) => Cell::Dead,
+        Cell::Dead if self.rule.births(*count as u32) => Cell::Alive,
+        other => *other,
+      })
+      .collect::<Vec<_>>();
+
+    let mut stats = StepStats::default();
+    for (before, after) in cells.iter().zip(next.iter()) {
+      match (before, after) {
+        (Cell::Dead, Cell::Alive) => stats.births += 1,
+        (Cell::Alive, Cell::Dead) => stats.deaths += 1,
+        _ => {}
+      }
+    }
+    if stats.births == 0 && stats.deaths == 0 {
+      return Ok(stats);
+    }
+
+    // Build the changed bricks in parallel, then write them in one go
+    let corners = self.brick_corners().collect::<Vec<_>>();
+    let tree_ref = &*tree;
+    let bricks = corners
+      .into_par_iter()
+      .filter_map(|corner| {
+        let mut brick = tree_ref.brick_at(corner)?.to_brick();
+        let mut changed = false;
+        for (x, y, z, w) in iproduct!(0..FAB, 0..FAB, 0..FAB, 0..FAB) {
+          let pos = BlockPos::new(x, y, z, w);
+          let Some(idx) = self.idx_of(BlockPos(corner.0 + pos.0)) else {
+            continue;
+          };
+          if cells[idx] != next[idx] {
+            let foxel = match next[idx] {
+              Cell::Alive => self.alive,
+              _ => Foxel::AIR,
+            };
+            brick.set(pos.0, foxel);
+            changed = true;
+          }
+        }
+        changed.then_some((corner, brick))
+      })
+      .collect::<Vec<_>>();
+
+    stats.bricks_written = bricks.len();
+    for (corner, brick) in bricks {
+      tree.set_brick(corner, &brick)?;
+    }
+    Ok(stats)
+  }
+
+  fn dims(&self) -> [usize; 4] {
+    let size = self.max.0 - self.min.0;
+    size.as_array().map(|v| v as usize + 1)
+  }
+
+  fn len(&self) -> usize {
+    self.dims().iter().product()
+  }
+
+  /// How far apart neighbours along each axis are in the buffers.
+  /// X is the most significant, like everywhere else.
+  fn strides(&self) -> [usize; 4] {
+    let [_, dy, dz, dw] = self.dims();
+    [dy * dz * dw, dz * dw, dw, 1]
+  }
+
+  fn pos_of(&self, idx: usize) -> BlockPos {
+    let dims = self.dims();
+    let strides = self.strides();
+    let mut offset = [0; 4];
+    for axis in 0..4 {
+      offset[axis] = (idx / strides[axis] % dims[axis]) as i32;
+    }
+    BlockPos(self.min.0 + offset.into())
+  }
+
+  fn idx_of(&self, pos: BlockPos) -> Option<usize> {
+    let dims = self.dims();
+    let strides = self.strides();
+    let offset = pos.0 - self.min.0;
+    let mut idx = 0;
+    for axis in 0..4 {
+      let v = offset[axis];
+      if v < 0 || v as usize >= dims[axis] {
+        return None;
+      }
+      idx += v as usize * strides[axis];
+    }
+    Some(idx)
+  }
+
+  fn brick_corners(&self) -> impl Iterator<Item = BlockPos> {
+    let lo = Hexadecitree::brick_corner(self.min);
+    let hi = Hexadecitree::brick_corner(self.max);
+    let range = move |axis: usize| (lo[axis]..=hi[axis]).step_by(FAB as usize);
+    iproduct!(range(0), range(1), range(2), range(3))
+      .map(|(x, y, z, w)| BlockPos::new(x, y, z, w))
+  }
+
+  /// How many live neighbours each foxel has. Nothing outside the box
+  /// counts.
+  fn neighbour_counts(&self, cells: &[Cell]) -> Vec<u8> {
+    let dims = self.dims();
+    let strides = self.strides();
+    let alive = cells
+      .par_iter()
+      .map(|c| (*c == Cell::Alive) as u8)
+      .collect::<Vec<_>>();
+
+    match self.rule.neighbourhood {
+      Neighbourhood::Moore => {
+        // A 3^4 box sum is four 3-wide sums, one along each axis.
+        // Then take away the foxel itself.
+        let mut sums = alive.clone();
+        let mut scratch = vec![0; sums.len()];
+        for axis in 0..4 {
+          box_sum_along(&sums, &mut scratch, dims[axis], strides[axis]);
+          std::mem::swap(&mut sums, &mut scratch);
+        }
+        (sums.par_iter_mut().zip(alive.par_iter())).for_each(|(s, a)| *s -= a);
+        sums
+      }
+      Neighbourhood::VonNeumann => (0..alive.len())
+        .into_par_iter()
+        .map(|idx| {
+          let mut count = 0;
+          for axis in 0..4 {
+            let along = idx / strides[axis] % dims[axis];
+            if along > 0 {
+              count += alive[idx - strides[axis]];
+            }
+            if along + 1 < dims[axis] {
+              count += alive[idx + strides[axis]];
+            }
+          }
+          count
+        })
+        .collect(),
+    }
+  }
+}
+
+/// Each foxel and its two neighbours along one axis, added up.
+fn box_sum_along(src: &[u8], dst: &mut [u8], dim: usize, stride: usize) {
+  dst.par_iter_mut().enumerate().for_each(|(idx, out)| {
+    let along = idx / stride % dim;
+    let mut sum = src[idx];
+    if along > 0 {
+      sum += src[idx - stride];
+    }
+    if along + 1 < dim {
+      sum += src[idx + stride];
+    }
+    *out = sum;
+  });
+}
@@ -0,0 +1,283 @@
+//! Water, lava and anything else that flows.
+//!
+//! Each fluid foxel has a level from 1 to `MAX_LEVEL` in its state, where
+//! no state means full. Fluid falls along -X, and whatever can't fall
+//! spreads out over the Y, Z and W neighbours. Fluid is never made or
+//! destroyed, just moved around.
+//!
+//! Only bricks near something that changed get looked at, and everything
+//! is done in sorted order, so the same world always flows the same way.
+
+use ahash::AHashSet;
+use ultraviolet::IVec4;
+
+use crate::{
+  math::{
+    hexadecitree::{
+      reprs::{Brick, BrickRef},
+      state::FoxelState,
+      Hexadecitree, SetFoxelError,
+    },
+    BlockPos,
+  },
+  world::foxel::Foxel,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FluidKind {
+  pub foxel: Foxel,
+  /// Only flows on every this many ticks; thicker fluids are slower.
+  pub every: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct FluidSim {
+  kinds: Vec<FluidKind>,
+  /// Corners of the bricks to look at next tick.
+  active: AHashSet<BlockPos>,
+  ticks: u64,
+}
+
+/// Which way fluid spreads once it can't fall.
+const SIDEWAYS: [IVec4; 6] = [
+  IVec4::new(0, 1, 0, 0),
+  IVec4::new(0, -1, 0, 0),
+  IVec4::new(0, 0, 1, 0),
+  IVec4::new(0, 0, -1, 0),
+  IVec4::new(0, 0, 0, 1),
+  IVec4::new(0, 0, 0, -1),
+];
+
+impl FluidSim {
+  pub const MAX_LEVEL: u16 = 8;
+
+  /// A sim that knows about water and lava.
+  pub fn new() -> Self {
+    let mut sim = Self {
+      kinds: Vec::new(),
+      active: AHashSet::new(),
+      ticks: 0,
+    };
+    sim.register(Foxel::named("water"), 1);
+    sim.register(Foxel::named("lava"), 4);
+    sim
+  }
+
+  /// Make a foxel flow, every `every` ticks.
+  pub fn register(&mut self, foxel: Foxel, every: u32) {
+    let kind = FluidKind {
+      foxel,
+      every: every.max(1),
+    };
+    match self.kinds.iter_mut().find(|k| k.foxel == foxel) {
+      Some(slot) => *slot = kind,
+      None => self.kinds.push(kind),
+    }
+  }
+
+  pub fn kind(&self, foxel: Foxel) -> Option<&FluidKind> {
+    self.kinds.iter().find(|k| k.foxel == foxel)
+  }
+
+  pub fn is_fluid(&self, foxel: Foxel) -> bool {
+    self.kind(foxel).is_some()
+  }
+
+  /// How full a fluid foxel with this state is.
+  pub fn level(state: FoxelState) -> u16 {
+    if state.is_none() {
+      Self::MAX_LEVEL
+    } else {
+      state.0.min(Self::MAX_LEVEL)
+    }
+  }
+
+  /// The state to give a fluid foxel to make it this full.
+  pub fn level_state(level: u16) -> FoxelState {
+    if level >= Self::MAX_LEVEL {
+      FoxelState::NONE
+    } else {
+      FoxelState(level)
+    }
+  }
+
+  /// Something changed at `pos`, so look at it and its neighbours next
+  /// tick.
+  pub fn wake(&mut self, pos: BlockPos) {
+    let fab = Hexadecitree::FOXELS_ACROSS_BRICK as i32;
+    let corner = Hexadecitree::brick_corner(pos);
+    self.active.insert(corner);
+    // Neighbours across a brick face are in another brick
+    for axis in 0..4 {
+      let offset = pos[axis] - corner[axis];
+      let step = match offset {
+        0 => -fab,
+        o if o == fab - 1 => fab,
+        _ => continue,
+      };
+      let mut other = corner;
+      other.0[axis] += step;
+      self.active.insert(other);
+    }
+  }
+
+  /// Look at every brick with fluid in it next tick, like after loading.
+  pub fn wake_all(&mut self, tree: &Hexadecitree) {
+    for (corner, brick) in tree.bricks() {
+      let has_fluid = match brick {
+        BrickRef::Solid(f) => self.is_fluid(f),
+        BrickRef::Ref(b) => b.0.iter().any(|f| self.is_fluid(f.decode())),
+      };
+      if has_fluid {
+        self.active.insert(corner);
+      }
+    }
+  }
+
+  /// How many bricks will be looked at next tick.
+  pub fn active_bricks(&self) -> usize {
+    self.active.len()
+  }
+
+  pub fn ticks(&self) -> u64 {
+    self.ticks
+  }
+
+  /// Let everything flow for one tick.
+  ///
+  /// If there's no room in the tree for where some fluid wants to go, it
+  /// stays put and tries again next tick, and the first error is returned
+  /// once everything else has flowed.
+  pub fn step(&mut self, tree: &mut Hexadecitree) -> Result<(), SetFoxelError> {
+    let tick = self.ticks;
+    self.ticks += 1;
+
+    // Whether a foxel is fluid, and if so whether it flows this tick
+    let kinds = &self.kinds;
+    let due = |foxel: Foxel| {
+      let kind = kinds.iter().find(|k| k.foxel == foxel)?;
+      Some(tick.checked_rem(kind.every as u64) == Some(0))
+    };
+    let mut cells = Vec::new();
+    // Bricks with slow fluid that isn't due yet have to stay awake
+    let mut waiting = Vec::new();
+    for corner in std::mem::take(&mut self.active) {
+      match tree.brick_at(corner) {
+        Some(BrickRef::Solid(f)) => match due(f) {
+          Some(true) => cells.extend(
+            (0..Hexadecitree::FOXELS_PER_BRICK as usize)
+              .map(|idx| BlockPos(corner.0 + Brick::idx_to_offset(idx))),
+          ),
+          Some(false) => waiting.push(corner),
+          None => {}
+        },
+        Some(BrickRef::Ref(b)) => {
+          for (idx, f) in b.0.iter().enumerate() {
+            match due(f.decode()) {
+              Some(true) => {
+                cells.push(BlockPos(corner.0 + Brick::idx_to_offset(idx)))
+              }
+              Some(false) => waiting.push(corner),
+              None => {}
+            }
+          }
+        }
+        None => {}
+      }
+    }
+    self.active.extend(waiting);
+    // Lowest first, so falling fluid lands on fluid that's already moved
+    cells.sort_unstable_by_key(|pos| pos.as_array());
+
+    let mut changed = AHashSet::new();
+    let mut failed = None;
+    for pos in cells {
+      // Fluid that's just moved in waits for next tick
+      if changed.contains(&pos) {
+        continue;
+      }
+      if let Err(err) = flow(tree, pos, &mut changed) {
+        self.wake(pos);
+        failed.get_or_insert(err);
+      }
+    }
+    for pos in changed {
+      self.wake(pos);
+    }
+    failed.map_or(Ok(()), Err)
+  }
+}
+
+impl Default for FluidSim {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+fn flow(
+  tree: &mut Hexadecitree,
+  pos: BlockPos,
+  changed: &mut AHashSet<BlockPos>,
+) -> Result<(), SetFoxelError> {
+  let Some((fluid, state)) = tree.get_with_state(pos) else {
+    return Ok(());
+  };
+  let mut put = |tree: &mut Hexadecitree, pos: BlockPos, level: u16| {
+    let (foxel, state) = if level == 0 {
+      (Foxel::AIR, FoxelState::NONE)
+    } else {
+      (fluid, FluidSim::level_state(level))
+    };
+    tree.set_with_state(pos, foxel, state)?;
+    changed.insert(pos);
+    Ok(())
+  };
+  // Either both ends of a move get written or neither does, so running
+  // out of room never makes or loses any fluid
+  let mut transfer = |tree: &mut Hexadecitree,
+                      (to, to_level): (BlockPos, u16),
+                      from_level: u16,
+                      amount: u16| {
+    put(tree, to, to_level + amount)?;
+    if let Err(err) = put(tree, pos, from_level - amount) {
+      put(tree, to, to_level)?;
+      return Err(err);
+    }
+    Ok(())
+  };
+
+  let mut level = FluidSim::level(state);
+
+  let below = BlockPos(pos.0 - IVec4::unit_x());
+  match tree.get_with_state(below) {
+    Some((f, _)) if f == Foxel::AIR => {
+      return transfer(tree, (below, 0), level, level);
+    }
+    Some((f, s)) if f == fluid => {
+      let below_level = FluidSim::level(s);
+      let moving = (FluidSim::MAX_LEVEL - below_level).min(level);
+      if moving > 0 {
+        transfer(tree, (below, below_level), level, moving)?;
+        level -= moving;
+      }
+    }
+    _ => {}
+  }
+
+  for dir in SIDEWAYS {
+    if level <= 1 {
+      break;
+    }
+    let side = BlockPos(pos.0 + dir);
+    let side_level = match tree.get_with_state(side) {
+      Some((f, _)) if f == Foxel::AIR => 0,
+      Some((f, s)) if f == fluid => FluidSim::level(s),
+      _ => continue,
+    };
+    if side_level + 1 < level {
+      transfer(tree, (side, side_level), level, 1)?;
+      level -= 1;
+    }
+  }
+  Ok(())
+}
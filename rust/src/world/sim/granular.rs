@@ -0,0 +1,164 @@
+//! Sand, gravel and anything else that falls when there's nothing under it.
+//!
+//! Grains drop along -X into anything that isn't solid, swapping places
+//! with whatever was there, so sand sinks through water. A grain that
+//! can't drop straight down slides off diagonally if there's room, which
+//! piles things up instead of stacking them in columns.
+//!
+//! Nothing gets scanned for. Only foxels near a change get looked at, so
+//! grains placed straight into the tree stay put until something near
+//! them moves.
+
+use ahash::AHashSet;
+use ultraviolet::IVec4;
+
+use crate::{
+  math::{
+    hexadecitree::{Hexadecitree, SetFoxelError},
+    BlockPos,
+  },
+  world::foxel::{Foxel, FoxelRegistry},
+};
+
+#[derive(Debug, Clone)]
+pub struct GranularSim {
+  falls: Vec<Foxel>,
+  /// Foxels to look at next tick.
+  pending: AHashSet<BlockPos>,
+  ticks: u64,
+}
+
+const SIDEWAYS: [IVec4; 6] = [
+  IVec4::new(0, 1, 0, 0),
+  IVec4::new(0, -1, 0, 0),
+  IVec4::new(0, 0, 1, 0),
+  IVec4::new(0, 0, -1, 0),
+  IVec4::new(0, 0, 0, 1),
+  IVec4::new(0, 0, 0, -1),
+];
+
+impl GranularSim {
+  /// Foxel types with this tag fall.
+  pub const TAG: &'static str = "falls";
+
+  /// A sim where everything tagged `falls` in the builtin registry falls.
+  pub fn new() -> Self {
+    Self::with_falling(FoxelRegistry::builtin().with_tag(Self::TAG).collect())
+  }
+
+  pub fn with_falling(falls: Vec<Foxel>) -> Self {
+    Self {
+      falls,
+      pending: AHashSet::new(),
+      ticks: 0,
+    }
+  }
+
+  pub fn falls(&self, foxel: Foxel) -> bool {
+    self.falls.contains(&foxel)
+  }
+
+  /// Something changed at `pos`, so check on anything that might have
+  /// been resting on it or wants to move into it.
+  pub fn wake(&mut self, pos: BlockPos) {
+    let up = IVec4::unit_x();
+    self.pending.insert(pos);
+    self.pending.insert(BlockPos(pos.0 + up));
+    for side in SIDEWAYS {
+      // Something that could slide down into `pos`
+      self.pending.insert(BlockPos(pos.0 + up + side));
+      // Something that could slide down past `pos`
+      self.pending.insert(BlockPos(pos.0 + side));
+    }
+  }
+
+  /// How many foxels will be looked at next tick.
+  pub fn pending(&self) -> usize {
+    self.pending.len()
+  }
+
+  /// Let every grain that's been woken fall by one foxel.
+  ///
+  /// Grains with no room in the tree to move into stay put and try again
+  /// next tick, and the first error is returned once everything else has
+  /// fallen.
+  pub fn step(&mut self, tree: &mut Hexadecitree) -> Result<(), SetFoxelError> {
+    let tick = self.ticks;
+    self.ticks += 1;
+
+    let mut cells = std::mem::take(&mut self.pending)
+      .into_iter()
+      .filter(|pos| tree.get(*pos).is_some_and(|f| self.falls(f)))
+      .collect::<Vec<_>>();
+    // Lowest first, so a column falls all together
+    cells.sort_unstable_by_key(|pos| pos.as_array());
+
+    let mut moved = AHashSet::new();
+    let mut failed = None;
+    for pos in cells {
+      // Grains that have just landed here wait for next tick
+      if moved.contains(&pos) {
+        continue;
+      }
+      // Turn the order sides are tried in, so piles come out even
+      let spin = tick as i64 + pos.as_array().iter().sum::<i32>() as i64;
+      let first_side = spin.rem_euclid(SIDEWAYS.len() as i64) as usize;
+      let Some(to) = landing_spot(tree, pos, first_side) else {
+        continue;
+      };
+      if let Err(err) = swap(tree, pos, to) {
+        self.pending.insert(pos);
+        failed.get_or_insert(err);
+        continue;
+      }
+      moved.insert(to);
+      self.wake(pos);
+      self.wake(to);
+    }
+    failed.map_or(Ok(()), Err)
+  }
+}
+
+impl Default for GranularSim {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+/// Swap two foxels and their states over. If either write fails, the tree
+/// is left how it was, so grains never get lost or copied.
+fn swap(
+  tree: &mut Hexadecitree,
+  a: BlockPos,
+  b: BlockPos,
+) -> Result<(), SetFoxelError> {
+  let (a_foxel, a_state) =
+    tree.get_with_state(a).ok_or(SetFoxelError::OutOfBounds)?;
+  let (b_foxel, b_state) =
+    tree.get_with_state(b).ok_or(SetFoxelError::OutOfBounds)?;
+  tree.set_with_state(b, a_foxel, a_state)?;
+  if let Err(err) = tree.set_with_state(a, b_foxel, b_state) {
+    tree.set_with_state(b, b_foxel, b_state)?;
+    return Err(err);
+  }
+  Ok(())
+}
+
+/// Where a grain at `pos` would move to, if anywhere.
+fn landing_spot(
+  tree: &Hexadecitree,
+  pos: BlockPos,
+  first_side: usize,
+) -> Option<BlockPos> {
+  let free = |pos: BlockPos| tree.get(pos).is_some_and(|f| !f.ty().solid);
+  let below = BlockPos(pos.0 - IVec4::unit_x());
+  if free(below) {
+    return Some(below);
+  }
+  (0..SIDEWAYS.len())
+    .map(|i| SIDEWAYS[(first_side + i) % SIDEWAYS.len()])
+    .find(|side| {
+      free(BlockPos(pos.0 + *side)) && free(BlockPos(below.0 + *side))
+    })
+    .map(|side| BlockPos(below.0 + side))
+}
@@ -0,0 +1,151 @@
+use itertools::iproduct;
+use tesseractory::{
+  math::{hexadecitree::Hexadecitree, rng::SplitMix64, BlockPos},
+  world::{
+    ao::{AoCache, FaceAo},
+    foxel::Foxel,
+    World,
+  },
+};
+use ultraviolet::Vec4;
+
+const UP: usize = 1;
+
+fn floor(tree: &mut Hexadecitree) {
+  let stone = Foxel::named("stone");
+  for (y, z, w) in iproduct!(-6..=6, -6..=6, -6..=6) {
+    tree.set(BlockPos::new(-1, y, z, w), stone).unwrap();
+  }
+}
+
+fn wall(tree: &mut Hexadecitree, axis: usize) {
+  let stone = Foxel::named("stone");
+  for (x, a, b) in iproduct!(0..3, -6..=6, -6..=6) {
+    let mut coords = [x, 0, 0, 0];
+    let mut rest = [a, b].into_iter();
+    for (other, coord) in coords.iter_mut().enumerate().skip(1) {
+      *coord = if other == axis {
+        3
+      } else {
+        rest.next().unwrap()
+      };
+    }
+    tree.set(BlockPos(coords.into()), stone).unwrap();
+  }
+}
+
+fn up_level(ao: &mut AoCache, tree: &Hexadecitree, pos: BlockPos) -> u8 {
+  ao.ensure(tree, [pos]);
+  ao.get(pos).unwrap().level(UP)
+}
+
+#[test]
+fn faces() {
+  let ao = FaceAo::default().with_level(FaceAo::face(2, false), 2);
+  assert_eq!(ao.level(4), 2);
+  assert_eq!(ao.level(5), 0);
+  assert_eq!(FaceAo::face_of(-Vec4::unit_z()), 4);
+  assert_eq!(
+    ao.shade(-Vec4::unit_z()),
+    1.0 - 2.0 * FaceAo::DARKEN_PER_LEVEL
+  );
+  assert_eq!(ao.shade(Vec4::unit_z()), 1.0);
+  assert_eq!(ao.with_level(4, 9).level(4), FaceAo::MAX_LEVEL);
+}
+
+#[test]
+fn corners_get_darker() {
+  let mut tree = Hexadecitree::new();
+  let mut ao = AoCache::new();
+  floor(&mut tree);
+  assert_eq!(up_level(&mut ao, &tree, BlockPos::new(-1, 2, 2, 2)), 0);
+  // Air's not worth remembering anything about
+  assert!(ao.get(BlockPos::new(100, 0, 0, 0)).is_none());
+
+  for (walls, level) in [(1, 1), (2, 2), (3, 3)] {
+    wall(&mut tree, walls);
+    ao.clear();
+    assert_eq!(
+      up_level(&mut ao, &tree, BlockPos::new(-1, 2, 2, 2)),
+      level,
+      "{} walls",
+      walls
+    );
+    assert_eq!(up_level(&mut ao, &tree, BlockPos::new(-1, -3, -3, -3)), 0);
+  }
+  // Faces that can't be seen don't matter
+  assert_eq!(ao.get(BlockPos::new(-1, 2, 2, 2)).unwrap().level(0), 0);
+}
+
+#[test]
+fn changes_next_door_invalidate() {
+  let stone = Foxel::named("stone");
+  let mut world = World::new(Vec4::unit_x());
+  floor(&mut world.foxels);
+  // Right at the edge of a brick
+  let pos = BlockPos::new(-1, 0, 0, 0);
+  assert_eq!(world.ao.shade(&world.foxels, pos, Vec4::unit_x()), 1.0);
+
+  // A bit of wall just over the edge
+  for (z, w) in iproduct!(-1..=1, -1..=1) {
+    world.set_foxel(BlockPos::new(0, -1, z, w), stone).unwrap();
+  }
+  assert!(world.ao.get(pos).is_none());
+  assert!(world.ao.shade(&world.foxels, pos, Vec4::unit_x()) < 1.0);
+
+  for (z, w) in iproduct!(-1..=1, -1..=1) {
+    world
+      .set_foxel(BlockPos::new(0, -1, z, w), Foxel::AIR)
+      .unwrap();
+  }
+  assert_eq!(world.ao.shade(&world.foxels, pos, Vec4::unit_x()), 1.0);
+}
+
+#[test]
+fn matches_fresh_after_edits() {
+  let palette = ["stone", "stone", "glass", "air", "air"].map(Foxel::named);
+  let mut rng = SplitMix64::new(9);
+  let mut world = World::new(Vec4::unit_x());
+  floor(&mut world.foxels);
+  let region = || {
+    iproduct!(-2..4, -8..8, -8..8, -8..8)
+      .map(|(x, y, z, w)| BlockPos::new(x, y, z, w))
+  };
+  for _ in 0..20 {
+    for _ in 0..20 {
+      let pos = BlockPos::new(
+        rng.range_i32(-1, 3),
+        rng.range_i32(-8, 8),
+        rng.range_i32(-8, 8),
+        rng.range_i32(-8, 8),
+      );
+      let foxel = palette[rng.range_i32(0, palette.len() as i32) as usize];
+      world.set_foxel(pos, foxel).unwrap();
+    }
+    world.ao.ensure(&world.foxels, region());
+  }
+
+  let mut fresh = AoCache::new();
+  fresh.ensure(&world.foxels, region());
+  for pos in region() {
+    assert_eq!(world.ao.get(pos), fresh.get(pos), "at {:?}", pos);
+  }
+}
+
+#[test]
+fn traces_darker_in_corners() {
+  let mut world = World::new(Vec4::unit_x());
+  floor(&mut world.foxels);
+  for axis in 1..4 {
+    wall(&mut world.foxels, axis);
+  }
+  let down = -Vec4::unit_x();
+  let open = Vec4::new(5.0, -3.5, -3.5, -3.5);
+  let corner = Vec4::new(5.0, 2.5, 2.5, 2.5);
+
+  let flat = world.foxels.trace(open, down, 20);
+  assert_eq!(world.trace(open, down, 20), flat);
+  let tucked = world.trace(corner, down, 20);
+  assert_eq!(tucked.hit.unwrap().pos, BlockPos::new(-1, 2, 2, 2));
+  assert!(tucked.colour.x < flat.colour.x);
+}
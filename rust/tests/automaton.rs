@@ -0,0 +1,227 @@
+use itertools::iproduct;
+use tesseractory::{
+  math::{hexadecitree::Hexadecitree, BlockPos},
+  world::{
+    foxel::Foxel,
+    light::LightMap,
+    sim::{Automaton, Neighbourhood, Rule, RuleError},
+    World,
+  },
+};
+use ultraviolet::Vec4;
+
+fn alive_cells(tree: &Hexadecitree, ca: &Automaton) -> Vec<BlockPos> {
+  let (min, max) = (ca.min(), ca.max());
+  iproduct!(min.x..=max.x, min.y..=max.y, min.z..=max.z, min.w..=max.w)
+    .map(|(x, y, z, w)| BlockPos::new(x, y, z, w))
+    .filter(|pos| tree.get(*pos) == Some(ca.alive))
+    .collect()
+}
+
+#[test]
+fn parse_rules() {
+  let rule: Rule = "B4/S4,5/VN".parse().unwrap();
+  assert_eq!(rule.neighbourhood, Neighbourhood::VonNeumann);
+  assert!(rule.births(4) && !rule.births(5));
+  assert!(rule.survives(4) && rule.survives(5) && !rule.survives(3));
+
+  let rule: Rule = "B3-5,10/S".parse().unwrap();
+  assert_eq!(rule.neighbourhood, Neighbourhood::Moore);
+  assert!((3..=5).all(|n| rule.births(n)) && rule.births(10));
+  assert!(!rule.births(6));
+  assert!((0..=80).all(|n| !rule.survives(n)));
+
+  assert!(matches!(
+    "S4/B4".parse::<Rule>(),
+    Err(RuleError::Malformed(_))
+  ));
+  assert!(matches!(
+    "B4/S4/X".parse::<Rule>(),
+    Err(RuleError::Malformed(_))
+  ));
+  assert!(matches!(
+    "Bx/S4".parse::<Rule>(),
+    Err(RuleError::BadCount(_))
+  ));
+  assert_eq!("B9/S/VN".parse::<Rule>(), Err(RuleError::TooMany(9)));
+  assert!("B80/S".parse::<Rule>().is_ok());
+}
+
+#[test]
+fn von_neumann_spreads_to_faces() {
+  let red = Foxel::named("red");
+  let mut tree = Hexadecitree::new();
+  let rule = "B1/S/VN".parse().unwrap();
+  let ca = Automaton::new(
+    rule,
+    red,
+    BlockPos::new(-4, -4, -4, -4),
+    BlockPos::new(4, 4, 4, 4),
+  );
+  tree.set(BlockPos::new(0, 0, 0, 0), red).unwrap();
+
+  let stats = ca.step(&mut tree).unwrap();
+  assert_eq!((stats.births, stats.deaths), (8, 1));
+  let alive = alive_cells(&tree, &ca);
+  assert_eq!(alive.len(), 8);
+  assert!(alive.iter().all(|p| p
+    .as_array()
+    .iter()
+    .map(|v| v.abs())
+    .sum::<i32>()
+    == 1));
+}
+
+#[test]
+fn moore_counts_corners() {
+  let red = Foxel::named("red");
+  let mut tree = Hexadecitree::new();
+  let rule = "B2/S".parse().unwrap();
+  let ca = Automaton::new(
+    rule,
+    red,
+    BlockPos::new(-4, -4, -4, -4),
+    BlockPos::new(4, 4, 4, 4),
+  );
+  tree.set(BlockPos::new(0, 0, 0, 0), red).unwrap();
+  tree.set(BlockPos::new(1, 0, 0, 0), red).unwrap();
+
+  // Everything touching both of them
+  let stats = ca.step(&mut tree).unwrap();
+  assert_eq!((stats.births, stats.deaths), (2 * 27 - 2, 2));
+}
+
+/// The slow obvious way, to check the fast way against.
+fn naive_step(tree: &Hexadecitree, ca: &Automaton) -> Vec<Option<Foxel>> {
+  let (min, max) = (ca.min(), ca.max());
+  let inside = |p: BlockPos| (0..4).all(|a| min[a] <= p[a] && p[a] <= max[a]);
+  iproduct!(min.x..=max.x, min.y..=max.y, min.z..=max.z, min.w..=max.w)
+    .map(|(x, y, z, w)| {
+      let pos = BlockPos::new(x, y, z, w);
+      let count = iproduct!(-1..=1, -1..=1, -1..=1, -1..=1)
+        .filter(|d| *d != (0, 0, 0, 0))
+        .map(|(dx, dy, dz, dw)| BlockPos::new(x + dx, y + dy, z + dz, w + dw))
+        .filter(|p| inside(*p) && tree.get(*p) == Some(ca.alive))
+        .count() as u32;
+      match tree.get(pos) {
+        Some(f) if f == ca.alive && !ca.rule.survives(count) => {
+          Some(Foxel::AIR)
+        }
+        Some(f) if f == Foxel::AIR && ca.rule.births(count) => Some(ca.alive),
+        other => other,
+      }
+    })
+    .collect()
+}
+
+#[test]
+fn matches_naive_across_bricks() {
+  let [green, stone] = ["green", "stone"].map(Foxel::named);
+  let mut tree = Hexadecitree::new();
+  let rule = "B5-8/S4-9".parse().unwrap();
+  // Straddles brick edges on every axis
+  let ca = Automaton::new(
+    rule,
+    green,
+    BlockPos::new(5, -6, 3, -2),
+    BlockPos::new(-4, 5, 14, 9),
+  );
+  // Some stuff in the way, which should never change
+  for y in -6..=5 {
+    tree.set(BlockPos::new(0, y, 8, 3), stone).unwrap();
+  }
+  ca.seed(&mut tree, 7, 0.3).unwrap();
+
+  for _ in 0..3 {
+    let expected = naive_step(&tree, &ca);
+    ca.step(&mut tree).unwrap();
+    let (min, max) = (ca.min(), ca.max());
+    let got =
+      iproduct!(min.x..=max.x, min.y..=max.y, min.z..=max.z, min.w..=max.w)
+        .map(|(x, y, z, w)| tree.get(BlockPos::new(x, y, z, w)))
+        .collect::<Vec<_>>();
+    assert_eq!(got, expected);
+  }
+  assert_eq!(tree.get(BlockPos::new(0, 2, 8, 3)), Some(stone));
+  // Nothing leaks out of the box
+  assert_eq!(tree.get(BlockPos::new(6, 0, 8, 3)), Some(Foxel::AIR));
+}
+
+#[test]
+fn seeding_is_deterministic() {
+  let blue = Foxel::named("blue");
+  let rule = "B4/S4".parse().unwrap();
+  let ca = Automaton::new(
+    rule,
+    blue,
+    BlockPos::new(0, 0, 0, 0),
+    BlockPos::new(9, 9, 9, 9),
+  );
+  let run = |seed| {
+    let mut tree = Hexadecitree::new();
+    ca.seed(&mut tree, seed, 0.2).unwrap();
+    for _ in 0..2 {
+      ca.step(&mut tree).unwrap();
+    }
+    alive_cells(&tree, &ca)
+  };
+  assert_eq!(run(1), run(1));
+  assert_ne!(run(1), run(2));
+}
+
+#[test]
+fn nothing_happening_writes_nothing() {
+  let red = Foxel::named("red");
+  let mut tree = Hexadecitree::new();
+  let rule = "B3/S".parse().unwrap();
+  let ca = Automaton::new(
+    rule,
+    red,
+    BlockPos::new(0, 0, 0, 0),
+    BlockPos::new(15, 15, 15, 15),
+  );
+  let stats = ca.step(&mut tree).unwrap();
+  assert_eq!(stats.bricks_written, 0);
+  assert_eq!(tree.composite_brick_count(), 0);
+}
+
+#[test]
+fn the_world_keeps_up() {
+  let [red, sand, stone] = ["red", "sand", "stone"].map(Foxel::named);
+  let mut world = World::new(Vec4::unit_x());
+  for (y, z, w) in iproduct!(-2..=2, -2..=2, -2..=2) {
+    world.set_foxel(BlockPos::new(-3, y, z, w), stone).unwrap();
+    world.set_foxel(BlockPos::new(0, y, z, w), red).unwrap();
+  }
+  world.set_foxel(BlockPos::new(1, 0, 0, 0), sand).unwrap();
+  // Nothing survives, so the shelf goes on the first step
+  let rule = "B/S".parse().unwrap();
+  world.automaton = Some(Automaton::new(
+    rule,
+    red,
+    BlockPos::new(0, -2, -2, -2),
+    BlockPos::new(0, 2, 2, 2),
+  ));
+
+  world.tick();
+  assert_eq!(
+    world.foxels.get(BlockPos::new(0, 0, 0, 0)),
+    Some(Foxel::AIR)
+  );
+  let mut fresh = LightMap::new();
+  fresh.rebuild(&world.foxels);
+  for (x, y, z, w) in iproduct!(-4..4, -3..=3, -3..=3, -3..=3) {
+    let pos = BlockPos::new(x, y, z, w);
+    assert_eq!(
+      world.light.get(&world.foxels, pos),
+      fresh.get(&world.foxels, pos),
+      "at {:?}",
+      pos
+    );
+  }
+
+  for _ in 0..20 {
+    world.tick();
+  }
+  assert_eq!(world.foxels.get(BlockPos::new(-2, 0, 0, 0)), Some(sand));
+}
@@ -0,0 +1,177 @@
+mod common;
+
+use common::{random_point, random_pos};
+use itertools::iproduct;
+use tesseractory::{
+  math::{hexadecitree::Hexadecitree, rng::SplitMix64, BlockPos},
+  world::foxel::Foxel,
+};
+use ultraviolet::{IVec4, Vec4};
+
+/// A floor at X = -1, which is everything below X = 0.
+fn floor() -> Hexadecitree {
+  let mut h = Hexadecitree::new();
+  for (y, z, w) in iproduct!(-6..6, -6..6, -6..6) {
+    h.set(BlockPos::new(-1, y, z, w), Foxel::named("stone"))
+      .unwrap();
+  }
+  h
+}
+
+#[test]
+fn falls_onto_the_floor() {
+  let h = floor();
+  let (min, max) = (
+    Vec4::new(2.5, -0.5, -0.5, -0.5),
+    Vec4::broadcast(0.5) + Vec4::unit_x() * 3.0,
+  );
+  let hit = h.box_cast(min, max, -Vec4::unit_x(), 10.0).unwrap();
+  assert_eq!(hit.dist, 2.5);
+  assert_eq!(hit.normal, Vec4::unit_x());
+  assert_eq!(hit.pos.0.x, -1);
+  assert_eq!(hit.foxel, Foxel::named("stone"));
+
+  assert_eq!(h.box_cast(min, max, -Vec4::unit_x(), 2.0), None);
+  assert_eq!(h.box_cast(min, max, Vec4::unit_x(), 100.0), None);
+}
+
+/// Outside the world is just air, so boxes can come in from out there.
+#[test]
+fn sweeps_in_from_outside() {
+  let h = floor();
+  let outside = Hexadecitree::MAX_COORD as f32 + 20.0;
+  let (min, max) = (
+    Vec4::new(outside, -0.3, -0.3, -0.3),
+    Vec4::new(outside + 1.0, 0.3, 0.3, 0.3),
+  );
+  let hit = h.box_cast(min, max, -Vec4::unit_x(), 1000.0).unwrap();
+  assert_eq!(hit.dist, outside);
+  assert_eq!(hit.normal, Vec4::unit_x());
+  assert_eq!(hit.pos.0.x, -1);
+  let ray = h.raycast(min, -Vec4::unit_x(), 1000.0).unwrap();
+  assert_eq!(ray.dist, hit.dist);
+
+  // From the other side too, and sideways
+  let below =
+    Vec4::unit_x() * (Hexadecitree::MIN_COORD as f32 - 20.0 - outside);
+  let hit = h.box_cast(min + below, max + below, Vec4::unit_x(), 1000.0);
+  assert_eq!(hit.unwrap().pos.0.x, -1);
+  // Down in the floor, so it runs into the end of it
+  let side =
+    Vec4::unit_y() * (outside + 10.0) - Vec4::unit_x() * (outside + 1.5);
+  let hit = h.box_cast(min + side, max + side, -Vec4::unit_y(), 1000.0);
+  assert_eq!(hit.unwrap().pos.0.y, 5);
+
+  // But not going the other way
+  assert_eq!(h.box_cast(min, max, Vec4::unit_x(), 1000.0), None);
+}
+
+#[test]
+fn sits_and_slides() {
+  let h = floor();
+  // Sitting right on top of it
+  let (min, max) = (Vec4::zero(), Vec4::new(1.8, 0.6, 0.6, 0.6));
+  for dir in [
+    Vec4::unit_y(),
+    -Vec4::unit_z(),
+    Vec4::new(0.0, 1.0, 0.0, -1.0),
+  ] {
+    assert_eq!(h.box_cast(min, max, dir, 4.0), None, "along {:?}", dir);
+  }
+  let hit = h.box_cast(min, max, -Vec4::unit_x(), 4.0).unwrap();
+  assert_eq!(hit.dist, 0.0);
+  assert_eq!(hit.normal, Vec4::unit_x());
+
+  // Sunk into it a bit
+  let hit = h
+    .box_cast(min - Vec4::unit_x() * 0.1, max, Vec4::unit_y(), 4.0)
+    .unwrap();
+  assert_eq!(hit.dist, 0.0);
+  assert_eq!(hit.normal, Vec4::zero());
+}
+
+#[test]
+fn whole_face_catches() {
+  let mut h = Hexadecitree::new();
+  // Nowhere near the ray from any corner of the box
+  let post = BlockPos::new(1, 6, 0, 2);
+  h.set(post, Foxel::named("stone")).unwrap();
+  let (min, max) = (Vec4::broadcast(0.5), Vec4::broadcast(2.5));
+  let hit = h.box_cast(min, max, Vec4::unit_y(), 10.0).unwrap();
+  assert_eq!(hit.pos, post);
+  assert_eq!(hit.dist, 3.5);
+  assert_eq!(hit.normal, -Vec4::unit_y());
+  assert_eq!(h.raycast(max, Vec4::unit_y(), 10.0), None);
+}
+
+/// When the box first overlaps the foxel at `pos`, if it ever does.
+fn slab_time(min: Vec4, max: Vec4, dir: Vec4, pos: IVec4) -> Option<f32> {
+  let (mut enter, mut exit) = (f32::NEG_INFINITY, f32::INFINITY);
+  for axis in 0..4 {
+    let (lo, hi) = (
+      pos[axis] as f32 - max[axis],
+      pos[axis] as f32 + 1.0 - min[axis],
+    );
+    if dir[axis] == 0.0 {
+      if lo >= 0.0 || hi <= 0.0 {
+        return None;
+      }
+      continue;
+    }
+    let (a, b) = (lo / dir[axis], hi / dir[axis]);
+    enter = enter.max(a.min(b));
+    exit = exit.min(a.max(b));
+  }
+  (enter < exit && exit > 0.0).then_some(enter.max(0.0))
+}
+
+#[test]
+fn matches_slabs_across_bricks() {
+  let mut h = Hexadecitree::new();
+  let mut rng = SplitMix64::new(47);
+  let mut solid = Vec::new();
+  // Around the corner where 16 bricks meet
+  for _ in 0..400 {
+    let pos = random_pos(&mut rng, -12, 12);
+    h.set(pos, Foxel::named("stone")).unwrap();
+    solid.push(pos.0);
+  }
+
+  let mut hits = 0;
+  for _ in 0..400 {
+    let min = random_point(&mut rng, 14.0);
+    let max = min + (random_point(&mut rng, 1.0) + Vec4::broadcast(1.2)).abs();
+    let dir = (random_point(&mut rng, 4.0) - min).normalized();
+
+    let expected = solid
+      .iter()
+      .filter_map(|pos| slab_time(min, max, dir, *pos))
+      .fold(None, |best: Option<f32>, t| {
+        Some(best.map_or(t, |b| b.min(t)))
+      })
+      .filter(|t| *t <= 30.0);
+    let hit = h.box_cast(min, max, dir, 30.0);
+    match (hit, expected) {
+      (None, None) => {}
+      (Some(hit), Some(t)) => {
+        assert!(
+          (hit.dist - t).abs() < 1e-3,
+          "{:?} to {:?} along {:?}: {} vs {}",
+          min,
+          max,
+          dir,
+          hit.dist,
+          t
+        );
+        let time = slab_time(min, max, dir, hit.pos.0).unwrap();
+        assert!((time - t).abs() < 1e-3);
+        hits += 1;
+      }
+      _ => panic!(
+        "{:?} to {:?} along {:?}: {:?} vs {:?}",
+        min, max, dir, hit, expected
+      ),
+    }
+  }
+  assert!(hits > 100, "{}", hits);
+}
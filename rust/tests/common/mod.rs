@@ -0,0 +1,70 @@
+//! Fixtures shared between the test files. Not every file uses all of them.
+#![allow(dead_code)]
+
+use itertools::iproduct;
+use tesseractory::{
+  math::{
+    hexadecitree::{packet::LANES, Hexadecitree, SetFoxelError},
+    rng::SplitMix64,
+    BlockPos,
+  },
+  world::foxel::Foxel,
+};
+use ultraviolet::Vec4;
+
+/// Anywhere in the box from `-scale` to `scale` along every axis.
+pub fn random_point(rng: &mut SplitMix64, scale: f32) -> Vec4 {
+  Vec4::from([(); 4].map(|_| (rng.next_f32() * 2.0 - 1.0) * scale))
+}
+
+/// Each coordinate in `lo..hi`.
+pub fn random_pos(rng: &mut SplitMix64, lo: i32, hi: i32) -> BlockPos {
+  BlockPos([(); 4].map(|_| rng.range_i32(lo, hi)).into())
+}
+
+/// `count` foxels from `palette` scattered over `-half..half` along every
+/// axis, with bricks of nothing in between.
+pub fn scattered_tree(
+  seed: u64,
+  count: usize,
+  half: i32,
+  palette: &[Foxel],
+) -> Hexadecitree {
+  let mut tree = Hexadecitree::new();
+  let mut rng = SplitMix64::new(seed);
+  for _ in 0..count {
+    let pos = random_pos(&mut rng, -half, half);
+    let foxel = palette[rng.range_i32(0, palette.len() as i32) as usize];
+    tree.set(pos, foxel).unwrap();
+  }
+  tree
+}
+
+/// Fill up every composite brick the tree has room for, well away from
+/// the origin.
+pub fn use_up_memory(tree: &mut Hexadecitree) {
+  let fab = Hexadecitree::FOXELS_ACROSS_BRICK as i32;
+  let (min, bricks) = (
+    Hexadecitree::MIN_COORD,
+    Hexadecitree::BRICKS_ACROSS_WORLD as i32,
+  );
+  let stone = Foxel::named("stone");
+  for (y, z, w) in iproduct!(0..bricks, 0..bricks, 0..bricks) {
+    let pos =
+      BlockPos::new(8 * fab, min + y * fab, min + z * fab, min + w * fab);
+    if tree.set(pos, stone) == Err(SetFoxelError::OutOfMemory) {
+      return;
+    }
+  }
+  panic!("never ran out of room");
+}
+
+/// A packet of rays from all over, mostly pointing in towards the middle.
+/// They aren't normalized.
+pub fn random_rays(rng: &mut SplitMix64) -> ([Vec4; LANES], [Vec4; LANES]) {
+  let starts = [(); LANES].map(|_| random_point(rng, 15.0));
+  let mut dirs = starts.map(|start| random_point(rng, 8.0) - start);
+  // Straight down an axis too
+  dirs[3] = -Vec4::unit_z();
+  (starts, dirs)
+}
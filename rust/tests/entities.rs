@@ -0,0 +1,183 @@
+use tesseractory::{
+  math::BlockPos,
+  world::{
+    entity::{Component, EntityStore, FoxelEntity, Inventory, SignText},
+    foxel::Foxel,
+    World,
+  },
+};
+use ultraviolet::Vec4;
+
+#[derive(Debug, PartialEq)]
+struct Power(u8);
+
+impl Component for Power {
+  const NAME: &'static str = "test_power";
+
+  fn save(&self, out: &mut Vec<u8>) {
+    out.push(self.0);
+  }
+
+  fn load(bytes: &[u8]) -> Option<Self> {
+    match bytes {
+      [b] => Some(Power(*b)),
+      _ => None,
+    }
+  }
+}
+
+#[test]
+fn typed_components() {
+  let mut e = FoxelEntity::new().with(SignText("hi".to_owned()));
+  assert!(e.has::<SignText>());
+  assert!(e.get::<Inventory>().is_none());
+
+  assert_eq!(e.insert(Power(3)), None);
+  assert_eq!(e.insert(Power(4)), Some(Power(3)));
+  e.get_mut::<Power>().unwrap().0 += 1;
+  assert_eq!(e.get::<Power>(), Some(&Power(5)));
+  assert_eq!(e.component_count(), 2);
+
+  assert_eq!(e.remove::<SignText>(), Some(SignText("hi".to_owned())));
+  assert!(!e.has::<SignText>());
+}
+
+#[test]
+fn placing_makes_entities() {
+  let mut world = World::new(Vec4::unit_x());
+  let pos = BlockPos::new(1, -2, 3, -4);
+
+  world.set_foxel(pos, Foxel::named("chest")).unwrap();
+  let inv = world.entities.component_mut::<Inventory>(pos).unwrap();
+  inv.items.push((Foxel::named("stone"), 12));
+
+  // Replacing it with the same thing changes nothing
+  world.set_foxel(pos, Foxel::named("chest")).unwrap();
+  assert_eq!(
+    world
+      .entities
+      .component::<Inventory>(pos)
+      .unwrap()
+      .items
+      .len(),
+    1
+  );
+
+  world.set_foxel(pos, Foxel::named("sign")).unwrap();
+  assert!(world.entities.component::<Inventory>(pos).is_none());
+  assert!(world.entities.component::<SignText>(pos).is_some());
+
+  world.set_foxel(pos, Foxel::named("stone")).unwrap();
+  assert!(world.entities.get(pos).is_none());
+  assert!(world.entities.is_empty());
+}
+
+#[test]
+fn region_query() {
+  let mut store = EntityStore::new();
+  let spots = [
+    BlockPos::new(0, 0, 0, 0),
+    BlockPos::new(7, 7, 7, 7),
+    BlockPos::new(8, 0, 0, 0),
+    BlockPos::new(-1, 0, 0, 0),
+    BlockPos::new(3, -20, 5, 5),
+  ];
+  for pos in spots {
+    store.insert(pos, FoxelEntity::new());
+  }
+  assert_eq!(store.len(), spots.len());
+
+  let mut found = store
+    .in_region(BlockPos::new(0, 0, 0, 0), BlockPos::new(8, 7, 7, 7))
+    .map(|(pos, _)| pos.as_array())
+    .collect::<Vec<_>>();
+  found.sort();
+  assert_eq!(found, vec![[0, 0, 0, 0], [7, 7, 7, 7], [8, 0, 0, 0]]);
+}
+
+#[test]
+fn entities_get_saved() {
+  let mut world = World::new(Vec4::unit_x());
+  let chest = BlockPos::new(0, 1, 2, 3);
+  let sign = BlockPos::new(-9, 1, 2, 3);
+  world.set_foxel(chest, Foxel::named("chest")).unwrap();
+  world.set_foxel(sign, Foxel::named("sign")).unwrap();
+  world.entities.get_mut(chest).unwrap().insert(Power(9));
+  world.entities.component_mut::<SignText>(sign).unwrap().0 =
+    "four dimensions".to_owned();
+
+  let mut bytes = Vec::new();
+  world.save(&mut bytes).unwrap();
+
+  // Nothing told it about `Power`, so that gets skipped
+  let loaded = World::load(bytes.as_slice()).unwrap();
+  assert_eq!(loaded.entities.len(), 2);
+  assert_eq!(
+    loaded.entities.component::<SignText>(sign).unwrap().0,
+    "four dimensions"
+  );
+  let chest_entity = loaded.entities.get(chest).unwrap();
+  assert!(chest_entity.has::<Inventory>());
+  assert!(!chest_entity.has::<Power>());
+
+  let mut store = EntityStore::new();
+  store.register_component::<Power>();
+  let mut r = &bytes[bytes.len() - entity_section_len(&world)..];
+  store.read_from(&mut r).unwrap();
+  assert_eq!(store.component::<Power>(chest), Some(&Power(9)));
+}
+
+#[test]
+fn old_saves_still_load() {
+  let mut world = World::new(Vec4::unit_x());
+  world
+    .foxels
+    .set(BlockPos::new(1, 1, 1, 1), Foxel::named("dirt"))
+    .unwrap();
+  let mut bytes = Vec::new();
+  world.save(&mut bytes).unwrap();
+
+  // Version 1 is the same, minus the entities on the end
+  bytes[4..6].copy_from_slice(&1u16.to_le_bytes());
+  bytes.truncate(bytes.len() - entity_section_len(&world));
+  let loaded = World::load(bytes.as_slice()).unwrap();
+  assert_eq!(
+    loaded.foxels.get(BlockPos::new(1, 1, 1, 1)),
+    Some(Foxel::named("dirt"))
+  );
+  assert!(loaded.entities.is_empty());
+}
+
+fn entity_section_len(world: &World) -> usize {
+  let mut bytes = Vec::new();
+  world.entities.write_to(&mut bytes).unwrap();
+  bytes.len()
+}
+
+#[test]
+fn writing_the_tree_directly_keeps_up() {
+  let mut world = World::new(Vec4::unit_x());
+  let pos = BlockPos::new(2, 0, 0, 0);
+  world.foxels.set(pos, Foxel::named("chest")).unwrap();
+  assert!(world.entities.get(pos).is_none());
+  world.tick();
+  assert!(world.entities.component::<Inventory>(pos).is_some());
+
+  // Like water flowing over it
+  world.foxels.set(pos, Foxel::named("water")).unwrap();
+  world.tick();
+  assert!(world.entities.is_empty());
+}
+
+#[test]
+fn huge_components_dont_get_allocated() {
+  let mut bytes = Vec::new();
+  bytes.extend(1u32.to_le_bytes());
+  bytes.extend([0; 16]);
+  bytes.extend(1u16.to_le_bytes());
+  bytes.extend([1, b'x']);
+  bytes.extend(u32::MAX.to_le_bytes());
+  bytes.extend([0; 8]);
+  let mut store = EntityStore::new();
+  assert!(store.read_from(&mut bytes.as_slice()).is_err());
+}
@@ -0,0 +1,192 @@
+mod common;
+
+use common::use_up_memory;
+use itertools::iproduct;
+use tesseractory::{
+  math::{
+    hexadecitree::{Hexadecitree, SetFoxelError},
+    BlockPos,
+  },
+  world::{
+    foxel::Foxel,
+    sim::{FixedTicker, FluidSim},
+    World,
+  },
+};
+use ultraviolet::Vec4;
+
+fn world() -> World {
+  World::new(Vec4::unit_x())
+}
+
+/// A stone floor at x = -1, walled in at +-`half` on Y, Z and W.
+fn basin(world: &mut World, half: i32) {
+  let stone = Foxel::named("stone");
+  for (x, y, z, w) in iproduct!(-1..3, -half..=half, -half..=half, -half..=half)
+  {
+    if x == -1 || [y, z, w].iter().any(|v| v.abs() == half) {
+      world.foxels.set(BlockPos::new(x, y, z, w), stone).unwrap();
+    }
+  }
+}
+
+/// Total fluid of one kind within `half` of the origin.
+fn volume(world: &World, fluid: Foxel, half: i32) -> u32 {
+  iproduct!(-1..16, -half..=half, -half..=half, -half..=half)
+    .filter_map(|(x, y, z, w)| {
+      let (f, s) = world.foxels.get_with_state(BlockPos::new(x, y, z, w))?;
+      (f == fluid).then(|| FluidSim::level(s) as u32)
+    })
+    .sum()
+}
+
+fn run(world: &mut World, ticks: usize) {
+  for _ in 0..ticks {
+    world.tick();
+  }
+}
+
+#[test]
+fn falls_along_x() {
+  let water = Foxel::named("water");
+  let mut world = world();
+  basin(&mut world, 4);
+  world.set_foxel(BlockPos::new(10, 0, 0, 0), water).unwrap();
+
+  run(&mut world, 10);
+  assert_eq!(
+    world.foxels.get(BlockPos::new(10, 0, 0, 0)),
+    Some(Foxel::AIR)
+  );
+  assert_eq!(world.foxels.get(BlockPos::new(0, 0, 0, 0)), Some(water));
+  assert_eq!(volume(&world, water, 4), FluidSim::MAX_LEVEL as u32);
+}
+
+#[test]
+fn spreads_and_keeps_volume() {
+  let water = Foxel::named("water");
+  let mut world = world();
+  basin(&mut world, 4);
+  for x in 0..3 {
+    world.set_foxel(BlockPos::new(x, 0, 0, 0), water).unwrap();
+  }
+  let before = volume(&world, water, 4);
+
+  run(&mut world, 40);
+  assert_eq!(volume(&world, water, 4), before);
+  // Spread out along every sideways axis
+  for pos in [
+    BlockPos::new(0, 1, 0, 0),
+    BlockPos::new(0, 0, -1, 0),
+    BlockPos::new(0, 0, 0, 1),
+  ] {
+    assert_eq!(world.foxels.get(pos), Some(water), "{:?}", pos);
+  }
+  // And piled up no higher than the floor
+  assert_eq!(
+    world.foxels.get(BlockPos::new(2, 0, 0, 0)),
+    Some(Foxel::AIR)
+  );
+}
+
+#[test]
+fn settles_and_sleeps() {
+  let water = Foxel::named("water");
+  let mut world = world();
+  basin(&mut world, 3);
+  world.set_foxel(BlockPos::new(0, 0, 0, 0), water).unwrap();
+  assert!(world.fluids.active_bricks() > 0);
+
+  run(&mut world, 60);
+  assert_eq!(world.fluids.active_bricks(), 0);
+
+  // Knocking a hole in the floor wakes it back up
+  world
+    .set_foxel(BlockPos::new(-1, 0, 0, 0), Foxel::AIR)
+    .unwrap();
+  assert!(world.fluids.active_bricks() > 0);
+  world.tick();
+  assert_eq!(world.foxels.get(BlockPos::new(-1, 0, 0, 0)), Some(water));
+}
+
+#[test]
+fn deterministic() {
+  let build = || {
+    let mut world = world();
+    basin(&mut world, 5);
+    for (x, y) in iproduct!(2..8, -2..2) {
+      world
+        .set_foxel(BlockPos::new(x, y, x - 4, 1), Foxel::named("water"))
+        .unwrap();
+    }
+    run(&mut world, 30);
+    let mut foxels = world.foxels.states().collect::<Vec<_>>();
+    foxels.sort_unstable_by_key(|(pos, _)| pos.as_array());
+    let cells = iproduct!(-1..8, -5..=5, -5..=5, -5..=5)
+      .map(|(x, y, z, w)| world.foxels.get(BlockPos::new(x, y, z, w)))
+      .collect::<Vec<_>>();
+    (foxels, cells)
+  };
+  assert_eq!(build(), build());
+}
+
+#[test]
+fn lava_is_slower() {
+  let [water, lava] = ["water", "lava"].map(Foxel::named);
+  let mut world = world();
+  world.set_foxel(BlockPos::new(20, 0, 0, 0), water).unwrap();
+  world.set_foxel(BlockPos::new(20, 10, 0, 0), lava).unwrap();
+
+  run(&mut world, 8);
+  let fallen = |fluid| {
+    (0..=20)
+      .find(|x| {
+        let pos = BlockPos::new(*x, if fluid == water { 0 } else { 10 }, 0, 0);
+        world.foxels.get(pos) == Some(fluid)
+      })
+      .map(|x| 20 - x)
+      .unwrap()
+  };
+  assert_eq!(fallen(water), 8);
+  assert_eq!(fallen(lava), 2);
+}
+
+#[test]
+fn nothing_lost_when_out_of_room() {
+  let water = Foxel::named("water");
+  let mut tree = Hexadecitree::new();
+  // Falling out of its brick into one that's all air
+  let stuck = BlockPos::new(0, 0, 0, 0);
+  tree.set(stuck, water).unwrap();
+  // Falling within its brick
+  let free = BlockPos::new(3, 0, 0, 0);
+  tree.set(free, water).unwrap();
+  use_up_memory(&mut tree);
+
+  let mut sim = FluidSim::new();
+  sim.wake(stuck);
+  sim.wake(free);
+  assert_eq!(sim.step(&mut tree), Err(SetFoxelError::OutOfMemory));
+  assert_eq!(tree.get(stuck), Some(water));
+  assert_eq!(
+    FluidSim::level(tree.get_state(stuck).unwrap()),
+    FluidSim::MAX_LEVEL
+  );
+  assert_eq!(tree.get(BlockPos::new(-1, 0, 0, 0)), Some(Foxel::AIR));
+  // Everything else still flows, and the stuck one keeps trying
+  assert_eq!(tree.get(free), Some(Foxel::AIR));
+  assert_eq!(tree.get(BlockPos::new(2, 0, 0, 0)), Some(water));
+  assert_eq!(sim.step(&mut tree), Err(SetFoxelError::OutOfMemory));
+  assert_eq!(tree.get(stuck), Some(water));
+}
+
+#[test]
+fn fixed_ticks() {
+  let mut ticker = FixedTicker::new(10.0);
+  assert_eq!(ticker.advance(0.05), 0);
+  assert_eq!(ticker.advance(0.06), 1);
+  assert_eq!(ticker.advance(0.2), 2);
+  // A huge hitch doesn't make it run forever
+  assert_eq!(ticker.advance(100.0), 8);
+  assert_eq!(ticker.advance(0.0), 0);
+}
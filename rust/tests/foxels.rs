@@ -0,0 +1,88 @@
+use tesseractory::world::foxel::{
+  Foxel, FoxelRegistry, RegistryError, RegistryErrorKind,
+};
+
+#[test]
+fn builtin_loads() {
+  let reg = FoxelRegistry::builtin();
+  assert_eq!(reg.by_name("air"), Some(Foxel::AIR));
+  assert!(!reg.get(Foxel::AIR).solid);
+  assert!(Foxel::AIR.transparent());
+
+  let stone = reg.expect("stone");
+  assert_eq!(stone, Foxel::named("stone"));
+  assert_eq!(reg.get(stone).name, "stone");
+  assert!(!stone.transparent());
+  assert!(reg.with_tag("natural").any(|f| f == stone));
+  assert!(reg.by_name("unobtainium").is_none());
+}
+
+#[test]
+fn ids_in_file_order() {
+  let reg = FoxelRegistry::parse(
+    "# comment\n\
+     air  #000000 0 0 no\n\
+     \n\
+     goo  #11aa22 0.5 0.25 no sticky wet\n\
+     rock #808080 1 0 yes\n",
+  )
+  .unwrap();
+  assert_eq!(reg.len(), 3);
+  let goo = reg.expect("goo");
+  assert_eq!(goo.id(), 1);
+  assert_eq!(reg.expect("rock").id(), 2);
+
+  let ty = reg.get(goo);
+  assert_eq!(ty.colour, [0x11, 0xaa, 0x22]);
+  assert_eq!(ty.opacity, 0.5);
+  assert_eq!(ty.emissive, 0.25);
+  assert!(ty.has_tag("sticky") && ty.has_tag("wet"));
+
+  // Unknown ids don't panic
+  assert_eq!(reg.get(Foxel::INVALID).name, "invalid");
+}
+
+#[test]
+fn bad_data() {
+  let err = |src| FoxelRegistry::parse(src).unwrap_err();
+  assert_eq!(
+    err("stone #808080 1 0 yes"),
+    RegistryError {
+      line: 1,
+      kind: RegistryErrorKind::FirstIsNotAir
+    }
+  );
+  assert_eq!(
+    err("air #fff 0 0 no").kind,
+    RegistryErrorKind::BadColour("#fff".to_owned())
+  );
+  assert_eq!(
+    err("air #ffffff 0 0 no\nair #ffffff 0 0 no").kind,
+    RegistryErrorKind::DuplicateName("air".to_owned())
+  );
+  assert_eq!(
+    err("air #ffffff 0 0 no\nx #ffffff 1 0 maybe").kind,
+    RegistryErrorKind::BadSolidity("maybe".to_owned())
+  );
+  assert_eq!(
+    err("air #ffffff 0").kind,
+    RegistryErrorKind::MissingField("emissive")
+  );
+}
+
+#[test]
+fn palette() {
+  let reg = FoxelRegistry::builtin();
+  let palette = reg.palette_rgba();
+  let size = FoxelRegistry::PALETTE_SIZE;
+  assert_eq!(palette.len(), size * size * 4);
+
+  for (foxel, ty) in reg.iter() {
+    let i = foxel.id() as usize * 4;
+    assert_eq!(palette[i..i + 3], ty.colour, "{}", ty.name);
+  }
+  // Air is see-through, stone isn't
+  assert_eq!(palette[3], 0);
+  let stone = Foxel::named("stone").id() as usize * 4;
+  assert_eq!(palette[stone + 3], 255);
+}
@@ -0,0 +1,85 @@
+use tesseractory::{
+  math::geo::Rotor4,
+  render::{
+    golden::{Golden, GoldenCase},
+    Camera,
+  },
+  world::scenes::SceneRegistry,
+};
+use ultraviolet::Vec4;
+
+/// Turns forward (+Y) to face `dir`.
+fn facing(dir: Vec4) -> Rotor4 {
+  Rotor4::from_rotation_between(Vec4::unit_y(), dir.normalized())
+}
+
+fn cases() -> Vec<GoldenCase> {
+  let cam = |pos: Vec4, dir: Vec4| Camera::new(pos, facing(dir), 1.0, 0.1);
+  vec![
+    GoldenCase::new(
+      "axis-cross",
+      "axis-cross",
+      cam(
+        Vec4::new(6.3, -9.7, 7.1, 0.4),
+        Vec4::new(-0.4, 1.0, -0.5, 0.0),
+      ),
+    ),
+    // Turned so W goes across the screen where Z did
+    GoldenCase::new(
+      "axis-cross-w",
+      "axis-cross",
+      Camera::new(
+        Vec4::new(4.3, -12.6, 0.4, 4.7),
+        Rotor4::from_rotation_between(Vec4::unit_z(), Vec4::unit_w()),
+        1.0,
+        0.1,
+      ),
+    ),
+    GoldenCase::new(
+      "fractal",
+      "fractal",
+      cam(
+        Vec4::new(30.2, -22.3, 28.9, 0.3),
+        Vec4::new(-0.8, 1.0, -0.7, 0.0),
+      ),
+    ),
+    GoldenCase::new(
+      "pools",
+      "pools",
+      cam(
+        Vec4::new(12.4, -9.6, 0.3, 0.45),
+        Vec4::new(-1.0, 0.8, 0.0, 0.0),
+      ),
+    ),
+    GoldenCase::new(
+      "terrain",
+      "terrain:7",
+      cam(
+        Vec4::new(40.3, -30.6, 0.2, 0.7),
+        Vec4::new(-0.5, 1.0, 0.0, 0.1),
+      ),
+    ),
+  ]
+}
+
+#[test]
+fn scenes_look_right() {
+  let golden = Golden::new(
+    concat!(env!("CARGO_MANIFEST_DIR"), "/tests/golden"),
+    concat!(env!("CARGO_TARGET_TMPDIR"), "/golden"),
+  );
+  let scenes = SceneRegistry::builtin();
+  let failures = cases()
+    .iter()
+    .filter_map(|case| {
+      let res = golden.check_case(&scenes, case);
+      res.err().map(|e| format!("{}: {:?}", case.name, e))
+    })
+    .collect::<Vec<_>>();
+  assert!(
+    failures.is_empty(),
+    "some renders don't match (run with {} set to update them):\n{}",
+    Golden::UPDATE_VAR,
+    failures.join("\n")
+  );
+}
@@ -0,0 +1,185 @@
+mod common;
+
+use common::use_up_memory;
+use itertools::iproduct;
+use tesseractory::{
+  math::{
+    hexadecitree::{Hexadecitree, SetFoxelError},
+    BlockPos,
+  },
+  world::{foxel::Foxel, sim::GranularSim, World},
+};
+use ultraviolet::Vec4;
+
+fn world_with_floor(half: i32) -> World {
+  let mut world = World::new(Vec4::unit_x());
+  let stone = Foxel::named("stone");
+  for (y, z, w) in iproduct!(-half..=half, -half..=half, -half..=half) {
+    world.foxels.set(BlockPos::new(-1, y, z, w), stone).unwrap();
+  }
+  world
+}
+
+fn run(world: &mut World, ticks: usize) {
+  for _ in 0..ticks {
+    world.tick();
+  }
+}
+
+#[test]
+fn falls_until_supported() {
+  let sand = Foxel::named("sand");
+  let mut world = world_with_floor(2);
+  world.set_foxel(BlockPos::new(6, 0, 0, 0), sand).unwrap();
+
+  run(&mut world, 3);
+  assert_eq!(world.foxels.get(BlockPos::new(3, 0, 0, 0)), Some(sand));
+  run(&mut world, 10);
+  assert_eq!(world.foxels.get(BlockPos::new(0, 0, 0, 0)), Some(sand));
+  assert_eq!(world.granular.pending(), 0);
+}
+
+#[test]
+fn piles_up() {
+  let gravel = Foxel::named("gravel");
+  let mut world = world_with_floor(6);
+  for _ in 0..20 {
+    world.set_foxel(BlockPos::new(8, 0, 0, 0), gravel).unwrap();
+    run(&mut world, 12);
+  }
+  run(&mut world, 40);
+
+  let grains = iproduct!(0..9, -6..=6, -6..=6, -6..=6)
+    .map(|(x, y, z, w)| BlockPos::new(x, y, z, w))
+    .filter(|pos| world.foxels.get(*pos) == Some(gravel))
+    .collect::<Vec<_>>();
+  assert_eq!(grains.len(), 20);
+  let height = grains.iter().map(|p| p.x).max().unwrap();
+  assert!(height < 4, "stacked {} high", height + 1);
+  // Nothing's left hanging over air
+  for pos in grains {
+    let below = BlockPos::new(pos.x - 1, pos.y, pos.z, pos.w);
+    assert_ne!(world.foxels.get(below), Some(Foxel::AIR), "{:?}", pos);
+  }
+}
+
+#[test]
+fn cascades_when_support_goes() {
+  let [sand, stone] = ["sand", "stone"].map(Foxel::named);
+  let mut world = world_with_floor(2);
+  for (y, z, w) in iproduct!(-2..=2, -2..=2, -2..=2) {
+    world.foxels.set(BlockPos::new(3, y, z, w), stone).unwrap();
+  }
+  for x in 4..8 {
+    world.set_foxel(BlockPos::new(x, 0, 0, 0), sand).unwrap();
+  }
+  // Held up by the shelf
+  run(&mut world, 10);
+  assert_eq!(world.foxels.get(BlockPos::new(4, 0, 0, 0)), Some(sand));
+
+  // Knock a hole right under it
+  world
+    .set_foxel(BlockPos::new(3, 0, 0, 0), Foxel::AIR)
+    .unwrap();
+  run(&mut world, 40);
+  let sand_count = iproduct!(0..8, -2..=2, -2..=2, -2..=2)
+    .filter(|(x, y, z, w)| {
+      world.foxels.get(BlockPos::new(*x, *y, *z, *w)) == Some(sand)
+    })
+    .count();
+  assert_eq!(sand_count, 4);
+  assert_eq!(
+    world.foxels.get(BlockPos::new(4, 0, 0, 0)),
+    Some(Foxel::AIR)
+  );
+  assert_eq!(world.foxels.get(BlockPos::new(0, 0, 0, 0)), Some(sand));
+}
+
+#[test]
+fn cascades_when_a_whole_brick_goes() {
+  let sand = Foxel::named("sand");
+  let mut world = world_with_floor(2);
+  let shelf = BlockPos::new(8, 0, 0, 0);
+  world
+    .foxels
+    .fill_brick(shelf, Foxel::named("stone"))
+    .unwrap();
+  world.set_foxel(BlockPos::new(16, 1, 1, 1), sand).unwrap();
+  run(&mut world, 10);
+  assert_eq!(world.foxels.get(BlockPos::new(16, 1, 1, 1)), Some(sand));
+
+  world.foxels.fill_brick(shelf, Foxel::AIR).unwrap();
+  run(&mut world, 40);
+  assert_eq!(world.foxels.get(BlockPos::new(0, 1, 1, 1)), Some(sand));
+}
+
+#[test]
+fn sinks_through_water() {
+  let [sand, water, stone] = ["sand", "water", "stone"].map(Foxel::named);
+  let mut world = World::new(Vec4::unit_x());
+  // A one-wide well, so the water can't go anywhere
+  for (x, y, z, w) in iproduct!(-1..4, -1..=1, -1..=1, -1..=1) {
+    if x == -1 || (y, z, w) != (0, 0, 0) {
+      world.foxels.set(BlockPos::new(x, y, z, w), stone).unwrap();
+    }
+  }
+  world.set_foxel(BlockPos::new(0, 0, 0, 0), water).unwrap();
+  world.set_foxel(BlockPos::new(1, 0, 0, 0), sand).unwrap();
+
+  run(&mut world, 2);
+  assert_eq!(world.foxels.get(BlockPos::new(0, 0, 0, 0)), Some(sand));
+  assert_eq!(world.foxels.get(BlockPos::new(1, 0, 0, 0)), Some(water));
+}
+
+#[test]
+fn only_reacts_to_changes() {
+  let sand = Foxel::named("sand");
+  let mut world = World::new(Vec4::unit_x());
+  world.foxels.track_changes(false);
+  world.foxels.set(BlockPos::new(5, 0, 0, 0), sand).unwrap();
+  world.foxels.track_changes(true);
+  run(&mut world, 5);
+  assert_eq!(world.foxels.get(BlockPos::new(5, 0, 0, 0)), Some(sand));
+
+  // Poking something next to it is enough
+  world.foxels.set(BlockPos::new(4, 0, 0, 1), sand).unwrap();
+  run(&mut world, 3);
+  assert_eq!(
+    world.foxels.get(BlockPos::new(5, 0, 0, 0)),
+    Some(Foxel::AIR)
+  );
+}
+
+#[test]
+fn nothing_lost_when_out_of_room() {
+  let sand = Foxel::named("sand");
+  let mut tree = Hexadecitree::new();
+  // Can't fall into the brick of air below it
+  let stuck = BlockPos::new(0, 0, 0, 0);
+  tree.set(stuck, sand).unwrap();
+  // Falls within its brick
+  let free = BlockPos::new(5, 0, 0, 0);
+  tree.set(free, sand).unwrap();
+  // Can fall out, but can't leave a hole behind in a brick of all sand
+  let full = BlockPos::new(0, 8, 0, 0);
+  tree.fill_brick(full, sand).unwrap();
+  tree
+    .set(BlockPos::new(-8, 15, 7, 7), Foxel::named("stone"))
+    .unwrap();
+  use_up_memory(&mut tree);
+
+  let mut sim = GranularSim::new();
+  for pos in [stuck, free, full] {
+    sim.wake(pos);
+  }
+  assert_eq!(sim.step(&mut tree), Err(SetFoxelError::OutOfMemory));
+  assert_eq!(tree.get(stuck), Some(sand));
+  assert_eq!(tree.get(free), Some(Foxel::AIR));
+  assert_eq!(tree.get(BlockPos::new(4, 0, 0, 0)), Some(sand));
+  assert_eq!(tree.get(full), Some(sand));
+  assert_eq!(tree.get(BlockPos::new(-1, 8, 0, 0)), Some(Foxel::AIR));
+  let grains = iproduct!(-8..8, 0..16, 0..8, 0..8)
+    .filter(|&(x, y, z, w)| tree.get(BlockPos::new(x, y, z, w)) == Some(sand))
+    .count();
+  assert_eq!(grains, 2 + Hexadecitree::FOXELS_PER_BRICK as usize);
+}
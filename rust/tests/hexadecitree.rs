@@ -1,20 +1,31 @@
+mod common;
+
+use common::{random_point, random_rays, scattered_tree};
 use itertools::iproduct;
 use tesseractory::{
-  math::{hexadecitree::Hexadecitree, BlockPos},
+  math::{
+    hexadecitree::{
+      packet::LANES, reprs::Brick, state::FoxelState, trace::OPAQUE_ENOUGH,
+      Hexadecitree, SetFoxelError,
+    },
+    rng::SplitMix64,
+    BlockPos,
+  },
   world::foxel::Foxel,
 };
+use ultraviolet::{IVec4, Vec3, Vec4, Vec4x8};
 
 #[test]
 fn smoke() {
   let palette = [
-    Foxel::Red,
-    Foxel::Green,
-    Foxel::Blue,
-    Foxel::RG,
-    Foxel::GB,
-    Foxel::RB,
-    Foxel::White,
-    Foxel::Black,
+    Foxel::named("red"),
+    Foxel::named("green"),
+    Foxel::named("blue"),
+    Foxel::named("rg"),
+    Foxel::named("gb"),
+    Foxel::named("rb"),
+    Foxel::named("white"),
+    Foxel::named("black"),
   ];
 
   let mut h = Hexadecitree::new();
@@ -30,3 +41,286 @@ fn smoke() {
 
   // panic!("{}", h.memory());
 }
+
+/// Every position should get its own slot, especially around the negative
+/// brick boundaries.
+#[test]
+fn no_aliasing() {
+  let mut h = Hexadecitree::new();
+  let range = -17..17;
+  let foxel_for = |x: i32, y: i32| {
+    if (x + 2 * y).rem_euclid(3) == 0 {
+      Foxel::named("red")
+    } else {
+      Foxel::named("blue")
+    }
+  };
+  for (x, y) in iproduct!(range.clone(), range.clone()) {
+    h.set(BlockPos::new(x, y, 0, -8), foxel_for(x, y)).unwrap();
+  }
+  for (x, y) in iproduct!(range.clone(), range.clone()) {
+    let pos = BlockPos::new(x, y, 0, -8);
+    assert_eq!(h.get(pos), Some(foxel_for(x, y)), "{:?}", pos);
+  }
+}
+
+#[test]
+fn state() {
+  let mut h = Hexadecitree::new();
+  let stone = Foxel::named("stone");
+  let pos = BlockPos::new(-3, 5, 8, -9);
+
+  assert_eq!(h.get_state(pos), Some(FoxelState::NONE));
+  h.set_with_state(pos, stone, FoxelState(7)).unwrap();
+  assert_eq!(h.get_with_state(pos), Some((stone, FoxelState(7))));
+
+  // Same foxel keeps the state
+  h.set(pos, stone).unwrap();
+  assert_eq!(h.get_state(pos), Some(FoxelState(7)));
+  assert_eq!(h.set_state(pos, FoxelState(9)), Ok(FoxelState(7)));
+
+  let found = h.states_in_brick(pos).collect::<Vec<_>>();
+  assert_eq!(found, vec![(pos, FoxelState(9))]);
+  assert_eq!(h.states().count(), 1);
+
+  // Changing it doesn't
+  h.set(pos, Foxel::named("dirt")).unwrap();
+  assert_eq!(h.get_state(pos), Some(FoxelState::NONE));
+  assert_eq!(h.state_count(), 0);
+
+  assert_eq!(
+    h.set_state(BlockPos::new(1000, 0, 0, 0), FoxelState(1)),
+    Err(SetFoxelError::OutOfBounds)
+  );
+}
+
+#[test]
+fn state_cleared_by_whole_bricks() {
+  let mut h = Hexadecitree::new();
+  let (stone, dirt) = (Foxel::named("stone"), Foxel::named("dirt"));
+  let kept = BlockPos::new(0, 0, 0, 1);
+  let lost = BlockPos::new(0, 0, 0, 2);
+  h.fill_brick(kept, stone).unwrap();
+  h.set_state(kept, FoxelState(1)).unwrap();
+  h.set_state(lost, FoxelState(2)).unwrap();
+
+  let mut brick = Brick::composite_solid(stone);
+  brick.set(IVec4::new(0, 0, 0, 2), dirt);
+  h.set_brick(kept, &brick).unwrap();
+  assert_eq!(h.get_state(kept), Some(FoxelState(1)));
+  assert_eq!(h.get_state(lost), Some(FoxelState::NONE));
+
+  h.fill_brick(kept, dirt).unwrap();
+  assert_eq!(h.state_count(), 0);
+}
+
+#[test]
+fn trace_through_glass() {
+  let glass = Foxel::named("glass");
+  let stone = Foxel::named("stone");
+  let mut h = Hexadecitree::new();
+  let start = Vec4::new(0.5, 0.5, 0.5, 0.5);
+  let dir = Vec4::unit_y();
+
+  let empty = h.trace(start, dir, 32);
+  assert_eq!(empty.opacity, 0.0);
+  assert_eq!(empty.hit, None);
+  let sky = Vec3::new(0.2, 0.4, 0.8);
+  assert_eq!(empty.over(sky), sky);
+
+  h.set(BlockPos::new(0, 3, 0, 0), glass).unwrap();
+  let through = h.trace(start, dir, 32);
+  assert_eq!(through.hit, None);
+  assert!((through.opacity - glass.ty().opacity).abs() < 1e-6);
+
+  // Two panes let less through than one
+  h.set(BlockPos::new(0, 4, 0, 0), glass).unwrap();
+  let thicker = h.trace(start, dir, 32);
+  assert!(thicker.opacity > through.opacity && thicker.opacity < 1.0);
+
+  h.set(BlockPos::new(0, 10, 0, 0), stone).unwrap();
+  let blocked = h.trace(start, dir, 32);
+  let hit = blocked.hit.unwrap();
+  assert_eq!(hit.foxel, stone);
+  assert_eq!(hit.pos, BlockPos::new(0, 10, 0, 0));
+  assert_eq!(hit.normal, -Vec4::unit_y());
+  assert_eq!(blocked.opacity, 1.0);
+  // The sky doesn't show through, but the glass still tints the stone
+  assert_eq!(blocked.over(Vec3::one()), blocked.over(Vec3::zero()));
+  assert_ne!(
+    blocked.colour,
+    h.trace(Vec4::new(0.5, 5.5, 0.5, 0.5), dir, 32).colour
+  );
+}
+
+#[test]
+fn trace_stops_when_opaque_enough() {
+  let red_glass = Foxel::named("red-glass");
+  let mut h = Hexadecitree::new();
+  for y in 1..20 {
+    h.set(BlockPos::new(0, y, 0, 0), red_glass).unwrap();
+  }
+  let trace = h.trace(Vec4::new(0.5, 0.5, 0.5, 0.5), Vec4::unit_y(), 64);
+  assert!(trace.opacity >= OPAQUE_ENOUGH);
+  let hit = trace.hit.unwrap();
+  assert_eq!(hit.foxel, red_glass);
+  assert!(hit.pos.y < 19);
+}
+
+#[test]
+fn changes_are_reported() {
+  let stone = Foxel::named("stone");
+  let mut h = Hexadecitree::new();
+  h.set(BlockPos::new(1, 2, 3, 4), stone).unwrap();
+  assert!(h.take_changes().is_empty());
+
+  h.track_changes(true);
+  let a = BlockPos::new(1, 2, 3, 4);
+  let b = BlockPos::new(-9, 0, 0, 0);
+  h.set(a, Foxel::AIR).unwrap();
+  h.set(b, stone).unwrap();
+  // Setting something to what it already is isn't a change
+  h.set(b, stone).unwrap();
+  h.set_state(b, FoxelState(3)).unwrap();
+  assert_eq!(h.take_changes(), vec![(a, stone), (b, Foxel::AIR)]);
+  assert!(h.take_changes().is_empty());
+
+  // Whole bricks count every foxel in them that changed
+  let corner = BlockPos::new(64, 0, 0, 0);
+  h.set(corner, stone).unwrap();
+  h.take_changes();
+  h.fill_brick(corner, stone).unwrap();
+  let changes = h.take_changes();
+  assert_eq!(changes.len(), Hexadecitree::FOXELS_PER_BRICK as usize - 1);
+  assert!(changes.iter().all(|(_, old)| *old == Foxel::AIR));
+  assert!(!changes.iter().any(|(pos, _)| *pos == corner));
+  h.fill_brick(corner, stone).unwrap();
+  assert!(h.take_changes().is_empty());
+
+  h.track_changes(false);
+  h.set(a, stone).unwrap();
+  assert!(h.take_changes().is_empty());
+}
+
+#[test]
+fn raycast_hits_a_wall() {
+  let stone = Foxel::named("stone");
+  let mut h = Hexadecitree::new();
+  for (y, z, w) in iproduct!(-4..4, -4..4, -4..4) {
+    h.set(BlockPos::new(5, y, z, w), stone).unwrap();
+    h.set(BlockPos::new(-128, y, z, w), stone).unwrap();
+  }
+
+  let start = Vec4::broadcast(0.5);
+  let hit = h.raycast(start, Vec4::unit_x() * 3.0, 20.0).unwrap();
+  assert_eq!(hit.foxel, stone);
+  assert_eq!(hit.pos, BlockPos::new(5, 0, 0, 0));
+  assert_eq!(hit.prev, BlockPos::new(4, 0, 0, 0));
+  assert_eq!(hit.normal, -Vec4::unit_x());
+  assert_eq!(hit.dist, 4.5);
+  assert_eq!(h.raycast(start, Vec4::unit_x(), 4.0), None);
+  assert_eq!(h.raycast(start, Vec4::unit_y(), 100.0), None);
+
+  // Right up against it, the start cell is the previous one
+  let hit = h.raycast(Vec4::new(4.9, 0.5, 0.5, 0.5), Vec4::unit_x(), 1.0);
+  assert_eq!(hit.unwrap().prev, BlockPos::new(4, 0, 0, 0));
+
+  // Coming in from outside the world
+  let outside = Vec4::new(-140.5, 0.5, 0.5, 0.5);
+  let hit = h.raycast(outside, Vec4::unit_x(), 50.0).unwrap();
+  assert_eq!(hit.pos, BlockPos::new(-128, 0, 0, 0));
+  assert_eq!(hit.dist, 12.5);
+
+  let trace = h.trace(start, Vec4::unit_x(), 20);
+  assert_eq!(trace.hit.unwrap().dist, 4.5);
+}
+
+/// Check against plain old ray-box intersection.
+#[test]
+fn raycast_matches_slabs() {
+  let stone = Foxel::named("stone");
+  let mut h = Hexadecitree::new();
+  for (x, y, z, w) in iproduct!(-2..2, -2..2, -2..2, -2..2) {
+    h.set(BlockPos::new(x, y, z, w), stone).unwrap();
+  }
+
+  let mut rng = SplitMix64::new(41);
+  for _ in 0..500 {
+    let start = random_point(&mut rng, 10.0);
+    let target = random_point(&mut rng, 1.5);
+    let dir = (target - start).normalized();
+    if start.as_array().iter().all(|v| (-2.0..2.0).contains(v)) {
+      continue;
+    }
+
+    let (mut enter, mut enter_axis) = (f32::MIN, 0);
+    for axis in 0..4 {
+      let (a, b) = (
+        (-2.0 - start[axis]) / dir[axis],
+        (2.0 - start[axis]) / dir[axis],
+      );
+      if a.min(b) > enter {
+        (enter, enter_axis) = (a.min(b), axis);
+      }
+    }
+
+    let hit = h.raycast(start, dir, 100.0).unwrap();
+    assert!(
+      (hit.dist - enter).abs() < 1e-3,
+      "{:?} along {:?}: {} vs {}",
+      start,
+      dir,
+      hit.dist,
+      enter
+    );
+    assert_eq!(hit.normal[enter_axis], -dir[enter_axis].signum());
+    let normal = IVec4::from(hit.normal.as_array().map(|v| v as i32));
+    assert_eq!(hit.prev.0, hit.pos.0 + normal);
+  }
+}
+
+/// Some stone and glass scattered about, with bricks of nothing in between.
+fn scattered(seed: u64) -> Hexadecitree {
+  let palette = ["stone", "glass"].map(Foxel::named);
+  scattered_tree(seed, 5000, 10, &palette)
+}
+
+#[test]
+fn raycast_x8_matches_raycast() {
+  let h = scattered(43);
+  let mut rng = SplitMix64::new(44);
+  let mut hits = 0;
+  for _ in 0..100 {
+    let (starts, dirs) = random_rays(&mut rng);
+    let packet = h.raycast_x8(Vec4x8::from(starts), Vec4x8::from(dirs), 80.0);
+    for lane in 0..LANES {
+      let single = h.raycast(starts[lane], dirs[lane], 80.0);
+      assert_eq!(
+        packet[lane], single,
+        "{:?} along {:?}",
+        starts[lane], dirs[lane]
+      );
+      hits += single.is_some() as usize;
+    }
+  }
+  assert!(hits > 300);
+}
+
+#[test]
+fn trace_x8_matches_trace() {
+  let h = scattered(45);
+  let mut rng = SplitMix64::new(46);
+  for _ in 0..100 {
+    let (starts, dirs) = random_rays(&mut rng);
+    let dirs = dirs.map(|d| d.normalized());
+    let packet = h.trace_x8(Vec4x8::from(starts), Vec4x8::from(dirs), 200);
+    for lane in 0..LANES {
+      let single = h.trace(starts[lane], dirs[lane], 200);
+      assert_eq!(
+        packet[lane], single,
+        "{:?} along {:?}",
+        starts[lane], dirs[lane]
+      );
+    }
+  }
+}
@@ -0,0 +1,165 @@
+use itertools::iproduct;
+use tesseractory::{
+  math::{rng::SplitMix64, BlockPos},
+  world::{
+    foxel::Foxel,
+    light::{BrickLight, Light, LightMap},
+    World,
+  },
+};
+use ultraviolet::Vec4;
+
+fn world() -> World {
+  World::new(Vec4::unit_x())
+}
+
+fn light(world: &World, x: i32, y: i32, z: i32, w: i32) -> Light {
+  world
+    .light
+    .get(&world.foxels, BlockPos::new(x, y, z, w))
+    .unwrap()
+}
+
+#[test]
+fn open_sky() {
+  let world = world();
+  assert_eq!(light(&world, 0, 0, 0, 0), Light::SKY);
+  assert_eq!(light(&world, -100, 50, -3, 7), Light::SKY);
+  assert_eq!(world.light.stored_bricks(), 0);
+  assert!(matches!(
+    world.light.brick(&world.foxels, BlockPos::new(0, 0, 0, 0)),
+    BrickLight::Uniform(Light::SKY)
+  ));
+}
+
+#[test]
+fn roof_with_a_hole() {
+  let stone = Foxel::named("stone");
+  let mut world = world();
+  for (y, z, w) in iproduct!(-8..=8, -8..=8, -8..=8) {
+    if (y, z, w) != (0, 0, 0) {
+      world.foxels.set(BlockPos::new(10, y, z, w), stone).unwrap();
+    }
+  }
+  world.light.rebuild(&world.foxels);
+
+  assert_eq!(light(&world, 11, 0, 0, 0).sky(), 15);
+  assert_eq!(light(&world, 10, 1, 0, 0).sky(), 0);
+  // Straight down through the hole doesn't lose anything
+  assert_eq!(light(&world, 0, 0, 0, 0).sky(), 15);
+  assert_eq!(light(&world, 5, 1, 0, 0).sky(), 14);
+  assert_eq!(light(&world, 5, 3, 0, 0).sky(), 12);
+  assert_eq!(light(&world, 5, 2, 2, 0).sky(), 11);
+  // Comes in from around the edge too
+  assert_eq!(light(&world, 5, 8, 8, 8).sky(), 14);
+  assert!(matches!(
+    world.light.brick(&world.foxels, BlockPos::new(5, 0, 0, 0)),
+    BrickLight::Mixed(_)
+  ));
+}
+
+#[test]
+fn lava_glows() {
+  let [lava, stone, glass] = ["lava", "stone", "glass"].map(Foxel::named);
+  let mut world = world();
+  world.set_foxel(BlockPos::new(0, 0, 0, 0), lava).unwrap();
+  assert_eq!(light(&world, 0, 0, 0, 0).block(), 15);
+  assert_eq!(light(&world, 0, 3, 0, 0).block(), 12);
+  assert_eq!(light(&world, 0, 1, 1, 0).block(), 13);
+  assert_eq!(light(&world, -20, 0, 0, 0).block(), 0);
+
+  // Glass dims it a bit more
+  world.set_foxel(BlockPos::new(0, 0, 0, 1), glass).unwrap();
+  assert_eq!(light(&world, 0, 0, 0, 1).block(), 13);
+  assert_eq!(light(&world, 0, 0, 0, 2).block(), 12);
+
+  // And a wall blocks it, so it has to go the long way round
+  for (x, z, w) in iproduct!(-20..=20, -20..=20, -20..=20) {
+    world.set_foxel(BlockPos::new(x, 2, z, w), stone).unwrap();
+  }
+  assert_eq!(light(&world, 0, 3, 0, 0).block(), 0);
+
+  world
+    .set_foxel(BlockPos::new(0, 0, 0, 0), Foxel::AIR)
+    .unwrap();
+  assert_eq!(light(&world, 0, 1, 0, 0).block(), 0);
+}
+
+#[test]
+fn incremental_matches_rebuild() {
+  let palette = ["stone", "stone", "stone", "glass", "water", "lava", "air"]
+    .map(Foxel::named);
+  let mut rng = SplitMix64::new(3);
+  let mut world = world();
+  // A lumpy roof over a room, then a lot of fiddling with it
+  for (y, z, w) in iproduct!(-6..=6, -6..=6, -6..=6) {
+    world
+      .set_foxel(BlockPos::new(6, y, z, w), palette[0])
+      .unwrap();
+  }
+  for _ in 0..300 {
+    let pos = BlockPos::new(
+      rng.range_i32(-2, 9),
+      rng.range_i32(-7, 8),
+      rng.range_i32(-7, 8),
+      rng.range_i32(-7, 8),
+    );
+    let foxel = palette[rng.range_i32(0, palette.len() as i32) as usize];
+    world.set_foxel(pos, foxel).unwrap();
+  }
+
+  let mut fresh = LightMap::new();
+  fresh.rebuild(&world.foxels);
+  for (x, y, z, w) in iproduct!(-4..12, -10..10, -10..10, -10..10) {
+    let pos = BlockPos::new(x, y, z, w);
+    assert_eq!(
+      world.light.get(&world.foxels, pos),
+      fresh.get(&world.foxels, pos),
+      "at {:?}, which is {:?}",
+      pos,
+      world.foxels.get(pos)
+    );
+  }
+}
+
+/// Whole bricks written at once get caught up one foxel at a time too.
+#[test]
+fn whole_bricks_match_rebuild() {
+  let mut world = world();
+  // A roof across a few bricks, to cover and uncover things under
+  for (y, z, w) in iproduct!(-2..2, -2..2, -2..2) {
+    let corner = BlockPos::new(16, y * 8, z * 8, w * 8);
+    world
+      .foxels
+      .fill_brick(corner, Foxel::named("stone"))
+      .unwrap();
+  }
+  world.foxels.take_changes();
+  world.light.rebuild(&world.foxels);
+
+  let steps = [
+    (BlockPos::new(0, 0, 0, 0), "stone"),
+    (BlockPos::new(8, 0, 0, 0), "lava"),
+    (BlockPos::new(16, -8, 0, 0), "air"),
+    (BlockPos::new(16, 0, 0, 0), "glass"),
+    (BlockPos::new(8, 0, 0, 0), "air"),
+  ];
+  for (corner, name) in steps {
+    world.foxels.fill_brick(corner, Foxel::named(name)).unwrap();
+    world.wake_changed();
+
+    let mut fresh = LightMap::new();
+    fresh.rebuild(&world.foxels);
+    for (x, y, z, w) in iproduct!(-8..32, -16..16, -8..16, -8..16) {
+      let pos = BlockPos::new(x, y, z, w);
+      assert_eq!(
+        world.light.get(&world.foxels, pos),
+        fresh.get(&world.foxels, pos),
+        "after filling {:?} with {}, at {:?}",
+        corner,
+        name,
+        pos
+      );
+    }
+  }
+}
@@ -0,0 +1,107 @@
+use tesseractory::math::noise::*;
+use ultraviolet::{f32x8, Vec4, Vec4x8};
+
+/// A bunch of spread-out, non-lattice-aligned points
+fn points() -> Vec<Vec4> {
+  (0..256)
+    .map(|i| {
+      let i = i as f32;
+      Vec4::new(
+        i * 0.37 - 40.0,
+        (i * 1.71).sin() * 30.0,
+        i * -0.13 + 5.5,
+        (i * 0.53).cos() * 12.0 - 3.0,
+      )
+    })
+    .collect()
+}
+
+fn all_noises() -> Vec<(&'static str, Box<dyn Noise4>)> {
+  vec![
+    ("simplex", Box::new(Simplex4::new(1))),
+    ("value", Box::new(Value4::new(2))),
+    ("worley f1", Box::new(Worley4::new(3, WorleyMode::F1))),
+    (
+      "worley f2-f1",
+      Box::new(Worley4::new(3, WorleyMode::F2MinusF1)),
+    ),
+    ("fbm", Box::new(Fbm::new(Simplex4::new(4), 5))),
+    ("ridged", Box::new(Ridged::new(Value4::new(5), 4))),
+    (
+      "warp",
+      Box::new(DomainWarp::new(Simplex4::new(6), Value4::new(7), 2.0)),
+    ),
+  ]
+}
+
+#[test]
+fn deterministic() {
+  for ((name, a), (_, b)) in all_noises().into_iter().zip(all_noises()) {
+    for p in points() {
+      assert_eq!(a.sample(p), b.sample(p), "{} at {:?}", name, p);
+    }
+  }
+}
+
+#[test]
+fn seeds_matter() {
+  let a = Simplex4::new(10);
+  let b = Simplex4::new(11);
+  assert!(points().into_iter().any(|p| a.sample(p) != b.sample(p)));
+
+  let a = Value4::new(10);
+  let b = Value4::new(11);
+  assert!(points().into_iter().any(|p| a.sample(p) != b.sample(p)));
+}
+
+#[test]
+fn ranges() {
+  for (name, noise) in all_noises() {
+    let (lo, hi) = if name.starts_with("worley") {
+      (0.0, 2.0)
+    } else {
+      (-1.05, 1.05)
+    };
+    for p in points() {
+      let v = noise.sample(p);
+      assert!((lo..=hi).contains(&v), "{} at {:?} was {}", name, p, v);
+    }
+  }
+}
+
+#[test]
+fn wide_matches_scalar() {
+  let points = points();
+  for (name, noise) in all_noises() {
+    for chunk in points.chunks_exact(8) {
+      let chunk: [Vec4; 8] = chunk.try_into().unwrap();
+      let wide = noise.sample_x8(Vec4x8::from(chunk)).to_array();
+      let scalar = chunk.map(|p| noise.sample(p));
+      for (w, s) in wide.into_iter().zip(scalar) {
+        assert!((w - s).abs() < 1e-4, "{}: {} vs {}", name, w, s);
+      }
+    }
+  }
+}
+
+#[test]
+fn smooth() {
+  let step = Vec4::new(1e-3, -1e-3, 1e-3, 1e-3);
+  for (name, noise) in all_noises() {
+    for p in points() {
+      let diff = (noise.sample(p) - noise.sample(p + step)).abs();
+      assert!(diff < 0.05, "{} jumped by {} at {:?}", name, diff, p);
+    }
+  }
+}
+
+#[test]
+fn value_noise_hits_lattice() {
+  // At integer points, value noise is exactly the lattice value, so
+  // the x8 path with all-integer inputs should agree exactly too
+  let noise = Value4::new(99);
+  let p = Vec4x8::splat(Vec4::new(3.0, -2.0, 7.0, 0.0));
+  let wide = noise.sample_x8(p);
+  let scalar = noise.sample(Vec4::new(3.0, -2.0, 7.0, 0.0));
+  assert_eq!(wide.to_array(), f32x8::splat(scalar).to_array());
+}
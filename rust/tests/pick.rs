@@ -0,0 +1,96 @@
+use itertools::iproduct;
+use tesseractory::{
+  math::{geo::Rotor4, BlockPos},
+  render::{Camera, Renderer},
+  world::{foxel::Foxel, World},
+};
+use ultraviolet::{Vec2, Vec4};
+
+const ASPECT: f32 = 1000.0 / 600.0;
+
+/// A wall at Y = 4, in front of a camera at the origin looking along +Y.
+fn walled() -> (World, Camera) {
+  let mut world = World::new(Vec4::unit_x());
+  for (x, z, w) in iproduct!(-8..8, -8..8, -8..8) {
+    world
+      .foxels
+      .set(BlockPos::new(x, 4, z, w), Foxel::named("stone"))
+      .unwrap();
+  }
+  let camera =
+    Camera::new(Vec4::new(0.5, 0.5, 0.5, 0.5), Rotor4::identity(), 1.0, 0.1);
+  (world, camera)
+}
+
+#[test]
+fn picks_the_middle() {
+  let (world, camera) = walled();
+  let middle = Vec2::broadcast(0.5);
+  let pick = world.pick(&camera, middle, ASPECT, World::REACH).unwrap();
+  assert_eq!(pick.pos, BlockPos::new(0, 4, 0, 0));
+  assert_eq!(pick.place_pos, BlockPos::new(0, 3, 0, 0));
+  assert_eq!(pick.normal, -Vec4::unit_y());
+  assert_eq!(pick.foxel, Foxel::named("stone"));
+  assert!((pick.dist - 3.5).abs() < 1e-5);
+
+  assert_eq!(world.pick(&camera, middle, ASPECT, 3.4), None);
+}
+
+#[test]
+fn picks_what_gets_drawn() {
+  let (mut world, camera) = walled();
+  world
+    .foxels
+    .set(BlockPos::new(1, 3, -1, 0), Foxel::named("red"))
+    .unwrap();
+  let renderer = Renderer::new(20, 12);
+  let mut reds = 0;
+  for (x, y) in iproduct!(0..20, 0..12) {
+    let uv = renderer.uv(x, y);
+    let (start, dir) = camera.ray(uv, renderer.aspect());
+    let drawn = world.trace(start, dir, renderer.max_steps).hit.unwrap();
+    let pick = world.pick(&camera, uv, renderer.aspect(), 100.0).unwrap();
+    assert_eq!(pick.pos, drawn.pos, "at ({}, {})", x, y);
+    assert_eq!(pick.normal, drawn.normal, "at ({}, {})", x, y);
+    reds += (pick.foxel == Foxel::named("red")) as usize;
+  }
+  assert!(reds > 0);
+}
+
+#[test]
+fn breaking_and_placing() {
+  let (mut world, camera) = walled();
+  let middle = Vec2::broadcast(0.5);
+  let stone = Foxel::named("stone");
+  let placed = world
+    .place_picked(&camera, middle, ASPECT, World::REACH, Foxel::named("sand"))
+    .unwrap();
+  assert_eq!(placed, Some(BlockPos::new(0, 3, 0, 0)));
+  assert_eq!(
+    world.foxels.get(BlockPos::new(0, 3, 0, 0)),
+    Some(Foxel::named("sand"))
+  );
+
+  let broken = world
+    .break_picked(&camera, middle, ASPECT, World::REACH)
+    .unwrap();
+  assert_eq!(broken, Some(Foxel::named("sand")));
+  let broken = world
+    .break_picked(&camera, middle, ASPECT, World::REACH)
+    .unwrap();
+  assert_eq!(broken, Some(stone));
+  assert_eq!(
+    world.foxels.get(BlockPos::new(0, 4, 0, 0)),
+    Some(Foxel::AIR)
+  );
+
+  // Nothing there through the hole
+  assert_eq!(
+    world.break_picked(&camera, middle, ASPECT, 100.0).unwrap(),
+    None
+  );
+  let placed = world
+    .place_picked(&camera, middle, ASPECT, 100.0, stone)
+    .unwrap();
+  assert_eq!(placed, None);
+}
@@ -0,0 +1,294 @@
+use itertools::iproduct;
+use tesseractory::{
+  math::{geo::Rotor4, hexadecitree::trace::face_light, BlockPos},
+  render::{
+    sky, to_rgba, Camera, Image, PpmError, RenderProgress, Renderer, ShadowMask,
+  },
+  world::{
+    foxel::Foxel,
+    scenes::{SceneRegistry, SceneSpec},
+    World,
+  },
+};
+use ultraviolet::{Vec2, Vec3, Vec4};
+
+fn camera(rot: Rotor4) -> Camera {
+  Camera::new(Vec4::new(0.5, 0.5, 0.5, 0.5), rot, 1.0, 0.1)
+}
+
+fn foxel_colour(foxel: Foxel) -> Vec3 {
+  let [r, g, b] = foxel.ty().colour.map(|c| c as f32 / 255.0);
+  Vec3::new(r, g, b)
+}
+
+#[test]
+fn rays_like_the_shader() {
+  let cam = camera(Rotor4::identity());
+  let (start, dir) = cam.ray(Vec2::broadcast(0.5), 2.0);
+  assert_eq!(dir, Vec4::unit_y());
+  assert_eq!(start, cam.pos + Vec4::unit_y() * 0.1);
+
+  // Up the screen is +X, left is +Z
+  let (_, top) = cam.ray(Vec2::new(0.5, 0.0), 2.0);
+  assert!(top.x > 0.0 && top.z == 0.0);
+  let (_, left) = cam.ray(Vec2::new(0.0, 0.5), 2.0);
+  assert!(left.z > 0.0 && left.x == 0.0);
+  // It's twice as wide, so it goes twice as far sideways
+  assert!((left.z / left.y - 2.0 * top.x / top.y).abs() < 1e-6);
+
+  let down = Rotor4::from_rotation_between(Vec4::unit_y(), -Vec4::unit_x());
+  let (_, dir) = camera(down).ray(Vec2::broadcast(0.5), 2.0);
+  assert!((dir - -Vec4::unit_x()).mag() < 1e-5);
+}
+
+#[test]
+fn empty_world_is_sky() {
+  let mut world = World::new(Vec4::unit_x());
+  let renderer = Renderer::new(8, 6);
+  let image = renderer.render(&mut world, &camera(Rotor4::identity()));
+  assert_eq!((image.width(), image.height()), (8, 6));
+  for (x, y) in iproduct!(0..8, 0..6) {
+    let (_, dir) = camera(Rotor4::identity()).ray(renderer.uv(x, y), 8.0 / 6.0);
+    assert_eq!(image.get(x, y), to_rgba(sky(dir)));
+  }
+  // Straight along Y the sky is as green as it gets
+  assert_eq!(to_rgba(sky(Vec4::unit_y())), [0, 230, 0, 255]);
+}
+
+#[test]
+fn sees_a_wall() {
+  let stone = Foxel::named("stone");
+  let mut world = World::new(Vec4::unit_x());
+  for (x, z, w) in iproduct!(-20..20, -20..20, -20..20) {
+    world.foxels.set(BlockPos::new(x, 10, z, w), stone).unwrap();
+  }
+  let renderer = Renderer::new(9, 9);
+  let image = renderer.render(&mut world, &camera(Rotor4::identity()));
+  let lit = foxel_colour(stone) * face_light(-Vec4::unit_y());
+  assert_eq!(image.get(4, 4), to_rgba(lit));
+  // The wall fills the whole view
+  assert!(image.pixels().iter().all(|p| *p == to_rgba(lit)));
+
+  // Turning round, there's nothing there
+  let back = Rotor4::from_rotation_between(
+    Vec4::unit_y(),
+    -Vec4::unit_y() + Vec4::unit_z() * 1e-3,
+  );
+  let image = renderer.render(&mut world, &camera(back));
+  assert_ne!(image.get(4, 4), to_rgba(lit));
+}
+
+#[test]
+fn writes_ppm() {
+  let mut image = Image::new(3, 2);
+  image.set(2, 1, [10, 20, 30, 255]);
+  let mut out = Vec::new();
+  image.write_ppm(&mut out).unwrap();
+  let header = b"P6\n3 2\n255\n";
+  assert_eq!(&out[..header.len()], header);
+  assert_eq!(out.len(), header.len() + 3 * 2 * 3);
+  assert_eq!(&out[out.len() - 3..], &[10, 20, 30]);
+  assert_eq!(image.as_bytes().len(), 3 * 2 * 4);
+}
+
+#[test]
+fn ppm_round_trip() {
+  let mut image = Image::new(5, 3);
+  image.set(4, 2, [10, 20, 30, 255]);
+  image.set(0, 1, [200, 0, 7, 255]);
+  let mut out = Vec::new();
+  image.write_ppm(&mut out).unwrap();
+  assert_eq!(Image::read_ppm(&out[..]).unwrap(), image);
+
+  for bad in [
+    &b"P3\n1 1\n255\n123"[..],
+    b"P6\n2 1\n255\n123",
+    b"P6\n1",
+    // Way too big to ever allocate
+    b"P6\n4294967295 4294967295\n255\n123",
+  ] {
+    assert!(matches!(Image::read_ppm(bad), Err(PpmError::Malformed(_))));
+  }
+}
+
+#[test]
+fn diffs_find_changes() {
+  let image = Image::new(4, 4);
+  let mut other = image.clone();
+  other.set(1, 2, [2, 0, 0, 255]);
+  assert!(image.diff(&other, 2).matches());
+
+  other.set(3, 3, [0, 9, 0, 255]);
+  let diff = image.diff(&other, 2);
+  assert_eq!(diff.differing, 1);
+  assert_eq!(diff.max, 9);
+  assert_eq!(diff.image.get(3, 3), [255, 0, 255, 255]);
+  assert_ne!(diff.image.get(1, 2), [255, 0, 255, 255]);
+
+  assert!(!image.diff(&Image::new(4, 3), 255).matches());
+}
+
+#[test]
+fn packets_match_single_rays() {
+  let stone = Foxel::named("stone");
+  let glass = Foxel::named("glass");
+  let mut world = World::new(Vec4::unit_x());
+  for (x, z, w) in iproduct!(-20..20, -20..20, -20..20) {
+    let foxel = if (x + z + w) % 3 == 0 { glass } else { stone };
+    world
+      .foxels
+      .set(BlockPos::new(x, 6 + (x + w) % 2, z, w), foxel)
+      .unwrap();
+  }
+  // Not a whole number of packets wide
+  let renderer = Renderer::new(13, 7);
+  let rot = Rotor4::from_rotation_between(
+    Vec4::unit_y(),
+    Vec4::new(0.3, 1.0, -0.2, 0.4).normalized(),
+  );
+  let camera = camera(rot);
+  let image = renderer.render(&mut world, &camera);
+  for (x, y) in iproduct!(0..13, 0..7) {
+    let single = to_rgba(renderer.pixel(&mut world, &camera, x, y));
+    assert_eq!(image.get(x, y), single, "at ({}, {})", x, y);
+  }
+}
+
+/// A floor below the camera, and a roof over part of it, with the sun
+/// straight overhead.
+fn roofed(roof_z: std::ops::Range<i32>, roof_w: std::ops::Range<i32>) -> World {
+  let stone = Foxel::named("stone");
+  let mut world = World::new(-Vec4::unit_x());
+  for (y, z, w) in iproduct!(-20..40, -20..20, -20..20) {
+    world.foxels.set(BlockPos::new(-3, y, z, w), stone).unwrap();
+    if roof_z.contains(&z) && roof_w.contains(&w) {
+      world.foxels.set(BlockPos::new(4, y, z, w), stone).unwrap();
+    }
+  }
+  world
+}
+
+/// Off the grid a bit, so no rays go exactly through foxel corners.
+fn roofed_camera() -> Camera {
+  Camera::new(Vec4::new(0.3, 0.6, 0.45, 0.4), Rotor4::identity(), 1.0, 0.1)
+}
+
+fn check_mask(
+  renderer: &Renderer,
+  world: &mut World,
+  camera: &Camera,
+  mask: &ShadowMask,
+) {
+  for (x, y) in iproduct!(0..renderer.width, 0..renderer.height) {
+    let single = renderer.shadow(world, camera, x, y);
+    assert_eq!(mask.get(x, y), single, "at ({}, {})", x, y);
+  }
+}
+
+#[test]
+fn roof_casts_a_shadow() {
+  // Only over +Z, which is the left of the screen
+  let mut world = roofed(0..20, -20..20);
+  let renderer = Renderer::new(10, 10);
+  let camera = roofed_camera();
+  let (image, mask) = renderer.render_with_shadows(&mut world, &camera);
+  check_mask(&renderer, &mut world, &camera, &mask);
+
+  // Looking down at the floor on both sides
+  assert!(mask.is_shadowed(0, 9));
+  assert_eq!(mask.get(0, 9), 1.0);
+  assert_eq!(mask.get(9, 9), 0.0);
+  let floor = foxel_colour(Foxel::named("stone")) * face_light(Vec4::unit_x());
+  assert_eq!(image.get(9, 9), to_rgba(floor));
+  let dark = floor * (1.0 - Renderer::SHADOW_DARKEN);
+  assert_eq!(image.get(0, 9), to_rgba(dark));
+  // The underside of the roof faces away from the sun, so `face_light`
+  // takes care of it instead
+  assert_eq!(mask.get(0, 0), 0.0);
+
+  let no_shadows = Renderer {
+    shadows: false,
+    ..renderer
+  };
+  let (image, mask) = no_shadows.render_with_shadows(&mut world, &camera);
+  assert!(mask.values().iter().all(|v| *v == 0.0));
+  assert_eq!(image.get(0, 9), to_rgba(floor));
+}
+
+#[test]
+fn shadows_go_along_w() {
+  // The roof covers everything, but only on one side of W
+  let mut world = roofed(-20..20, 0..20);
+  let renderer = Renderer::new(8, 8);
+  let under = roofed_camera();
+  let (_, mask) = renderer.render_with_shadows(&mut world, &under);
+  check_mask(&renderer, &mut world, &under, &mask);
+  assert!((0..8).all(|x| mask.is_shadowed(x, 7)));
+
+  let beside = Camera {
+    pos: under.pos - Vec4::unit_w() * 10.0,
+    ..under
+  };
+  let (_, mask) = renderer.render_with_shadows(&mut world, &beside);
+  assert!((0..8).all(|x| !mask.is_shadowed(x, 7)));
+}
+
+fn pools() -> World {
+  let spec = SceneSpec::new("pools", 0);
+  let sun_dir = Vec4::new(-0.5, 0.4, 0.2, 0.1).normalized();
+  SceneRegistry::builtin().build(&spec, sun_dir).unwrap()
+}
+
+fn pools_camera() -> Camera {
+  let rot = Rotor4::from_rotation_between(
+    Vec4::unit_y(),
+    Vec4::new(-1.0, 0.8, 0.1, 0.0).normalized(),
+  );
+  Camera::new(Vec4::new(12.4, -9.6, 0.3, 0.45), rot, 1.0, 0.1)
+}
+
+#[test]
+fn same_on_any_number_of_threads() {
+  let renderer = Renderer {
+    tile_size: 16,
+    ..Renderer::new(40, 30)
+  };
+  let renders = [1, 4].map(|threads| {
+    let pool = rayon::ThreadPoolBuilder::new()
+      .num_threads(threads)
+      .build()
+      .unwrap();
+    pool.install(|| renderer.render_with_shadows(&mut pools(), &pools_camera()))
+  });
+  assert_eq!(renders[0], renders[1]);
+
+  // And the same as one big tile
+  let one_tile = Renderer {
+    tile_size: 1000,
+    ..renderer
+  };
+  let render = one_tile.render_with_shadows(&mut pools(), &pools_camera());
+  assert_eq!(render, renders[0]);
+  assert!(render.1.values().iter().any(|v| *v > 0.5));
+}
+
+#[test]
+fn progress_and_cancelling() {
+  let renderer = Renderer {
+    tile_size: 16,
+    ..Renderer::new(40, 30)
+  };
+  let mut world = pools();
+  let progress = RenderProgress::new();
+  assert_eq!(progress.fraction(), 0.0);
+  let render = renderer.render_tiles(&mut world, &pools_camera(), &progress);
+  assert!(render.is_some());
+  assert_eq!(progress.tiles_total(), 3 * 2);
+  assert_eq!(progress.tiles_done(), 3 * 2);
+  assert_eq!(progress.fraction(), 1.0);
+
+  progress.cancel();
+  let render = renderer.render_tiles(&mut world, &pools_camera(), &progress);
+  assert!(render.is_none());
+  assert!(progress.tiles_done() < progress.tiles_total());
+}
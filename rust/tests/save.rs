@@ -0,0 +1,86 @@
+use tesseractory::{
+  math::{hexadecitree::state::FoxelState, BlockPos},
+  world::{
+    foxel::Foxel,
+    save::LoadError,
+    scenes::{SceneRegistry, SceneSpec},
+    World,
+  },
+};
+use ultraviolet::Vec4;
+
+fn sample_world() -> World {
+  let sun = Vec4::new(0.5, -0.5, 0.5, 0.5);
+  let mut world = SceneRegistry::builtin()
+    .build(&SceneSpec::new("fractal", 0), sun)
+    .unwrap();
+  let f = &mut world.foxels;
+  f.set_with_state(
+    BlockPos::new(50, 1, 2, 3),
+    Foxel::named("wood"),
+    FoxelState(3),
+  )
+  .unwrap();
+  f.set_state(BlockPos::new(0, 0, 0, 0), FoxelState(12))
+    .unwrap();
+  world
+}
+
+fn save(world: &World) -> Vec<u8> {
+  let mut bytes = Vec::new();
+  world.save(&mut bytes).unwrap();
+  bytes
+}
+
+#[test]
+fn round_trip() {
+  let world = sample_world();
+  let bytes = save(&world);
+  let loaded = World::load(bytes.as_slice()).unwrap();
+
+  assert_eq!(loaded.sun_dir, world.sun_dir);
+  assert_eq!(
+    loaded.foxels.composite_brick_count(),
+    world.foxels.composite_brick_count()
+  );
+  for x in -16..16 {
+    for v in -16..16 {
+      let pos = BlockPos::new(x, v, v.abs(), -v);
+      assert_eq!(
+        loaded.foxels.get_with_state(pos),
+        world.foxels.get_with_state(pos),
+        "{:?}",
+        pos
+      );
+    }
+  }
+  assert_eq!(
+    loaded.foxels.get_with_state(BlockPos::new(50, 1, 2, 3)),
+    Some((Foxel::named("wood"), FoxelState(3)))
+  );
+  assert_eq!(loaded.foxels.state_count(), 2);
+
+  // Saving it again gives the exact same thing
+  assert_eq!(save(&loaded), bytes);
+}
+
+#[test]
+fn bad_saves() {
+  let bytes = save(&sample_world());
+
+  assert!(matches!(
+    World::load(&b"nope"[..]),
+    Err(LoadError::BadMagic)
+  ));
+  assert!(matches!(
+    World::load(&bytes[..bytes.len() - 1]),
+    Err(LoadError::Corrupt(_))
+  ));
+
+  let mut future = bytes.clone();
+  future[4] = 99;
+  assert!(matches!(
+    World::load(future.as_slice()),
+    Err(LoadError::UnknownVersion(99))
+  ));
+}
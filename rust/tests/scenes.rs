@@ -0,0 +1,119 @@
+use tesseractory::{
+  math::{hexadecitree::Hexadecitree, BlockPos},
+  world::{
+    foxel::Foxel,
+    scenes::{Scene, SceneError, SceneRegistry, SceneSpec},
+  },
+};
+use ultraviolet::Vec4;
+
+fn build(spec: &str) -> Result<tesseractory::world::World, SceneError> {
+  SceneRegistry::builtin().build(&spec.parse()?, Vec4::unit_x())
+}
+
+#[test]
+fn every_builtin_builds() {
+  let reg = SceneRegistry::builtin();
+  for scene in reg.scenes() {
+    let world = build(scene.name).unwrap();
+    assert!(
+      world.foxels.composite_brick_count()
+        <= Hexadecitree::COMPOSITE_BRICK_COUNT as usize
+    );
+  }
+}
+
+#[test]
+fn axis_cross() {
+  let world = build("axis-cross").unwrap();
+  let f = &world.foxels;
+  assert_eq!(
+    f.get(BlockPos::new(0, 0, 0, 0)),
+    Some(Foxel::named("white"))
+  );
+  assert_eq!(f.get(BlockPos::new(9, 0, 0, 0)), Some(Foxel::named("red")));
+  assert_eq!(
+    f.get(BlockPos::new(0, 9, 0, 0)),
+    Some(Foxel::named("green"))
+  );
+  assert_eq!(f.get(BlockPos::new(0, 0, 9, 0)), Some(Foxel::named("blue")));
+  assert_eq!(f.get(BlockPos::new(0, 0, 0, 9)), Some(Foxel::named("rb")));
+  assert_eq!(f.get(BlockPos::new(10, 0, 0, 0)), Some(Foxel::AIR));
+}
+
+#[test]
+fn empty_is_empty() {
+  let world = build("empty").unwrap();
+  assert_eq!(world.foxels.composite_brick_count(), 0);
+}
+
+#[test]
+fn terrain_seeds() {
+  let a = build("terrain:5").unwrap();
+  let b = build("terrain:5").unwrap();
+  let c = build("terrain:6").unwrap();
+  let spots = (-40..40).flat_map(|x| {
+    (-32..32)
+      .step_by(5)
+      .map(move |v| BlockPos::new(x, v, -v, v / 2))
+  });
+  let a_spots = spots.clone().map(|p| a.foxels.get(p)).collect::<Vec<_>>();
+  let b_spots = spots.clone().map(|p| b.foxels.get(p)).collect::<Vec<_>>();
+  let c_spots = spots.map(|p| c.foxels.get(p)).collect::<Vec<_>>();
+  assert_eq!(a_spots, b_spots);
+  assert_ne!(a_spots, c_spots);
+}
+
+#[test]
+fn parse_specs() {
+  assert_eq!("fractal".parse(), Ok(SceneSpec::new("fractal", 0)));
+  assert_eq!("terrain:42".parse(), Ok(SceneSpec::new("terrain", 42)));
+  assert_eq!(
+    "terrain:lots".parse::<SceneSpec>(),
+    Err(SceneError::BadSeed("lots".to_owned()))
+  );
+  assert!(matches!(build("nowhere"), Err(SceneError::Unknown(_))));
+}
+
+#[test]
+fn config_precedence() {
+  let args = |a: &[&str]| a.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+  let env = || Some("empty".to_owned());
+
+  assert_eq!(
+    SceneSpec::from_config(args(&["--scene", "terrain:3"]), env()),
+    Ok(SceneSpec::new("terrain", 3))
+  );
+  assert_eq!(
+    SceneSpec::from_config(args(&["--scene=fractal"]), env()),
+    Ok(SceneSpec::new("fractal", 0))
+  );
+  assert_eq!(
+    SceneSpec::from_config(args(&["--verbose"]), env()),
+    Ok(SceneSpec::new("empty", 0))
+  );
+  assert_eq!(
+    SceneSpec::from_config(args(&[]), None),
+    Ok(SceneSpec::default())
+  );
+}
+
+#[test]
+fn custom_scenes() {
+  let mut reg = SceneRegistry::empty();
+  reg.register(Scene {
+    name: "one-foxel",
+    description: "",
+    build: |world, seed| {
+      let pos = BlockPos::new(seed as i32, 0, 0, 0);
+      world.foxels.set(pos, Foxel::named("stone")).map(|_| ())
+    },
+  });
+  let world = reg
+    .build(&SceneSpec::new("one-foxel", 7), Vec4::unit_x())
+    .unwrap();
+  assert_eq!(
+    world.foxels.get(BlockPos::new(7, 0, 0, 0)),
+    Some(Foxel::named("stone"))
+  );
+}
@@ -1,8 +1,19 @@
+mod common;
+
+use common::{random_point, scattered_tree};
 use itertools::Itertools;
-use tesseractory::math::{
-  basis4,
-  hexadecitree::iter::{IterItem, TreeIter},
-  BlockPos,
+use tesseractory::{
+  math::{
+    basis4,
+    hexadecitree::{
+      iter::{BrickSkipIter, IterItem, TreeIter},
+      reprs::BrickRef,
+      Hexadecitree,
+    },
+    rng::SplitMix64,
+    BlockPos,
+  },
+  world::foxel::Foxel,
 };
 use ultraviolet::{IVec4, Vec4};
 
@@ -124,3 +135,67 @@ fn losing_my_mind_over_here() {
   assert_eq!(over_line, hope_over);
   assert_eq!(under_line, hope_under);
 }
+
+fn in_composite_brick(tree: &Hexadecitree, pos: BlockPos) -> bool {
+  matches!(tree.brick_at(pos), Some(BrickRef::Ref(_)))
+}
+
+/// Skipping mustn't miss anything in bricks with something in them.
+#[test]
+fn skipping_sees_the_same_foxels() {
+  let tree = scattered_tree(5, 400, 40, &[Foxel::named("stone")]);
+  let mut rng = SplitMix64::new(6);
+  let mut compared = 0;
+  for _ in 0..100 {
+    let start = random_point(&mut rng, 40.0);
+    let dir = random_point(&mut rng, 1.0).normalized();
+
+    let mut skipping = BrickSkipIter::new(&tree, start, dir);
+    let mut skipped = Vec::new();
+    while let Some(item) = skipping.next() {
+      if skipping.entry_time() > 60.0 {
+        break;
+      }
+      if in_composite_brick(&tree, item.pos) {
+        skipped.push(item);
+      }
+    }
+
+    let mut stepping = TreeIter::new(start, dir);
+    let mut stepped = Vec::new();
+    while let Some(item) = stepping.next() {
+      if stepping.entry_time() > 60.0 {
+        break;
+      }
+      if in_composite_brick(&tree, item.pos) {
+        stepped.push(item);
+      }
+    }
+    assert_eq!(skipped, stepped, "from {:?} along {:?}", start, dir);
+    compared += skipped.len();
+  }
+  assert!(compared > 100);
+}
+
+#[test]
+fn skipping_takes_big_steps() {
+  let tree = Hexadecitree::new();
+  let start = Vec4::new(-100.5, 0.3, 0.2, 0.1);
+  let dir = Vec4::new(1.0, 0.1, 0.2, 0.3).normalized();
+  let mut iter = BrickSkipIter::new(&tree, start, dir);
+  let steps = iter.by_ref().take_while(|it| it.pos.x < 100).count();
+  assert!(steps < 100, "took {} steps", steps);
+  assert!(iter.entry_time() > 200.0);
+
+  let steps = TreeIter::new(start, dir)
+    .take_while(|it| it.pos.x < 100)
+    .count();
+  assert!(steps > 200);
+
+  // Straight along an axis, like a camera that hasn't turned
+  for dir in [Vec4::unit_x(), Vec4::new(1.0, 1.0, 0.0, 0.0).normalized()] {
+    let mut iter = BrickSkipIter::new(&tree, start, dir);
+    let steps = iter.by_ref().take_while(|it| it.pos.x < 100).count();
+    assert!(steps < 100, "took {} steps along {:?}", steps, dir);
+  }
+}
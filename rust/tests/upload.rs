@@ -0,0 +1,234 @@
+mod common;
+
+use common::random_pos;
+use itertools::iproduct;
+use tesseractory::{
+  math::{
+    geo::Rotor4,
+    hexadecitree::{
+      reprs::{Brick, BrickPtr, BrickPtrRepr, BrickRef},
+      upload::{UploadPatch, UploadState},
+      Hexadecitree,
+    },
+    rng::SplitMix64,
+    BlockPos,
+  },
+  render::{view::ViewVolume, Camera},
+  world::{ao::AoCache, foxel::Foxel},
+};
+use ultraviolet::{IVec4, Vec4};
+
+const BRICK_BYTES: usize = std::mem::size_of::<Brick>();
+const AO_BYTES: usize = Hexadecitree::GPU_AO_BYTES
+  / Hexadecitree::GPU_COMPOSITE_BRICKS_COUNT as usize;
+
+const ASPECT: f32 = 1000.0 / 600.0;
+
+/// Far enough back to see all of `spread_out`.
+fn from_behind() -> ViewVolume {
+  let pos = Vec4::new(0.5, -100.0, 0.5, 0.5);
+  Camera::new(pos, Rotor4::identity(), 2.0, 0.1).view_volume(ASPECT)
+}
+
+/// From the middle, looking along +Y, +Z or +W.
+fn turned(turn: usize) -> ViewVolume {
+  let rot = match turn % 3 {
+    0 => Rotor4::identity(),
+    1 => Rotor4::from_rotation_between(Vec4::unit_y(), Vec4::unit_z()),
+    _ => Rotor4::from_rotation_between(Vec4::unit_y(), Vec4::unit_w()),
+  };
+  Camera::new(Vec4::broadcast(0.5), rot, 1.5, 0.1).view_volume(ASPECT)
+}
+
+/// A few composite bricks, none of them next to each other.
+fn spread_out() -> Hexadecitree {
+  let mut h = Hexadecitree::new();
+  for (x, y) in iproduct!(-2..2, -2..2) {
+    let pos = BlockPos::new(x * 24 + 3, y * 24 + 3, 3, 3);
+    h.set(pos, Foxel::named("stone")).unwrap();
+  }
+  h
+}
+
+struct Gpu {
+  bytes: Vec<u8>,
+  state: UploadState,
+  ao: AoCache,
+}
+
+impl Gpu {
+  fn new() -> Self {
+    Self {
+      bytes: vec![0; Hexadecitree::GPU_TRANSFER_IMAGE_SIZE_SQ * 4],
+      state: UploadState::new(),
+      ao: AoCache::new(),
+    }
+  }
+
+  fn upload(&mut self, h: &mut Hexadecitree, view: &ViewVolume) -> UploadPatch {
+    h.upload(&mut self.bytes, view, &mut self.ao, &mut self.state)
+  }
+
+  /// What the shader would find at `pos`.
+  fn get(&self, pos: BlockPos) -> Foxel {
+    let fab = Hexadecitree::FOXELS_ACROSS_BRICK as i32;
+    let baw = Hexadecitree::BRICKS_ACROSS_WORLD as usize;
+    let grid_idx = pos.0.as_array().iter().fold(0, |idx, v| {
+      idx * baw + (v.div_euclid(fab) + baw as i32 / 2) as usize
+    });
+    let ptr = BrickPtrRepr(u16::from_ne_bytes([
+      self.bytes[grid_idx * 2],
+      self.bytes[grid_idx * 2 + 1],
+    ]));
+    match ptr.decode() {
+      BrickPtr::Solid(f) => f,
+      BrickPtr::Pointer(slot) => {
+        let offset = pos.0.map(|v| v.rem_euclid(fab));
+        let start = Hexadecitree::GPU_BRICK_PTRS_BYTES + slot * BRICK_BYTES;
+        Foxel::from_id(self.bytes[start + Brick::offset_to_idx(offset)])
+      }
+    }
+  }
+
+  fn ao_bytes(&self, corner: BlockPos) -> &[u8] {
+    let slots = self.state.slots();
+    let slot = slots.iter().position(|s| *s == Some(corner)).unwrap();
+    let start = Hexadecitree::GPU_AO_OFFSET + slot * AO_BYTES;
+    &self.bytes[start..start + AO_BYTES]
+  }
+}
+
+#[test]
+fn first_upload_writes_everything() {
+  let mut h = spread_out();
+  let mut gpu = Gpu::new();
+  let view = from_behind();
+  let patch = gpu.upload(&mut h, &view);
+  assert_eq!(patch.ranges()[0].start, 0);
+  assert!(patch.len() >= Hexadecitree::GPU_BRICK_PTRS_BYTES);
+  assert_eq!(gpu.state.slots().iter().flatten().count(), 16);
+  for (x, y) in iproduct!(-2..2, -2..2) {
+    let pos = BlockPos::new(x * 24 + 3, y * 24 + 3, 3, 3);
+    assert_eq!(gpu.get(pos), Foxel::named("stone"));
+    assert_eq!(gpu.get(BlockPos(pos.0 + IVec4::unit_w())), Foxel::AIR);
+  }
+
+  // Nothing's changed since
+  assert!(gpu.upload(&mut h, &view).is_empty());
+  // A new upload state starts over
+  let mut other = Gpu::new();
+  assert_eq!(other.upload(&mut h, &view).ranges()[0].start, 0);
+}
+
+#[test]
+fn small_edits_make_small_uploads() {
+  let mut h = spread_out();
+  let mut gpu = Gpu::new();
+  let view = from_behind();
+  gpu.upload(&mut h, &view);
+
+  // Setting something to what it already is doesn't count
+  h.set(BlockPos::new(3, 3, 3, 3), Foxel::named("stone"))
+    .unwrap();
+  assert!(gpu.upload(&mut h, &view).is_empty());
+
+  // In a brick that's already got a slot: just that slot and its AO
+  let pos = BlockPos::new(4, 3, 3, 3);
+  h.set(pos, Foxel::named("red")).unwrap();
+  gpu.ao.foxel_changed(pos);
+  let patch = gpu.upload(&mut h, &view);
+  assert_eq!(patch.len(), BRICK_BYTES + AO_BYTES);
+  assert_eq!(gpu.get(pos), Foxel::named("red"));
+  let row = Hexadecitree::GPU_TRANSFER_ROW_BYTES;
+  let rows = patch.rows().iter().map(|r| r.len()).sum::<usize>();
+  assert!(rows * row <= patch.len() + 4 * row, "{} rows", rows);
+
+  // Making a brick composite somewhere that can't be seen doesn't change
+  // anything, it's still drawn as air
+  let pos = BlockPos::new(100, 100, 100, 100);
+  h.set(pos, Foxel::named("sand")).unwrap();
+  let patch = gpu.upload(&mut h, &view);
+  assert_eq!(patch.len(), 0, "{:?}", patch);
+
+  // Filling a whole brick in is only the one pointer
+  h.fill_brick(BlockPos::new(-100, 0, 0, 0), Foxel::named("stone"))
+    .unwrap();
+  let patch = gpu.upload(&mut h, &view);
+  assert_eq!(patch.len(), 2);
+  assert_eq!(gpu.get(BlockPos::new(-99, 1, 1, 1)), Foxel::named("stone"));
+}
+
+/// Everything that got uploaded is the same as what's in the tree.
+fn check_against(gpu: &Gpu, h: &Hexadecitree, around: &[BlockPos]) {
+  let slots = gpu.state.slots();
+  for corner in slots.iter().flatten() {
+    for idx in (0..Hexadecitree::FOXELS_PER_BRICK as usize).step_by(7) {
+      let pos = BlockPos(corner.0 + Brick::idx_to_offset(idx));
+      assert_eq!(gpu.get(pos), h.get(pos).unwrap(), "at {:?}", pos);
+    }
+  }
+  for pos in around {
+    let corner = Hexadecitree::brick_corner(*pos);
+    let expected = if slots.contains(&Some(corner)) {
+      h.get(*pos).unwrap()
+    } else {
+      match h.brick_at(*pos).unwrap() {
+        BrickRef::Solid(f) => f,
+        // Composite bricks without a slot aren't drawn
+        BrickRef::Ref(_) => Foxel::AIR,
+      }
+    };
+    assert_eq!(gpu.get(*pos), expected, "at {:?}", pos);
+  }
+}
+
+#[test]
+fn incremental_matches_full() {
+  let mut h = Hexadecitree::new();
+  let mut rng = SplitMix64::new(49);
+  let foxels = ["stone", "red", "sand", "glass"].map(Foxel::named);
+  let mut touched = Vec::new();
+  let mut gpu = Gpu::new();
+  let mut seen = 0;
+
+  for round in 0..12 {
+    for _ in 0..20 {
+      let pos = random_pos(&mut rng, -20, 20);
+      let foxel = foxels[rng.range_i32(0, 4) as usize];
+      h.set(pos, foxel).unwrap();
+      gpu.ao.foxel_changed(pos);
+      touched.push(pos);
+    }
+    if round % 4 == 3 {
+      let corner = random_pos(&mut rng, -20, 20);
+      h.fill_brick(corner, Foxel::named("stone")).unwrap();
+      gpu.ao.box_changed(
+        Hexadecitree::brick_corner(corner),
+        BlockPos(Hexadecitree::brick_corner(corner).0 + IVec4::broadcast(7)),
+      );
+      touched.push(corner);
+    }
+    // Only sees some of them, and turns every few rounds
+    let view = turned(round / 3);
+    gpu.upload(&mut h, &view);
+    check_against(&gpu, &h, &touched);
+
+    let mut full = Gpu::new();
+    full.upload(&mut h, &view);
+    let mut slots = gpu.state.slots().to_vec();
+    let mut full_slots = full.state.slots().to_vec();
+    slots.sort_by_key(|s| s.map(|c| c.0.as_array().to_owned()));
+    full_slots.sort_by_key(|s| s.map(|c| c.0.as_array().to_owned()));
+    assert_eq!(slots, full_slots, "round {}", round);
+    seen += slots.iter().flatten().count();
+    for corner in slots.iter().flatten() {
+      assert!(
+        gpu.ao_bytes(*corner) == full.ao_bytes(*corner),
+        "AO for {:?} in round {}",
+        corner,
+        round
+      );
+    }
+  }
+  assert!(seen > 12, "{}", seen);
+}
@@ -0,0 +1,169 @@
+mod common;
+
+use common::{random_point, random_pos};
+use itertools::iproduct;
+use tesseractory::{
+  math::{
+    geo::Rotor4,
+    hexadecitree::{reprs::BrickRef, Hexadecitree},
+    rng::SplitMix64,
+    BlockPos,
+  },
+  render::{Camera, Renderer},
+  world::foxel::Foxel,
+};
+use ultraviolet::{Vec2, Vec4};
+
+/// Somewhere near the middle, facing any which way.
+fn random_camera(rng: &mut SplitMix64) -> (Camera, f32) {
+  let (a, b) = (random_point(rng, 1.0), random_point(rng, 1.0));
+  let rot = Rotor4::from_rotation_between(a.normalized(), b.normalized());
+  let pos = random_point(rng, 10.0);
+  let fov = 0.5 + rng.next_f32() * 1.5;
+  let aspect = 0.5 + rng.next_f32() * 1.5;
+  (Camera::new(pos, rot, fov, 0.1 + rng.next_f32()), aspect)
+}
+
+#[test]
+fn rays_stay_inside() {
+  let mut rng = SplitMix64::new(50);
+  for _ in 0..20 {
+    let (camera, aspect) = random_camera(&mut rng);
+    let view = camera.view_volume(aspect);
+    for _ in 0..50 {
+      let uv = Vec2::new(rng.next_f32(), rng.next_f32());
+      let (start, dir) = camera.ray(uv, aspect);
+      for t in [0.0, 0.5, 3.0, 40.0, 200.0] {
+        let point = start + dir * t;
+        assert!(view.contains(point), "{:?} at {:?}", camera, point);
+        assert!(view.intersects_box(point, point));
+      }
+    }
+    // The corners of the screen too
+    for (u, v) in iproduct!([0.0, 1.0], [0.0, 1.0]) {
+      let (start, dir) = camera.ray(Vec2::new(u, v), aspect);
+      assert!(view.contains(start + dir * 10.0));
+    }
+  }
+}
+
+#[test]
+fn off_screen_is_outside() {
+  let mut rng = SplitMix64::new(51);
+  for _ in 0..20 {
+    let (camera, aspect) = random_camera(&mut rng);
+    let view = camera.view_volume(aspect);
+    for uv in [(-0.1, 0.5), (1.1, 0.5), (0.5, -0.1), (0.5, 1.1)] {
+      let (start, dir) = camera.ray(uv.into(), aspect);
+      let point = start + dir * 10.0;
+      assert!(!view.contains(point), "{:?} at {:?}", camera, uv);
+      assert!(!view.intersects_box(point, point));
+    }
+
+    let (start, dir) = camera.ray(Vec2::broadcast(0.5), aspect);
+    // Off the hyperplane the screen's in
+    let ana = camera.rot * Vec4::unit_w();
+    assert!(!view.contains(start + dir * 10.0 + ana * 0.1));
+    assert!(!view.contains(start + dir * 10.0 - ana * 0.1));
+    // Behind, and closer than the rays start
+    assert!(!view.contains(camera.pos - dir * 10.0));
+    assert!(!view.contains(camera.pos + dir * camera.focal_dist * 0.3));
+  }
+}
+
+#[test]
+fn boxes() {
+  let camera = Camera::new(Vec4::zero(), Rotor4::identity(), 1.0, 0.1);
+  let view = camera.view_volume(2.0);
+  let fits = |min: Vec4, max: Vec4| view.intersects_box(min, max);
+
+  // Around the camera, and right in front
+  assert!(fits(Vec4::broadcast(-1.0), Vec4::broadcast(1.0)));
+  assert!(fits(Vec4::new(-1.0, 5.0, -1.0, -1.0), Vec4::broadcast(6.0)));
+  // Only just poking into the screen at the top
+  assert!(fits(
+    Vec4::new(4.9, 10.0, 0.0, -1.0),
+    Vec4::new(8.0, 11.0, 1.0, 1.0)
+  ));
+  assert!(!fits(
+    Vec4::new(5.1, 10.0, 0.0, -1.0),
+    Vec4::new(8.0, 10.0, 1.0, 1.0)
+  ));
+  // It's twice as wide as it is tall
+  assert!(fits(
+    Vec4::new(0.0, 10.0, 9.9, -1.0),
+    Vec4::new(1.0, 10.0, 12.0, 1.0)
+  ));
+  // Behind
+  assert!(!fits(
+    Vec4::new(-1.0, -8.0, -1.0, -1.0),
+    Vec4::new(1.0, -0.5, 1.0, 1.0)
+  ));
+  // Right in front, but not in the hyperplane
+  assert!(!fits(
+    Vec4::new(-1.0, 5.0, -1.0, 0.5),
+    Vec4::new(1.0, 6.0, 1.0, 2.0)
+  ));
+  assert!(!fits(
+    Vec4::new(-1.0, 5.0, -1.0, -2.0),
+    Vec4::new(1.0, 6.0, 1.0, -0.5)
+  ));
+
+  // Bricks go from their corner to the next one
+  assert!(view.intersects_brick(BlockPos::new(0, 8, 0, -8)));
+  assert!(!view.intersects_brick(BlockPos::new(0, 8, 0, 8)));
+  assert!(!view.intersects_brick(BlockPos::new(0, -16, 0, 0)));
+}
+
+#[test]
+fn drawn_bricks_are_never_culled() {
+  let mut h = Hexadecitree::new();
+  let mut rng = SplitMix64::new(52);
+  // Whole bricks to be sure of hitting something, and bits and pieces to
+  // make some composite ones
+  for _ in 0..150 {
+    h.fill_brick(random_pos(&mut rng, -40, 40), Foxel::named("stone"))
+      .unwrap();
+  }
+  for _ in 0..3000 {
+    h.set(random_pos(&mut rng, -40, 40), Foxel::named("red"))
+      .unwrap();
+  }
+  let composites = h
+    .bricks()
+    .filter(|(_, brick)| matches!(brick, BrickRef::Ref(_)))
+    .map(|(corner, _)| corner)
+    .collect::<Vec<_>>();
+
+  let renderer = Renderer::new(24, 16);
+  let (mut hits, mut culled) = (0, 0);
+  for _ in 0..10 {
+    let (camera, _) = random_camera(&mut rng);
+    let view = renderer.view_volume(&camera);
+    for (x, y) in iproduct!(0..renderer.width, 0..renderer.height) {
+      let (start, dir) = camera.ray(renderer.uv(x, y), renderer.aspect());
+      if let Some(hit) = h.raycast(start, dir, 200.0) {
+        let corner = Hexadecitree::brick_corner(hit.pos);
+        assert!(
+          view.intersects_brick(corner),
+          "{:?} at {:?}",
+          camera,
+          hit.pos
+        );
+        hits += 1;
+      }
+    }
+    culled += composites
+      .iter()
+      .filter(|c| !view.intersects_brick(**c))
+      .count();
+  }
+  assert!(hits > 100, "{}", hits);
+  // Most of them shouldn't be seen from any one place
+  assert!(
+    culled > composites.len() * 10 / 2,
+    "{} of {}",
+    culled,
+    composites.len() * 10
+  );
+}
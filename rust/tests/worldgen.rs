@@ -0,0 +1,371 @@
+use itertools::iproduct;
+use tesseractory::{
+  math::{
+    hexadecitree::{reprs::BrickRef, Hexadecitree},
+    rng::SplitMix64,
+    BlockPos,
+  },
+  world::{
+    foxel::Foxel,
+    gen::{
+      CaveGen, CaveParams, DecorationParams, Decorator, Schematic,
+      SchematicError, TerrainGen, TerrainParams,
+    },
+  },
+};
+use ultraviolet::IVec4;
+
+fn small_params(seed: u64) -> TerrainParams {
+  TerrainParams {
+    seed,
+    min: BlockPos::new(-32, -8, -8, -8),
+    max: BlockPos::new(31, 7, 7, 7),
+    ..Default::default()
+  }
+}
+
+fn generate(params: TerrainParams) -> Hexadecitree {
+  let mut tree = Hexadecitree::new();
+  TerrainGen::new(params).generate(&mut tree).unwrap();
+  tree
+}
+
+fn region(params: &TerrainParams) -> impl Iterator<Item = BlockPos> + '_ {
+  let (min, max) = (params.min, params.max);
+  iproduct!(min.x..=max.x, min.y..=max.y, min.z..=max.z, min.w..=max.w)
+    .map(|(x, y, z, w)| BlockPos::new(x, y, z, w))
+}
+
+#[test]
+fn same_seed_same_world() {
+  let params = small_params(1234);
+  let a = generate(params.clone());
+  let b = generate(params.clone());
+
+  for pos in region(&params) {
+    assert_eq!(a.get(pos), b.get(pos), "{:?}", pos);
+  }
+}
+
+#[test]
+fn different_seed_different_world() {
+  let params = small_params(1);
+  let a = generate(params.clone());
+  let b = generate(small_params(2));
+
+  assert!(region(&params).any(|pos| a.get(pos) != b.get(pos)));
+}
+
+#[test]
+fn layers() {
+  let params = small_params(99);
+  let gen = TerrainGen::new(params.clone());
+  let tree = generate(params.clone());
+
+  for (y, z, w) in iproduct!(-8..8, -8..8, -8..8) {
+    let h = gen.height_at(y, z, w);
+    assert_eq!(
+      tree.get(BlockPos::new(h, y, z, w)),
+      Some(Foxel::named("grass"))
+    );
+    assert_eq!(tree.get(BlockPos::new(h + 1, y, z, w)), Some(Foxel::AIR));
+    assert_eq!(
+      tree.get(BlockPos::new(h - 1, y, z, w)),
+      Some(Foxel::named("dirt"))
+    );
+    let deep = h - params.dirt_depth - 1;
+    assert_eq!(
+      tree.get(BlockPos::new(deep, y, z, w)),
+      Some(Foxel::named("stone"))
+    );
+  }
+}
+
+#[test]
+fn sky_and_bedrock_stay_solid() {
+  let params = small_params(5);
+  let tree = generate(params.clone());
+
+  let sky = tree.brick_at(BlockPos::new(params.max.x, 0, 0, 0)).unwrap();
+  assert!(matches!(sky, BrickRef::Solid(Foxel::AIR)));
+  let underground =
+    tree.brick_at(BlockPos::new(params.min.x, 0, 0, 0)).unwrap();
+  assert!(
+    matches!(underground, BrickRef::Solid(f) if f == Foxel::named("stone"))
+  );
+
+  // 2x2x2 columns, and the surface can't possibly be more than a few
+  // bricks tall with the default amplitude
+  assert!(tree.composite_brick_count() <= 8 * 4);
+}
+
+fn small_caves(seed: u64) -> CaveParams {
+  CaveParams {
+    seed,
+    depth: -24..=-4,
+    min: BlockPos::new(-32, -8, -8, -8),
+    max: BlockPos::new(31, 7, 7, 7),
+    worm_count: 3,
+    worm_length: 40,
+    ..Default::default()
+  }
+}
+
+fn solid_ground(params: &CaveParams) -> Hexadecitree {
+  let mut tree = Hexadecitree::new();
+  for x in (-32..0).step_by(8) {
+    for (y, z, w) in iproduct!([-8, 0], [-8, 0], [-8, 0]) {
+      tree
+        .fill_brick(BlockPos::new(x, y, z, w), Foxel::named("stone"))
+        .unwrap();
+    }
+  }
+  CaveGen::new(params.clone()).carve(&mut tree).unwrap();
+  tree
+}
+
+fn cave_region(params: &CaveParams) -> impl Iterator<Item = BlockPos> + '_ {
+  let (min, max) = (params.min, params.max);
+  iproduct!(min.x..0, min.y..=max.y, min.z..=max.z, min.w..=max.w)
+    .map(|(x, y, z, w)| BlockPos::new(x, y, z, w))
+}
+
+#[test]
+fn caves_deterministic() {
+  let params = small_caves(77);
+  let a = solid_ground(&params);
+  let b = solid_ground(&params);
+  for pos in cave_region(&params) {
+    assert_eq!(a.get(pos), b.get(pos), "{:?}", pos);
+  }
+}
+
+#[test]
+fn caves_stay_in_depth() {
+  let params = small_caves(3);
+  let gen = CaveGen::new(params.clone());
+  let tree = solid_ground(&params);
+
+  let mut carved = 0;
+  for pos in cave_region(&params) {
+    let foxel = tree.get(pos).unwrap();
+    if params.depth.contains(&pos.x) {
+      if foxel == Foxel::AIR {
+        carved += 1;
+      }
+      if gen.is_cavern(pos) {
+        assert_eq!(foxel, Foxel::AIR, "{:?} should be a cavern", pos);
+      }
+    } else {
+      assert_eq!(
+        foxel,
+        Foxel::named("stone"),
+        "{:?} is outside the depth",
+        pos
+      );
+    }
+  }
+  assert!(carved > 0);
+}
+
+#[test]
+fn worms_tunnel_through_w() {
+  let gen = CaveGen::new(small_caves(12));
+  for idx in 0..3 {
+    let path = gen.worm_path(idx);
+    assert_eq!(path.len(), 40);
+
+    let ws = path.iter().map(|(p, _)| p.w);
+    let spread =
+      ws.clone().fold(f32::MIN, f32::max) - ws.fold(f32::MAX, f32::min);
+    assert!(spread > 2.0, "worm #{} barely moved in W: {}", idx, spread);
+
+    for window in path.windows(2) {
+      let [(a, _), (b, _)] = window else {
+        unreachable!()
+      };
+      assert!((*a - *b).mag() <= 2.0, "worm #{} jumped", idx);
+    }
+  }
+}
+
+fn small_decoration(seed: u64) -> DecorationParams {
+  DecorationParams {
+    seed,
+    min: BlockPos::new(-32, -32, -32, -32),
+    max: BlockPos::new(31, 31, 31, 31),
+    ..Default::default()
+  }
+}
+
+#[test]
+fn decoration_is_order_independent() {
+  let terrain = TerrainGen::new(small_params(8));
+  let a = Decorator::new(small_decoration(4), terrain.clone());
+  let b = Decorator::new(small_decoration(4), terrain);
+
+  let cells = a.cells().collect::<Vec<_>>();
+  let forwards = cells
+    .iter()
+    .map(|c| a.placements_in_cell(*c))
+    .collect::<Vec<_>>();
+  let mut backwards = cells
+    .iter()
+    .rev()
+    .map(|c| b.placements_in_cell(*c))
+    .collect::<Vec<_>>();
+  backwards.reverse();
+  assert_eq!(forwards, backwards);
+  assert!(forwards.iter().any(|ps| !ps.is_empty()));
+}
+
+#[test]
+fn placements_dont_overlap() {
+  let terrain = TerrainGen::new(small_params(21));
+  let mut params = small_decoration(6);
+  // Cram things in so there's plenty to throw out
+  params.attempts_per_cell = 40;
+  let gen = Decorator::new(params, terrain.clone());
+
+  let all = gen
+    .cells()
+    .flat_map(|c| gen.placements_in_cell(c))
+    .collect::<Vec<_>>();
+  assert!(all.len() > 1);
+  for (i, a) in all.iter().enumerate() {
+    for b in &all[i + 1..] {
+      assert!(
+        !a.overlaps(b, 0),
+        "{:?} and {:?} overlap",
+        a.origin,
+        b.origin
+      );
+    }
+
+    // Sitting right on the ground
+    let ground = a.origin.0 + a.schematic.anchor();
+    assert_eq!(
+      terrain.height_at(ground.y, ground.z, ground.w) + 1,
+      ground.x
+    );
+  }
+}
+
+/// Anchored at one end or the other, so they reach a long way from their
+/// anchors in the direction they stick out.
+#[test]
+fn lopsided_schematics_dont_overlap() {
+  let terrain = TerrainGen::new(small_params(21));
+  let pole = |anchor: i32| {
+    let mut schem =
+      Schematic::new(IVec4::new(1, 20, 1, 1), IVec4::new(0, anchor, 0, 0));
+    for y in 0..20 {
+      schem.set(IVec4::new(0, y, 0, 0), Foxel::named("wood"));
+    }
+    (schem, 1)
+  };
+  let mut placed = 0;
+  for seed in 0..20 {
+    let params = DecorationParams {
+      // Thin in W, to keep the number of cells down
+      min: BlockPos::new(-32, -32, -32, -8),
+      max: BlockPos::new(31, 31, 31, 7),
+      cell_size: 8,
+      attempts_per_cell: 4,
+      tree_weight: 0,
+      rock_weight: 0,
+      schematics: vec![pole(0), pole(19)],
+      ..small_decoration(seed)
+    };
+    let gen = Decorator::new(params, terrain.clone());
+    let all = gen
+      .cells()
+      .flat_map(|c| gen.placements_in_cell(c))
+      .collect::<Vec<_>>();
+    for (i, a) in all.iter().enumerate() {
+      for b in &all[i + 1..] {
+        assert!(
+          !a.overlaps(b, 0),
+          "seed {}: {:?} and {:?} overlap",
+          seed,
+          a.bounds(),
+          b.bounds()
+        );
+      }
+    }
+    placed += all.len();
+  }
+  assert!(placed > 20);
+}
+
+#[test]
+fn decorations_land_on_terrain() {
+  let mut terrain = small_params(13);
+  terrain.min = BlockPos::new(-32, -32, -32, -32);
+  terrain.max = BlockPos::new(31, 31, 31, 31);
+  let mut tree = generate(terrain.clone());
+  let before = generate(terrain.clone());
+
+  let gen = Decorator::new(small_decoration(2), TerrainGen::new(terrain));
+  gen.decorate(&mut tree).unwrap();
+
+  let mut changed = 0;
+  for pos in iproduct!(-32..32, -32..32, -32..32, -32..32)
+    .map(|(x, y, z, w)| BlockPos::new(x, y, z, w))
+  {
+    let (old, new) = (before.get(pos).unwrap(), tree.get(pos).unwrap());
+    if old != new {
+      // Only ever fills in air
+      assert_eq!(old, Foxel::AIR, "{:?} got overwritten", pos);
+      assert!(["wood", "leaves", "stone"].map(Foxel::named).contains(&new));
+      changed += 1;
+    }
+  }
+  assert!(changed > 0);
+}
+
+#[test]
+fn schematic_round_trip() {
+  let mut rng = SplitMix64::new(5);
+  for schem in [Schematic::tree(&mut rng), Schematic::rock(&mut rng)] {
+    let bytes = schem.to_bytes();
+    assert_eq!(Schematic::from_bytes(&bytes), Ok(schem));
+    assert_eq!(
+      Schematic::from_bytes(&bytes[..bytes.len() - 1]),
+      Err(SchematicError::Truncated)
+    );
+  }
+  assert_eq!(
+    Schematic::from_bytes(b"nope, not a schematic"),
+    Err(SchematicError::BadMagic)
+  );
+}
+
+/// Just a header, with these sizes and the anchor in the corner.
+fn schematic_header(size: [u16; 4]) -> Vec<u8> {
+  let mut bytes = b"FXSC\x01".to_vec();
+  for v in size.into_iter().chain([0; 4]) {
+    bytes.extend_from_slice(&v.to_le_bytes());
+  }
+  bytes
+}
+
+#[test]
+fn schematic_sizes_get_checked() {
+  // Would be far too big to allocate
+  let huge = schematic_header([u16::MAX; 4]);
+  assert_eq!(Schematic::from_bytes(&huge), Err(SchematicError::BadSize));
+  let big = schematic_header([4000, 4000, 100, 1]);
+  assert_eq!(Schematic::from_bytes(&big), Err(SchematicError::Truncated));
+  let flat = schematic_header([3, 0, 3, 3]);
+  assert_eq!(Schematic::from_bytes(&flat), Err(SchematicError::BadSize));
+
+  let mut tiny = schematic_header([1, 2, 1, 1]);
+  tiny.extend([0, 0]);
+  assert!(Schematic::from_bytes(&tiny).is_ok());
+  tiny.push(0);
+  assert_eq!(
+    Schematic::from_bytes(&tiny),
+    Err(SchematicError::TrailingBytes)
+  );
+}